@@ -0,0 +1,448 @@
+//! One-click authentication with `wc_sessionAuthenticate`, described [here](https://specs.walletconnect.com/2.0/specs/clients/sign/session-authenticate).
+//! The dapp sends a CAIP-122 request on a new pairing and the wallet answers with a CACAO per
+//! account on the response topic, `sha256(requester public key)`, which is verified before the
+//! request resolves. If the CACAOs grant any of the methods the request asked for in its ReCap, a
+//! session with those methods is established in the same round-trip. The pairing and response
+//! topics are unsubscribed, and their keys and expiry forgotten, once the request resolves.
+
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::Utc;
+use rand::{rngs::OsRng, RngCore};
+use tokio::sync::oneshot;
+
+use crate::{
+    crypto::{hash_sha256, Crypto},
+    error::AuthenticateError,
+    expirations::ExpiryManager,
+    pairing::PairingUri,
+    peer::PeerRpc,
+    provider::{ACCOUNTS_CHANGED, CHAIN_CHANGED},
+    relayer::Relayer,
+    rpc::{
+        auth::cacao::CacaoVerifier,
+        types::{
+            AccountId, AuthPayload, Cacao, Metadata, Namespace, Participant, Relay,
+            SessionAuthenticateParams, SessionAuthenticateResponse, WC_SESSION_AUTHENTICATE,
+        },
+    },
+    session::{Session, Sessions, SESSION_TTL},
+    supervisor::Supervisor,
+    time,
+    types::Topic,
+    WalletConnect,
+};
+
+pub type Result<T> = std::result::Result<T, AuthenticateError>;
+
+/// How long the wallet has to respond to an authentication request
+pub const AUTHENTICATE_TTL: std::time::Duration = time::HOUR;
+
+/// A successful authentication
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+    pub cacaos: Vec<Cacao>,
+    pub responder: Participant,
+    /// The session established along with the authentication, if any methods were requested
+    pub session: Option<Session>,
+}
+
+/// An authentication request waiting for the wallet to respond
+pub struct PendingAuthentication {
+    response: oneshot::Receiver<Result<Authenticated>>,
+}
+
+impl PendingAuthentication {
+    /// Wait for the wallet to respond
+    pub async fn response(self) -> Result<Authenticated> {
+        self.response.await.map_err(|_| AuthenticateError::Cancelled)?
+    }
+}
+
+#[derive(Clone)]
+pub struct Authenticate {
+    crypto: Arc<Crypto>,
+    peer: PeerRpc,
+    relayer: Arc<Relayer>,
+    tasks: Supervisor,
+    expirer: Arc<ExpiryManager>,
+    sessions: Arc<Sessions>,
+    verifier: CacaoVerifier,
+    metadata: Metadata,
+}
+
+impl Authenticate {
+    pub fn new(context: &WalletConnect, metadata: Metadata) -> Result<Self> {
+        Self::with_parts(
+            context,
+            Arc::new(Crypto::new(context)?),
+            PeerRpc::new(context)?,
+            context.relayer().clone(),
+            Arc::new(ExpiryManager::new(context)?),
+            metadata,
+        )
+    }
+
+    /// Create an authenticator sharing its crypto, peer, relayer and expirer with other components
+    pub(crate) fn with_parts(
+        context: &WalletConnect,
+        crypto: Arc<Crypto>,
+        peer: PeerRpc,
+        relayer: Arc<Relayer>,
+        expirer: Arc<ExpiryManager>,
+        metadata: Metadata,
+    ) -> Result<Self> {
+        Ok(Self {
+            crypto,
+            peer,
+            relayer,
+            tasks: context.tasks().clone(),
+            expirer,
+            sessions: Arc::new(Sessions::new(context)?),
+            verifier: CacaoVerifier::default(),
+            metadata,
+        })
+    }
+
+    /// Verify CACAOs with `verifier`, i.e to support contract wallets through EIP-1271
+    pub fn verifier(mut self, verifier: CacaoVerifier) -> Self {
+        self.verifier = verifier;
+        self
+    }
+
+    /// Request authentication with `payload`.
+    /// Returns the pairing URI to show the wallet, and a handle to wait for its response.
+    pub async fn authenticate(
+        &self,
+        payload: AuthPayload,
+    ) -> Result<(PairingUri<'static>, PendingAuthentication)> {
+        let sym_key: [u8; 32] = {
+            let mut bytes = [0u8; 32];
+            OsRng.fill_bytes(&mut bytes);
+            bytes
+        };
+        let pairing_topic = self.crypto.set_symkey(sym_key, None)?;
+        let expiry = Utc::now() + AUTHENTICATE_TTL;
+
+        let uri = PairingUri::builder(pairing_topic.clone())
+            .version(2)
+            .protocol("irn")
+            .symmetric_key(sym_key)
+            .expiry_timestamp(expiry)
+            .parameter("methods".into(), WC_SESSION_AUTHENTICATE.into())
+            .build();
+        self.expirer.set_expiry(&pairing_topic, expiry.timestamp_millis())?;
+
+        // the wallet does not know a symmetric key for the response yet,
+        // so it responds with a type 1 envelope addressed to our public key
+        let self_public = self.crypto.generate_keypair()?;
+        let response_topic: Topic<'static> = hex::encode(hash_sha256(&self_public)).into();
+        self.crypto.set_receiver_key(&response_topic, &self_public)?;
+
+        self.relayer.subscribe(&pairing_topic).await?;
+        self.relayer.subscribe(&response_topic).await?;
+
+        let requester = Participant::new(self_public, self.metadata.clone());
+        let params = SessionAuthenticateParams {
+            requester: requester.clone(),
+            auth_payload: payload.clone(),
+            expiry_timestamp: expiry.timestamp(),
+        };
+
+        let (tx, response) = oneshot::channel();
+        let this = self.clone();
+        self.tasks.spawn(async move {
+            let result =
+                this.request(&pairing_topic, &response_topic, requester, &payload, params).await;
+            // both topics only carry this request
+            for topic in [&pairing_topic, &response_topic] {
+                if let Err(e) = this.relayer.unsubscribe(topic).await {
+                    log::warn!("Failed to unsubscribe from {topic}: {e}");
+                }
+            }
+            if let Err(e) = this.forget(&pairing_topic, &response_topic, &self_public) {
+                log::warn!("Failed to forget the keys of authentication request: {e}");
+            }
+            let _ = tx.send(result);
+        });
+
+        Ok((uri, PendingAuthentication { response }))
+    }
+
+    /// Delete the keys and expiry of a resolved request
+    fn forget(
+        &self,
+        pairing_topic: &Topic<'static>,
+        response_topic: &Topic<'static>,
+        self_public: &[u8; 32],
+    ) -> Result<()> {
+        self.crypto.delete_symkey(pairing_topic.clone())?;
+        self.crypto.delete_symkey(response_topic.clone())?;
+        self.crypto.delete_symkey(hex::encode(self_public).into())?;
+        self.expirer.delete_expiry(pairing_topic)?;
+        Ok(())
+    }
+
+    async fn request(
+        &self,
+        pairing_topic: &Topic<'static>,
+        response_topic: &Topic<'static>,
+        requester: Participant,
+        payload: &AuthPayload,
+        params: SessionAuthenticateParams,
+    ) -> Result<Authenticated> {
+        let result = self
+            .peer
+            .request_answered_on(
+                pairing_topic,
+                response_topic,
+                WC_SESSION_AUTHENTICATE,
+                params,
+                AUTHENTICATE_TTL,
+            )
+            .await?;
+        let response: SessionAuthenticateResponse = serde_json::from_value(result)?;
+        self.on_response(pairing_topic.clone(), requester, payload, response).await
+    }
+
+    async fn on_response(
+        &self,
+        pairing_topic: Topic<'static>,
+        requester: Participant,
+        payload: &AuthPayload,
+        response: SessionAuthenticateResponse,
+    ) -> Result<Authenticated> {
+        let SessionAuthenticateResponse { cacaos, responder } = response;
+        if cacaos.is_empty() {
+            return Err(AuthenticateError::NoCacaos);
+        }
+
+        let mut accounts: Vec<(AccountId, &Cacao)> = Vec::with_capacity(cacaos.len());
+        for cacao in &cacaos {
+            let p = &cacao.p;
+            if p.domain != payload.domain || p.aud != payload.aud || p.nonce != payload.nonce {
+                return Err(AuthenticateError::PayloadMismatch(p.iss.clone()));
+            }
+            self.verifier.verify(cacao).await?;
+            let account = p.account()?;
+            if !payload.chains.contains(account.chain_id()) {
+                return Err(AuthenticateError::UnrequestedChain(p.iss.clone()));
+            }
+            accounts.push((account, cacao));
+        }
+
+        let mut namespaces = HashMap::new();
+        for (account, cacao) in accounts {
+            // the wallet may grant fewer methods than requested, but never more
+            let key = account.chain_id().namespace().to_string();
+            let requested = payload.methods(&key);
+            let granted: Vec<_> =
+                cacao.p.methods(&key).into_iter().filter(|m| requested.contains(m)).collect();
+            if granted.is_empty() {
+                continue;
+            }
+            let namespace: &mut Namespace = namespaces.entry(key).or_insert_with(|| Namespace {
+                chains: vec![],
+                methods: vec![],
+                events: vec![CHAIN_CHANGED.into(), ACCOUNTS_CHANGED.into()],
+                accounts: vec![],
+            });
+            for method in granted {
+                if !namespace.methods.contains(&method) {
+                    namespace.methods.push(method);
+                }
+            }
+            let chain = account.chain_id().to_string();
+            if !namespace.chains.contains(&chain) {
+                namespace.chains.push(chain);
+            }
+            namespace.accounts.push(account);
+        }
+
+        let session = if namespaces.is_empty() {
+            None
+        } else {
+            let topic =
+                self.crypto.generate_shared_key(&requester.public_key, &responder.public_key)?;
+            let session = Session {
+                topic,
+                pairing_topic,
+                relay: Relay::new("irn", None::<&str>),
+                expiry: (Utc::now() + SESSION_TTL).timestamp_millis(),
+                acknowledged: true,
+                controller: hex::encode(responder.public_key),
+                namespaces,
+                required_namespaces: Default::default(),
+                this: requester,
+                peer: responder.clone(),
+            };
+            self.sessions.set(&session)?;
+            self.expirer.set_expiry(&session.topic, session.expiry)?;
+            self.relayer.subscribe(&session.topic).await?;
+            Some(session)
+        };
+
+        Ok(Authenticated { cacaos, responder, session })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use k256::ecdsa::SigningKey;
+
+    use super::*;
+    use crate::{
+        peer::PeerRequest,
+        rpc::{
+            auth::cacao::EIP191,
+            signature::{address_of, eip191_hash, keccak256, sign_hash, to_checksum_address},
+            transport::LoopbackRelay,
+            types::{
+                payload::{Payload, Response},
+                policy, CacaoHeader, CacaoSignature, ChainId, PolicyKind, Recap,
+            },
+        },
+        testing::context,
+    };
+
+    /// Answer the first `wc_sessionAuthenticate` received on the pairing of `uri` with a CACAO
+    /// signed by `key` for its account on `chain`, granting only the `granted` methods, the way a
+    /// wallet does. Returns the topic of the session it derives, and the public key of the
+    /// requester.
+    async fn respond(
+        wallet: &WalletConnect,
+        uri: &PairingUri<'static>,
+        key: &SigningKey,
+        chain: ChainId,
+        granted: &[&str],
+    ) -> (Topic<'static>, [u8; 32]) {
+        let crypto = Crypto::new(wallet).unwrap();
+        let (pairing_topic, sym_key, ..) = uri.decompose();
+        crypto.set_symkey(*sym_key.unwrap(), Some(&pairing_topic)).unwrap();
+        let peer = PeerRpc::new(wallet).unwrap();
+        let mut requests = peer.requests();
+        wallet.relayer().subscribe(&pairing_topic).await.unwrap();
+
+        let PeerRequest { request, .. } = requests.recv().await.unwrap();
+        assert_eq!(request.method, WC_SESSION_AUTHENTICATE);
+        let params: SessionAuthenticateParams = serde_json::from_value(request.params).unwrap();
+
+        let address = to_checksum_address(&address_of(key.verifying_key()));
+        let account = AccountId::new(chain, address);
+        let mut recap = Recap::default();
+        recap.add_methods("eip155", granted);
+        let auth_payload =
+            AuthPayload { resources: Some(vec![recap.encode()]), ..params.auth_payload };
+        let p = auth_payload.cacao_payload(&account);
+        let hash = eip191_hash(p.siwe_message().unwrap().as_bytes());
+        let s = format!("0x{}", hex::encode(sign_hash(key, &hash).unwrap()));
+        let cacao = Cacao {
+            h: CacaoHeader::default(),
+            p,
+            s: CacaoSignature { t: EIP191.into(), s, m: None },
+        };
+
+        let self_public = crypto.generate_keypair().unwrap();
+        let peer_public = params.requester.public_key;
+        let responder = Participant::new(self_public, Metadata::default());
+        let result = SessionAuthenticateResponse { cacaos: vec![cacao], responder };
+        let response =
+            Payload::Response(Response::success(request.id, serde_json::to_value(result).unwrap()));
+        let message = crypto
+            .encode_type1(&self_public, &peer_public, &serde_json::to_vec(&response).unwrap())
+            .unwrap();
+        let response_topic: Topic<'static> = hex::encode(hash_sha256(&peer_public)).into();
+        wallet
+            .relayer()
+            .publish(
+                &response_topic,
+                message,
+                policy(WC_SESSION_AUTHENTICATE, PolicyKind::Response),
+            )
+            .await
+            .unwrap();
+        (crypto.generate_shared_key(&self_public, &peer_public).unwrap(), peer_public)
+    }
+
+    /// A request for `personal_sign` and `eth_sendTransaction` on `eip155:1`
+    fn payload() -> AuthPayload {
+        AuthPayload::builder("app.example", "https://app.example/login", "1234")
+            .chains([ChainId::new("eip155", "1")])
+            .methods("eip155", ["personal_sign", "eth_sendTransaction"])
+            .build()
+    }
+
+    /// The keys and expiry of the request identified by its pairing and requester key are gone
+    fn assert_forgotten(
+        dapp: &WalletConnect,
+        pairing_topic: &Topic<'static>,
+        requester: &[u8; 32],
+    ) {
+        let keychain = Crypto::new(dapp).unwrap();
+        let response_topic: Topic<'static> = hex::encode(hash_sha256(requester)).into();
+        for topic in [pairing_topic.clone(), response_topic, hex::encode(requester).into()] {
+            assert_eq!(keychain.keychain().get(&topic).unwrap(), None, "{topic}");
+        }
+        assert_eq!(ExpiryManager::new(dapp).unwrap().get_expiry(pairing_topic).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let relay = LoopbackRelay::new();
+        let (dapp, wallet) = (context(&relay), context(&relay));
+        let authenticate = Authenticate::new(&dapp, Metadata::default()).unwrap();
+        let key = SigningKey::from_slice(&keccak256(b"cow")).unwrap();
+
+        let (uri, pending) = authenticate.authenticate(payload()).await.unwrap();
+        let (pairing_topic, ..) = uri.decompose();
+        assert!(dapp.relayer().is_subscribed(&pairing_topic));
+
+        let chain = ChainId::new("eip155", "1");
+        let (session_topic, requester) =
+            respond(&wallet, &uri, &key, chain, &["personal_sign"]).await;
+        let authenticated = tokio::time::timeout(Duration::from_secs(5), pending.response())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(authenticated.cacaos.len(), 1);
+        let session = authenticated.session.unwrap();
+        assert_eq!(session.topic, session_topic);
+        assert_eq!(session.namespaces["eip155"].methods, vec!["personal_sign".to_string()]);
+        assert_eq!(
+            Sessions::new(&dapp).unwrap().get(&session.topic).unwrap(),
+            Some(session.clone())
+        );
+
+        // only the session topic is left subscribed, and only its key and expiry are kept
+        assert_eq!(dapp.relayer().topics(), vec![session.topic.clone()]);
+        assert_eq!(session.this.public_key, requester);
+        assert_forgotten(&dapp, &pairing_topic, &requester);
+        let crypto = Crypto::new(&dapp).unwrap();
+        assert!(crypto.keychain().get(&session.topic).unwrap().is_some());
+        let expiry = ExpiryManager::new(&dapp).unwrap().get_expiry(&session.topic).unwrap();
+        assert_eq!(expiry, Some(session.expiry));
+    }
+
+    #[tokio::test]
+    async fn test_unrequested_chain() {
+        let relay = LoopbackRelay::new();
+        let (dapp, wallet) = (context(&relay), context(&relay));
+        let authenticate = Authenticate::new(&dapp, Metadata::default()).unwrap();
+        let key = SigningKey::from_slice(&keccak256(b"cow")).unwrap();
+
+        let (uri, pending) = authenticate.authenticate(payload()).await.unwrap();
+        let (pairing_topic, ..) = uri.decompose();
+        let chain = ChainId::new("eip155", "10");
+        let (_, requester) = respond(&wallet, &uri, &key, chain, &["personal_sign"]).await;
+        let error = tokio::time::timeout(Duration::from_secs(5), pending.response())
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(error, AuthenticateError::UnrequestedChain(_)));
+        assert!(Sessions::new(&dapp).unwrap().all().unwrap().is_empty());
+        assert!(dapp.relayer().topics().is_empty());
+        assert_forgotten(&dapp, &pairing_topic, &requester);
+    }
+}
@@ -0,0 +1,472 @@
+//! The core of a WalletConnect client. A [`Core`] owns the one crypto store, relayer, expirer and
+//! event bus of a [`WalletConnect`] context, and hands them to the pairing and sign clients built
+//! on top of it, so every component sees the same keys and subscriptions.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use chrono::Utc;
+
+use crate::{
+    authenticate::Authenticate,
+    crypto::Crypto,
+    error::CoreError,
+    events::GlobalEvents,
+    expirations::{ExpirationEvent, ExpiryManager},
+    pairing::{Pairing, PairingEvent},
+    peer::PeerRpc,
+    relayer::Relayer,
+    rpc::types::Metadata,
+    session::{Session, SessionEvent, Sessions},
+    types::Topic,
+    wallet::WalletClient,
+    RelayEvent, WalletConnect,
+};
+
+pub type Result<T> = std::result::Result<T, CoreError>;
+
+/// Longest [`Core::shutdown`] waits for the outbox to be published
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often pairings and sessions are checked for expiry once [`Core::init`] ran
+pub const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What [`Core::init`] found in storage
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Restored {
+    pub pairings: usize,
+    pub sessions: usize,
+    /// pairings and sessions purged because they expired while we were offline
+    pub expired: usize,
+    /// messages the relay queued for the restored topics
+    pub messages: usize,
+}
+
+/// A cheaply clonable handle to the components of a client
+#[derive(Clone)]
+pub struct Core {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    context: WalletConnect,
+    crypto: Arc<Crypto>,
+    relayer: Arc<Relayer>,
+    expirer: Arc<ExpiryManager>,
+    pairing: Arc<Pairing>,
+    peer: PeerRpc,
+    sign: WalletClient,
+    authenticate: Authenticate,
+}
+
+impl Core {
+    /// Open the database at `path`, connect to the relay and wire the components together.
+    /// Call [`Core::init`] before use.
+    pub async fn new<P: AsRef<Path>>(path: P, metadata: Metadata) -> Result<Self> {
+        Self::from_context(WalletConnect::new(path).await?, metadata)
+    }
+
+    /// Wire the components of an existing context together
+    pub fn from_context(context: WalletConnect, metadata: Metadata) -> Result<Self> {
+        let crypto = Arc::new(Crypto::new(&context)?);
+        let relayer = context.relayer().clone();
+        let expirer = Arc::new(ExpiryManager::new(&context)?);
+        let pairing = Pairing::with_parts(
+            &context,
+            crypto.clone(),
+            relayer.clone(),
+            expirer.clone(),
+            context.events().clone(),
+        )?;
+        let pairing = Arc::new(pairing);
+        let peer = PeerRpc::new(&context)?;
+        let sign = WalletClient::with_parts(
+            &context,
+            crypto.clone(),
+            pairing.clone(),
+            peer.clone(),
+            relayer.clone(),
+            metadata.clone(),
+        )?;
+        let authenticate = Authenticate::with_parts(
+            &context,
+            crypto.clone(),
+            peer.clone(),
+            relayer.clone(),
+            expirer.clone(),
+            metadata,
+        )?;
+        let inner = Inner { context, crypto, relayer, expirer, pairing, peer, sign, authenticate };
+        Ok(Self { inner: Arc::new(inner) })
+    }
+
+    /// Restore the state of a previous run: purge expired pairings and sessions, resubscribe to
+    /// the topics of the live ones, handle the messages the relay queued for them while we were
+    /// offline, and publish what is left in the outbox.
+    /// Emits a `Restore` event for every live pairing and session, and an `Expire` event for
+    /// every purged one. Afterwards, topics are purged with [`Core::expire`] as they expire.
+    pub async fn init(&self) -> Result<Restored> {
+        let Inner { context, expirer, pairing, .. } = &*self.inner;
+        let events = context.events();
+        let now = Utc::now().timestamp_millis();
+        let mut restored = Restored::default();
+        let mut topics = Vec::new();
+
+        let pairings = pairing.pairings();
+        for (topic, _) in pairings.all()? {
+            match expirer.get_expiry(&topic)? {
+                Some(expiry) if expiry > now => {
+                    events.emit(PairingEvent::Restore { topic: topic.clone(), expiry });
+                    topics.push(topic);
+                    restored.pairings += 1;
+                }
+                _ => {
+                    self.purge(&topic).await?;
+                    pairings.delete(&topic)?;
+                    events.emit(PairingEvent::Expire { topic });
+                    restored.expired += 1;
+                }
+            }
+        }
+
+        let sessions = Sessions::new(context)?;
+        for Session { topic, peer, expiry, .. } in sessions.all()? {
+            if expiry > now {
+                events.emit(SessionEvent::Restore { topic: topic.clone(), peer, expiry });
+                topics.push(topic);
+                restored.sessions += 1;
+            } else {
+                self.purge(&topic).await?;
+                sessions.delete(&topic)?;
+                events.emit(SessionEvent::Expire { topic });
+                restored.expired += 1;
+            }
+        }
+
+        restored.messages = self.inner.relayer.restore(&topics).await?;
+
+        let inner = Arc::downgrade(&self.inner);
+        context.tasks().spawn(async move {
+            let mut interval = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(core) = Weak::upgrade(&inner).map(|inner| Core { inner }) else { break };
+                if let Err(e) = core.expire(Utc::now().timestamp_millis()).await {
+                    log::warn!("Failed to purge expired topics: {e}");
+                }
+            }
+        });
+        Ok(restored)
+    }
+
+    /// Purge every topic which expired by the millisecond timestamp `now`: its key, expiry,
+    /// subscription and the pairing or session on it.
+    /// Emits an [`ExpirationEvent`] for every topic, and the `Expire` event of its pairing or
+    /// session. Returns the number of expired topics.
+    pub async fn expire(&self, now: i64) -> Result<usize> {
+        let Inner { context, expirer, pairing, .. } = &*self.inner;
+        let events = context.events();
+        let sessions = Sessions::new(context)?;
+        let mut expired = expirer.expired(now)?.into_iter().collect::<HashMap<_, _>>();
+        for session in sessions.all()? {
+            if session.expiry <= now {
+                expired.insert(session.topic, session.expiry);
+            } else if expired.remove(&session.topic).is_some() {
+                // the session was extended past the expiry of its topic
+                expirer.set_expiry(&session.topic, session.expiry)?;
+            }
+        }
+
+        for (topic, expiry) in &expired {
+            self.purge(topic).await?;
+            if pairing.pairings().get(topic)?.is_some() {
+                pairing.pairings().delete(topic)?;
+                events.emit(PairingEvent::Expire { topic: topic.clone() });
+            }
+            if sessions.get(topic)?.is_some() {
+                sessions.delete(topic)?;
+                events.emit(SessionEvent::Expire { topic: topic.clone() });
+            }
+            events.emit(ExpirationEvent { topic: topic.clone(), expiry: *expiry });
+        }
+        Ok(expired.len())
+    }
+
+    /// Stop the client: publish what is left in the outbox, unsubscribe from the topics no stored
+    /// pairing or session needs, cancel every background task and wait until all of them
+    /// finished, then close the connection to the relay.
+    /// Messages the outbox could not publish within [`SHUTDOWN_TIMEOUT`] are kept for the next run.
+    /// Emits a [`RelayEvent::Disconnect`] once the transport reports the connection closed.
+    pub async fn shutdown(self) -> Result<()> {
+        let Inner { context, relayer, pairing, .. } = &*self.inner;
+        let pending = context.outbox().drain(SHUTDOWN_TIMEOUT).await?;
+        if pending > 0 {
+            log::warn!("Shutting down with {pending} unpublished messages");
+        }
+
+        let mut stored =
+            pairing.pairings().all()?.into_iter().map(|(topic, _)| topic).collect::<HashSet<_>>();
+        stored.extend(Sessions::new(context)?.all()?.into_iter().map(|s| s.topic));
+        for topic in relayer.persisted()?.into_keys().filter(|t| !stored.contains(t)) {
+            if let Err(e) = relayer.unsubscribe(&topic).await {
+                log::warn!("Failed to unsubscribe from {topic}: {e}");
+            }
+        }
+
+        // the task reporting the health of the connection is gone, so the closing is reported here
+        context.tasks().shutdown().await;
+        let transport = context.transport();
+        let connected = transport.health().borrow().connected;
+        let disconnected = transport.disconnected();
+        transport.close().await;
+        match tokio::time::timeout(SHUTDOWN_TIMEOUT, disconnected).await {
+            Ok(reason) if connected => context.events().emit(RelayEvent::Disconnect { reason }),
+            Ok(_) => {}
+            Err(_) => log::warn!("The relay connection was not reported closed"),
+        }
+        Ok(())
+    }
+
+    /// Forget the key, expiry and subscription of an expired topic
+    async fn purge(&self, topic: &Topic<'static>) -> Result<()> {
+        if let Err(e) = self.inner.relayer.unsubscribe(topic).await {
+            log::warn!("Failed to unsubscribe from expired topic {topic}: {e}");
+        }
+        self.inner.crypto.delete_symkey(topic.clone())?;
+        self.inner.expirer.delete_expiry(topic)?;
+        Ok(())
+    }
+
+    pub fn context(&self) -> &WalletConnect {
+        &self.inner.context
+    }
+
+    pub fn events(&self) -> &GlobalEvents {
+        self.inner.context.events()
+    }
+
+    pub fn crypto(&self) -> &Crypto {
+        &self.inner.crypto
+    }
+
+    pub fn relayer(&self) -> &Relayer {
+        &self.inner.relayer
+    }
+
+    pub fn expirer(&self) -> &ExpiryManager {
+        &self.inner.expirer
+    }
+
+    pub fn pairing(&self) -> &Pairing {
+        &self.inner.pairing
+    }
+
+    pub fn peer(&self) -> &PeerRpc {
+        &self.inner.peer
+    }
+
+    /// The wallet side of the Sign protocol
+    pub fn sign(&self) -> &WalletClient {
+        &self.inner.sign
+    }
+
+    /// One-click authentication with `wc_sessionAuthenticate`
+    pub fn authenticate(&self) -> &Authenticate {
+        &self.inner.authenticate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::{
+        events::EventFilter,
+        rpc::{
+            transport::{
+                LoopbackRelay, LoopbackTransport, RecordingTransport, TransportCall, CLOSED,
+            },
+            types::{Participant, Policy, Relay},
+        },
+        testing, EventPayload, GlobalEventKind,
+    };
+
+    type Recorded = Arc<RecordingTransport<LoopbackTransport>>;
+
+    fn assert_send_sync<T: Send + Sync + Clone + 'static>() {}
+
+    fn core(relay: &LoopbackRelay) -> Core {
+        Core::from_context(testing::context(relay), Metadata::default()).unwrap()
+    }
+
+    /// A core whose calls to the relay are recorded
+    fn recorded(relay: &LoopbackRelay) -> (Core, Recorded) {
+        let transport = Arc::new(RecordingTransport::new(relay.connect()));
+        let context = WalletConnect::with_transport(testing::db(), transport.clone());
+        (Core::from_context(context, Metadata::default()).unwrap(), transport)
+    }
+
+    /// Store a session on a new topic, expiring at `expiry`
+    fn session(core: &Core, n: u8, expiry: i64) -> Topic<'static> {
+        let topic = core.crypto().set_symkey([n; 32], None).unwrap();
+        let peer = Participant::new([n; 32], Default::default());
+        let session = Session {
+            topic: topic.clone(),
+            pairing_topic: topic.clone(),
+            relay: Relay::new("irn", None::<&str>),
+            expiry,
+            acknowledged: true,
+            controller: hex::encode([n; 32]),
+            namespaces: Default::default(),
+            required_namespaces: Default::default(),
+            this: peer.clone(),
+            peer,
+        };
+        Sessions::new(core.context()).unwrap().set(&session).unwrap();
+        core.expirer().set_expiry(&topic, expiry).unwrap();
+        topic
+    }
+
+    fn subscribed(calls: &[TransportCall]) -> Vec<String> {
+        calls
+            .iter()
+            .filter_map(|call| match call {
+                TransportCall::Subscribe { topic } => Some(topic.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_core_is_send_sync() {
+        assert_send_sync::<Core>();
+    }
+
+    #[tokio::test]
+    async fn test_expire() {
+        let core = core(&LoopbackRelay::new());
+        let mut events = core.events().subscribe(
+            EventFilter::all()
+                .kind(GlobalEventKind::PairingExpire)
+                .kind(GlobalEventKind::Expiration),
+        );
+        let (expired, _) = core.pairing().create().await.unwrap();
+        let (live, _) = core.pairing().create().await.unwrap();
+        core.expirer().set_expiry(&expired, 1).unwrap();
+
+        assert_eq!(core.expire(Utc::now().timestamp_millis()).await.unwrap(), 1);
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event.payload,
+            EventPayload::Pairing(PairingEvent::Expire { topic: expired.clone() })
+        );
+        let event = events.next().await.unwrap().unwrap();
+        let expiration = ExpirationEvent { topic: expired.clone(), expiry: 1 };
+        assert_eq!(event.payload, EventPayload::Expiration(expiration));
+
+        assert!(core.pairing().get(&expired).unwrap().is_none());
+        assert!(!core.crypto().keychain().contains(&expired).unwrap());
+        assert!(core.expirer().get_expiry(&expired).unwrap().is_none());
+        assert_eq!(core.relayer().topics(), vec![live]);
+    }
+
+    #[tokio::test]
+    async fn test_init() {
+        let (core, transport) = recorded(&LoopbackRelay::new());
+        let mut events = core.events().subscribe(
+            EventFilter::all()
+                .kind(GlobalEventKind::PairingRestore)
+                .kind(GlobalEventKind::PairingExpire)
+                .kind(GlobalEventKind::SessionRestore)
+                .kind(GlobalEventKind::SessionExpire),
+        );
+        let (live_pairing, _) = core.pairing().create().await.unwrap();
+        let (expired_pairing, _) = core.pairing().create().await.unwrap();
+        core.expirer().set_expiry(&expired_pairing, 1).unwrap();
+        let future = (Utc::now() + crate::time::DAY).timestamp_millis();
+        let live_session = session(&core, 1, future);
+        let expired_session = session(&core, 2, 1);
+        let before = transport.calls().len();
+        let tasks = core.context().tasks().len();
+
+        let restored = core.init().await.unwrap();
+        assert_eq!(restored, Restored { pairings: 1, sessions: 1, expired: 2, messages: 0 });
+
+        let mut expected = vec![
+            EventPayload::Pairing(PairingEvent::Restore {
+                topic: live_pairing.clone(),
+                expiry: core.expirer().get_expiry(&live_pairing).unwrap().unwrap(),
+            }),
+            EventPayload::Pairing(PairingEvent::Expire { topic: expired_pairing.clone() }),
+            EventPayload::Session(SessionEvent::Restore {
+                topic: live_session.clone(),
+                peer: Participant::new([1; 32], Default::default()),
+                expiry: future,
+            }),
+            EventPayload::Session(SessionEvent::Expire { topic: expired_session.clone() }),
+        ];
+        for _ in 0..expected.len() {
+            let payload = events.next().await.unwrap().unwrap().payload;
+            let position = expected.iter().position(|e| *e == payload).unwrap();
+            expected.remove(position);
+        }
+
+        // expired topics are purged, live ones subscribed again and their mailbox fetched
+        for topic in [&expired_pairing, &expired_session] {
+            assert!(!core.crypto().keychain().contains(topic).unwrap());
+            assert!(core.expirer().get_expiry(topic).unwrap().is_none());
+        }
+        assert!(core.pairing().get(&expired_pairing).unwrap().is_none());
+        assert!(Sessions::new(core.context()).unwrap().get(&expired_session).unwrap().is_none());
+        let mut live = vec![live_pairing.to_string(), live_session.to_string()];
+        live.sort();
+        let calls = transport.calls().split_off(before);
+        assert_eq!(subscribed(&calls), live);
+        assert!(calls.contains(&TransportCall::Fetch { topics: live.clone() }));
+        let mut topics: Vec<_> = core.relayer().topics().iter().map(ToString::to_string).collect();
+        topics.sort();
+        assert_eq!(topics, live);
+
+        // and expiring topics are purged from now on
+        assert_eq!(core.context().tasks().len(), tasks + 1);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let (core, transport) = recorded(&LoopbackRelay::new());
+        core.init().await.unwrap();
+        let context = core.context().clone();
+        let mut events =
+            core.events().subscribe(EventFilter::all().kind(GlobalEventKind::RelayDisconnect));
+        let (pairing, _) = core.pairing().create().await.unwrap();
+        let stray: Topic<'static> = hex::encode([9u8; 32]).into();
+        core.relayer().subscribe(&stray).await.unwrap();
+        core.relayer().publish(&pairing, "message".into(), Policy::default()).await.unwrap();
+
+        core.shutdown().await.unwrap();
+        assert!(context.outbox().pending().unwrap().is_empty());
+        assert!(transport.published().contains(&(pairing.to_string(), "message".to_string())));
+
+        // topics no pairing or session needs are unsubscribed
+        let calls = transport.calls();
+        let unsubscribed = |topic: &Topic<'static>| {
+            calls.iter().any(|call| {
+                matches!(call, TransportCall::Unsubscribe { topic: t, .. } if *t == topic.to_string())
+            })
+        };
+        assert!(unsubscribed(&stray));
+        assert!(!unsubscribed(&pairing));
+
+        assert!(context.tasks().is_shutdown());
+        assert!(context.tasks().is_empty());
+        let event = events.next().await.unwrap().unwrap();
+        let disconnect = RelayEvent::Disconnect { reason: CLOSED.into() };
+        assert_eq!(event.payload, EventPayload::Relay(disconnect));
+        assert!(!context.transport().health().borrow().connected);
+    }
+}
@@ -1,8 +1,12 @@
+pub mod envelope;
 mod keychain;
 pub use keychain::{Result as KeychainResult, *};
+use rand::rngs::OsRng;
 use sha2::Digest;
+use x25519_dalek::{PublicKey, StaticSecret};
 
-use crate::{types::Topic, WalletConnect};
+use self::envelope::Envelope;
+use crate::{error::CryptoError, types::Topic, WalletConnect};
 
 pub type Result<T> = std::result::Result<T, crate::error::CryptoError>;
 
@@ -12,6 +16,17 @@ pub fn hash_sha256(bytes: &[u8]) -> [u8; 32] {
     hasher.finalize().into()
 }
 
+/// Derive the symmetric key shared between an X25519 secret and a peer's public key,
+/// `HKDF-SHA256(X25519(secret, public))`
+pub fn derive_symkey(secret: &[u8; 32], public: &[u8; 32]) -> [u8; 32] {
+    let shared = StaticSecret::from(*secret).diffie_hellman(&PublicKey::from(*public));
+    let mut key = [0u8; 32];
+    hkdf::Hkdf::<sha2::Sha256>::new(None, shared.as_bytes())
+        .expand(&[], &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
 pub struct Crypto {
     keychain: Keychain,
 }
@@ -33,6 +48,69 @@ impl Crypto {
         Ok(topic)
     }
 
+    /// Generate an X25519 keypair, storing the secret under the hex encoded public key
+    pub fn generate_keypair(&self) -> Result<[u8; 32]> {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret).to_bytes();
+        self.keychain.set(&hex::encode(public).into(), secret.to_bytes())?;
+        Ok(public)
+    }
+
+    /// Derive and store the symmetric key shared by our keypair `self_public` and a peer's
+    /// public key, returning its topic
+    pub fn generate_shared_key(
+        &self,
+        self_public: &[u8; 32],
+        peer_public: &[u8; 32],
+    ) -> Result<Topic<'static>> {
+        let secret = self.private_key(self_public)?;
+        self.set_symkey(derive_symkey(&secret, peer_public), None)
+    }
+
+    /// Accept type 1 envelopes on `topic`, addressed to our keypair `self_public`.
+    /// The keychain entry of a topic receiving type 1 envelopes is the receiver's secret.
+    pub fn set_receiver_key(&self, topic: &Topic<'static>, self_public: &[u8; 32]) -> Result<()> {
+        let secret = self.private_key(self_public)?;
+        self.keychain.set(topic, secret)?;
+        Ok(())
+    }
+
+    /// Encrypt `payload` with the symmetric key of `topic`
+    pub fn encode(&self, topic: &Topic<'static>, payload: &[u8]) -> Result<String> {
+        let key = self.symkey(topic)?;
+        Ok(Envelope::seal(&key, payload, None)?.encode())
+    }
+
+    /// Encrypt `payload` in a type 1 envelope for a peer we do not share a symmetric key with
+    pub fn encode_type1(
+        &self,
+        self_public: &[u8; 32],
+        peer_public: &[u8; 32],
+        payload: &[u8],
+    ) -> Result<String> {
+        let key = derive_symkey(&self.private_key(self_public)?, peer_public);
+        Ok(Envelope::seal(&key, payload, Some(*self_public))?.encode())
+    }
+
+    /// Decrypt a `message` received on `topic`
+    pub fn decode(&self, topic: &Topic<'static>, message: &str) -> Result<Vec<u8>> {
+        let envelope = Envelope::decode(message)?;
+        let key = match &envelope.sender_public_key {
+            Some(sender) => derive_symkey(&self.symkey(topic)?, sender),
+            None => self.symkey(topic)?,
+        };
+        envelope.open(&key)
+    }
+
+    fn private_key(&self, public: &[u8; 32]) -> Result<[u8; 32]> {
+        let topic = hex::encode(public).into();
+        self.keychain.get(&topic)?.ok_or_else(|| CryptoError::MissingKey(topic.to_string()))
+    }
+
+    fn symkey(&self, topic: &Topic<'static>) -> Result<[u8; 32]> {
+        self.keychain.get(topic)?.ok_or_else(|| CryptoError::MissingKey(topic.to_string()))
+    }
+
     pub fn delete_symkey(&self, topic: Topic<'static>) -> Result<()> {
         self.keychain.delete(&topic)?;
         Ok(())
@@ -0,0 +1,114 @@
+//! Encoding of encrypted payloads described [here](https://specs.walletconnect.com/2.0/specs/clients/core/crypto/crypto-envelopes)
+//! type 0: tp (1 byte) + iv (12 bytes) + sealbox
+//! type 1: tp (1 byte) + sender public key (32 bytes) + iv (12 bytes) + sealbox
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{error::CryptoError, SymKey};
+
+pub type Result<T> = std::result::Result<T, CryptoError>;
+
+pub const TYPE_0: u8 = 0;
+pub const TYPE_1: u8 = 1;
+pub const TYPE_LENGTH: usize = 1;
+pub const KEY_LENGTH: usize = 32;
+pub const IV_LENGTH: usize = 12;
+
+/// An encrypted payload.
+/// Type 1 envelopes carry the X25519 public key of the sender, for peers which do not share a
+/// symmetric key yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub sender_public_key: Option<[u8; KEY_LENGTH]>,
+    iv: [u8; IV_LENGTH],
+    sealed: Vec<u8>,
+}
+
+impl Envelope {
+    /// Seal `plaintext` with `key`
+    pub fn seal(
+        key: &SymKey,
+        plaintext: &[u8],
+        sender_public_key: Option<[u8; KEY_LENGTH]>,
+    ) -> Result<Self> {
+        let mut iv = [0u8; IV_LENGTH];
+        OsRng.fill_bytes(&mut iv);
+
+        let cipher = ChaCha20Poly1305::new(key.into());
+        let sealed = cipher
+            .encrypt(Nonce::from_slice(&iv), plaintext)
+            .map_err(|_| CryptoError::Encryption)?;
+        Ok(Self { sender_public_key, iv, sealed })
+    }
+
+    /// Open the envelope with `key`
+    pub fn open(&self, key: &SymKey) -> Result<Vec<u8>> {
+        let cipher = ChaCha20Poly1305::new(key.into());
+        cipher
+            .decrypt(Nonce::from_slice(&self.iv), self.sealed.as_slice())
+            .map_err(|_| CryptoError::Decryption)
+    }
+
+    /// Decode a base64 encoded envelope
+    pub fn decode(message: &str) -> Result<Self> {
+        let bytes = data_encoding::BASE64.decode(message.as_bytes())?;
+        let (tp, rest) = bytes.split_first().ok_or(CryptoError::MalformedEnvelope)?;
+        let (sender_public_key, rest) = match *tp {
+            TYPE_0 => (None, rest),
+            TYPE_1 if rest.len() >= KEY_LENGTH => {
+                let (key, rest) = rest.split_at(KEY_LENGTH);
+                (Some(key.try_into().expect("split at key length")), rest)
+            }
+            TYPE_1 => return Err(CryptoError::MalformedEnvelope),
+            tp => return Err(CryptoError::UnsupportedEnvelope(tp)),
+        };
+        if rest.len() < IV_LENGTH {
+            return Err(CryptoError::MalformedEnvelope);
+        }
+        let (iv, sealed) = rest.split_at(IV_LENGTH);
+        Ok(Self {
+            sender_public_key,
+            iv: iv.try_into().expect("split at iv length"),
+            sealed: sealed.to_vec(),
+        })
+    }
+
+    /// Encode the envelope as base64
+    pub fn encode(&self) -> String {
+        let mut bytes =
+            Vec::with_capacity(TYPE_LENGTH + KEY_LENGTH + IV_LENGTH + self.sealed.len());
+        match &self.sender_public_key {
+            Some(key) => {
+                bytes.push(TYPE_1);
+                bytes.extend_from_slice(key);
+            }
+            None => bytes.push(TYPE_0),
+        }
+        bytes.extend_from_slice(&self.iv);
+        bytes.extend_from_slice(&self.sealed);
+        data_encoding::BASE64.encode(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let key = [7u8; 32];
+        let message = Envelope::seal(&key, b"{\"id\":1}", None).unwrap().encode();
+        let envelope = Envelope::decode(&message).unwrap();
+        assert_eq!(envelope.sender_public_key, None);
+        assert_eq!(envelope.open(&key).unwrap(), b"{\"id\":1}");
+        assert!(matches!(envelope.open(&[8u8; 32]), Err(CryptoError::Decryption)));
+
+        let message = Envelope::seal(&key, b"{\"id\":2}", Some([1u8; 32])).unwrap().encode();
+        let envelope = Envelope::decode(&message).unwrap();
+        assert_eq!(envelope.sender_public_key, Some([1u8; 32]));
+        assert_eq!(envelope.open(&key).unwrap(), b"{\"id\":2}");
+    }
+}
@@ -46,7 +46,11 @@ impl Keychain {
     /// Get a symmetric key under a [`Topic`]
     pub fn get(&self, topic: &Topic<'static>) -> Result<Option<[u8; 32]>> {
         let read_txn = self.db.begin_read()?;
-        let table = read_txn.open_table(TABLE)?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
         Ok(table.get(topic)?.map(|v| v.value()))
     }
 
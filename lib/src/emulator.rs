@@ -0,0 +1,310 @@
+//! A headless wallet for integration tests. The emulator holds local secp256k1 keys, approves
+//! every proposal its [`EmulatorConfig`] satisfies, rejects the rest, and answers `personal_sign`,
+//! `eth_signTypedData_v4` and `eth_sendTransaction` deterministically, so a dapp can run a full
+//! connect → sign → disconnect scenario against it without a human in the loop.
+
+use std::collections::HashMap;
+
+use k256::ecdsa::SigningKey;
+use serde_json::Value;
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use crate::{
+    rpc::{
+        error::SignatureError,
+        signature::{
+            address_of, keccak256, parse_address, sign_request, to_checksum_address,
+            ETH_SIGN_TYPED_DATA_V4, PERSONAL_SIGN,
+        },
+        types::{
+            payload::{
+                ErrorData, UNSUPPORTED_ACCOUNTS, UNSUPPORTED_CHAINS, UNSUPPORTED_METHODS,
+                UNSUPPORTED_NAMESPACE_KEY, USER_REJECTED,
+            },
+            AccountId, ChainId, Metadata, Namespace, NamespaceMap, SessionRequestParams,
+        },
+    },
+    wallet::{
+        session_namespaces, Result, SessionProposal, SessionRequest, WalletClient, WalletEvent,
+    },
+    WalletConnect,
+};
+
+pub const ETH_SEND_TRANSACTION: &str = "eth_sendTransaction";
+
+/// What the emulator approves
+#[derive(Clone, Debug)]
+pub struct EmulatorConfig {
+    /// chains every key has an account on, i.e `eip155:1`
+    pub chains: Vec<ChainId>,
+    pub methods: Vec<String>,
+    pub events: Vec<String>,
+    pub metadata: Metadata,
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        Self {
+            chains: vec![ChainId::new("eip155", "1")],
+            methods: vec![
+                PERSONAL_SIGN.into(),
+                ETH_SIGN_TYPED_DATA_V4.into(),
+                ETH_SEND_TRANSACTION.into(),
+            ],
+            events: vec!["chainChanged".into(), "accountsChanged".into()],
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+impl EmulatorConfig {
+    /// The namespaces the emulator approves, without accounts
+    pub fn namespaces(&self) -> NamespaceMap {
+        let mut namespaces = HashMap::new();
+        for chain in &self.chains {
+            namespaces.entry(chain.namespace().to_string()).or_insert_with(|| Namespace {
+                chains: vec![],
+                methods: self.methods.clone(),
+                events: self.events.clone(),
+                accounts: vec![],
+            });
+        }
+        namespaces
+    }
+}
+
+pub struct WalletEmulator {
+    wallet: WalletClient,
+    accounts: Vec<AccountId>,
+    handle: JoinHandle<()>,
+}
+
+impl WalletEmulator {
+    /// Start an emulator answering with `keys`
+    pub fn new(
+        context: &WalletConnect,
+        keys: Vec<SigningKey>,
+        config: EmulatorConfig,
+    ) -> Result<Self> {
+        let wallet = WalletClient::new(context, config.metadata.clone())?;
+        let accounts = accounts(&keys, &config.chains);
+        let mut events = wallet.events();
+        let emulator =
+            Emulator { wallet: wallet.clone(), keys, config, accounts: accounts.clone() };
+        let handle = context.tasks().spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if let Err(e) = emulator.handle(event).await {
+                            log::warn!("Emulator failed to handle event: {e}");
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => log::warn!("WalletEmulator lagged by {n} events"),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        Ok(Self { wallet, accounts, handle })
+    }
+
+    /// The wallet the emulator drives, to pair with a dapp or disconnect a session
+    pub fn wallet(&self) -> &WalletClient {
+        &self.wallet
+    }
+
+    /// CAIP-10 accounts of every key on every configured chain
+    pub fn accounts(&self) -> &[AccountId] {
+        &self.accounts
+    }
+}
+
+impl Drop for WalletEmulator {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+struct Emulator {
+    wallet: WalletClient,
+    keys: Vec<SigningKey>,
+    config: EmulatorConfig,
+    accounts: Vec<AccountId>,
+}
+
+impl Emulator {
+    async fn handle(&self, event: WalletEvent) -> Result<()> {
+        match event {
+            WalletEvent::SessionProposal(proposal) => self.propose(*proposal).await?,
+            WalletEvent::SessionRequest(request) => {
+                let SessionRequest { id, topic, params, .. } = *request;
+                match answer(&self.keys, &self.config, &params) {
+                    Ok(result) => self.wallet.respond(&topic, id, result).await?,
+                    Err(error) => self.wallet.respond_error(&topic, id, error).await?,
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    async fn propose(&self, proposal: SessionProposal) -> Result<()> {
+        let namespaces = self.config.namespaces();
+        match session_namespaces(&proposal.params, namespaces, self.accounts.clone()) {
+            Ok(namespaces) => {
+                self.wallet.approve(proposal.id, namespaces, vec![]).await?;
+            }
+            Err(e) => {
+                let reason = ErrorData::new(UNSUPPORTED_NAMESPACE_KEY, e.to_string());
+                self.wallet.reject(proposal.id, reason).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// CAIP-10 accounts of `keys` on `chains`
+pub fn accounts(keys: &[SigningKey], chains: &[ChainId]) -> Vec<AccountId> {
+    chains
+        .iter()
+        .flat_map(|chain| {
+            keys.iter().map(|key| {
+                AccountId::new(chain.clone(), to_checksum_address(&address_of(key.verifying_key())))
+            })
+        })
+        .collect()
+}
+
+/// Answer a session request the way the emulator does. Signatures are deterministic (RFC 6979),
+/// and `eth_sendTransaction` is never broadcast: its result is the keccak256 hash of the
+/// transaction as sent by the dapp.
+pub fn answer(
+    keys: &[SigningKey],
+    config: &EmulatorConfig,
+    params: &SessionRequestParams,
+) -> std::result::Result<Value, ErrorData> {
+    let SessionRequestParams { request, chain_id } = params;
+    if !config.chains.contains(chain_id) {
+        return Err(ErrorData::new(UNSUPPORTED_CHAINS, format!("Unsupported chain {chain_id}.")));
+    }
+    if !config.methods.contains(&request.method) {
+        let message = format!("Unsupported method {}.", request.method);
+        return Err(ErrorData::new(UNSUPPORTED_METHODS, message));
+    }
+    let unknown_account =
+        |address: &str| ErrorData::new(UNSUPPORTED_ACCOUNTS, format!("Unknown account {address}."));
+
+    if request.method == ETH_SEND_TRANSACTION {
+        let from = request.params.pointer("/0/from").and_then(Value::as_str).unwrap_or_default();
+        let owned = parse_address(from)
+            .is_ok_and(|from| keys.iter().any(|key| address_of(key.verifying_key()) == from));
+        if !owned {
+            return Err(unknown_account(from));
+        }
+        let transaction = serde_json::to_vec(&request.params).unwrap_or_default();
+        return Ok(Value::String(format!("0x{}", hex::encode(keccak256(&transaction)))));
+    }
+
+    for key in keys {
+        match sign_request(key, &request.method, &request.params) {
+            Ok(Some(signature)) => return Ok(Value::String(signature)),
+            Ok(None) => break,
+            Err(SignatureError::UnknownAccount(_)) => continue,
+            Err(e) => return Err(ErrorData::new(USER_REJECTED, e.to_string())),
+        }
+    }
+    Err(match request.method.as_str() {
+        PERSONAL_SIGN | ETH_SIGN_TYPED_DATA_V4 => unknown_account("in request"),
+        method => ErrorData::new(UNSUPPORTED_METHODS, format!("Unsupported method {method}.")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        provider::{Provider, ProviderEvent},
+        rpc::{signature::verify_response, transport::LoopbackRelay, types::Caip27Request},
+        session::Sessions,
+        testing::{self, Dapp},
+    };
+
+    fn request(method: &str, params: Value) -> SessionRequestParams {
+        SessionRequestParams {
+            request: Caip27Request::new(method, params),
+            chain_id: ChainId::new("eip155", "1"),
+        }
+    }
+
+    #[test]
+    fn test_answer() {
+        let keys = vec![SigningKey::from_slice(&keccak256(b"cow")).unwrap()];
+        let config = EmulatorConfig::default();
+        let account = accounts(&keys, &config.chains).remove(0);
+        let address = account.address().to_string();
+
+        let params = json!(["hello walletconnect", address]);
+        let signature = answer(&keys, &config, &request(PERSONAL_SIGN, params.clone())).unwrap();
+        assert!(verify_response(&account, PERSONAL_SIGN, &params, &signature).unwrap());
+        assert_eq!(answer(&keys, &config, &request(PERSONAL_SIGN, params)).unwrap(), signature);
+
+        let transaction = json!([{ "from": address, "to": address, "value": "0x1" }]);
+        let hash = answer(&keys, &config, &request(ETH_SEND_TRANSACTION, transaction.clone()));
+        assert_eq!(hash, answer(&keys, &config, &request(ETH_SEND_TRANSACTION, transaction)));
+
+        let other = json!(["hello", "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"]);
+        let error = answer(&keys, &config, &request(PERSONAL_SIGN, other)).unwrap_err();
+        assert_eq!(error.code, UNSUPPORTED_ACCOUNTS);
+        let error = answer(&keys, &config, &request("eth_sign", json!([]))).unwrap_err();
+        assert_eq!(error.code, UNSUPPORTED_METHODS);
+
+        let mut params = request(PERSONAL_SIGN, json!(["hello", address]));
+        params.chain_id = ChainId::new("eip155", "10");
+        assert_eq!(answer(&keys, &config, &params).unwrap_err().code, UNSUPPORTED_CHAINS);
+    }
+
+    #[tokio::test]
+    async fn test_connect_sign_disconnect() {
+        let relay = LoopbackRelay::new();
+        let (dapp, wallet) = (Dapp::new(&relay), testing::context(&relay));
+        let keys = vec![SigningKey::from_slice(&keccak256(b"cow")).unwrap()];
+        let emulator = WalletEmulator::new(&wallet, keys, EmulatorConfig::default()).unwrap();
+        let chain = ChainId::new("eip155", "1");
+
+        // connect
+        let required = HashMap::from([(
+            "eip155".to_string(),
+            Namespace {
+                chains: vec![chain.to_string()],
+                methods: vec![PERSONAL_SIGN.into()],
+                events: vec![],
+                accounts: vec![],
+            },
+        )]);
+        let (uri, proposal) = dapp.propose(required, None).await;
+        emulator.wallet().pair(uri).await.unwrap();
+        let session = proposal.await.unwrap().unwrap();
+        assert_eq!(session.namespaces["eip155"].accounts, emulator.accounts());
+
+        // sign
+        let provider = Provider::new(&dapp.context, session.topic.clone(), chain).await.unwrap();
+        let mut events = provider.subscribe();
+        let address = emulator.accounts()[0].address().to_string();
+        let params = json!(["hello walletconnect", address]);
+        let signature = provider.request(PERSONAL_SIGN, params.clone()).await.unwrap();
+        assert!(
+            verify_response(&emulator.accounts()[0], PERSONAL_SIGN, &params, &signature).unwrap()
+        );
+
+        // disconnect
+        emulator.wallet().disconnect(&session.topic).await.unwrap();
+        assert_eq!(Sessions::new(&wallet).unwrap().get(&session.topic).unwrap(), None);
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, ProviderEvent::Disconnect { .. }));
+        assert_eq!(Sessions::new(&dapp.context).unwrap().get(&session.topic).unwrap(), None);
+    }
+}
@@ -35,6 +35,18 @@ pub enum KeychainError {
 pub enum CryptoError {
     #[error(transparent)]
     Keychain(#[from] KeychainError),
+    #[error("no symmetric key for topic {0}")]
+    MissingKey(String),
+    #[error("failed to encrypt payload")]
+    Encryption,
+    #[error("failed to decrypt envelope")]
+    Decryption,
+    #[error("malformed envelope")]
+    MalformedEnvelope,
+    #[error("unsupported envelope type {0}")]
+    UnsupportedEnvelope(u8),
+    #[error(transparent)]
+    Base64(#[from] data_encoding::DecodeError),
 }
 
 #[derive(Debug, Error)]
@@ -63,6 +75,10 @@ pub enum PairingError {
     Missing(Missing),
     #[error(transparent)]
     Speedy(#[from] speedy::Error),
+    #[error("pairing {0} already exists, pair with a new URI")]
+    AlreadyExists(String),
+    #[error("no pairing on topic {0}")]
+    NotFound(String),
 }
 
 #[derive(Debug)]
@@ -94,8 +110,260 @@ pub enum ExpiryError {
     MillisecondConversion,
 }
 
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error(transparent)]
+    Table(#[from] redb::TableError),
+    #[error(transparent)]
+    Db(#[from] redb::TransactionError),
+    #[error(transparent)]
+    Storage(#[from] redb::StorageError),
+    #[error(transparent)]
+    Commit(#[from] redb::CommitError),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum OutboxError {
+    #[error(transparent)]
+    Transport(#[from] crate::rpc::error::TransportError),
+    #[error(transparent)]
+    Table(#[from] redb::TableError),
+    #[error(transparent)]
+    Db(#[from] redb::TransactionError),
+    #[error(transparent)]
+    Storage(#[from] redb::StorageError),
+    #[error(transparent)]
+    Commit(#[from] redb::CommitError),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum WatchError {
+    #[error(transparent)]
+    Client(#[from] walletconnect_rpc::error::ClientError),
+    #[error(transparent)]
+    Auth(#[from] crate::rpc::error::AuthError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("invalid {0} claim in webhook event")]
+    InvalidEvent(&'static str),
+    #[error("webhooks are registered over the websocket, and this context has none")]
+    NoWebsocket,
+}
+
 #[derive(Debug, Error)]
 pub enum RelayerError {
     #[error(transparent)]
-    Rpc(#[from] crate::rpc::error::JsonRpcError),
+    Transport(#[from] crate::rpc::error::TransportError),
+    #[error(transparent)]
+    Table(#[from] redb::TableError),
+    #[error(transparent)]
+    Db(#[from] redb::TransactionError),
+    #[error(transparent)]
+    Storage(#[from] redb::StorageError),
+    #[error(transparent)]
+    Commit(#[from] redb::CommitError),
+    #[error(transparent)]
+    Outbox(#[from] OutboxError),
+}
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error(transparent)]
+    Table(#[from] redb::TableError),
+    #[error(transparent)]
+    Db(#[from] redb::TransactionError),
+    #[error(transparent)]
+    Storage(#[from] redb::StorageError),
+    #[error(transparent)]
+    Commit(#[from] redb::CommitError),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum PeerError {
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Relayer(#[from] RelayerError),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+    #[error("peer responded with error {}: {}", _0.code, _0.message)]
+    Response(crate::rpc::types::payload::ErrorData),
+    #[error("no response from peer within {0:?}")]
+    Timeout(std::time::Duration),
+    #[error("peer rpc was dropped before a response arrived")]
+    Closed,
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    History(#[from] HistoryError),
+}
+
+#[derive(Debug, Error)]
+pub enum ProviderError {
+    #[error(transparent)]
+    Peer(#[from] PeerError),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    Relayer(#[from] RelayerError),
+    #[error(transparent)]
+    Signature(#[from] crate::rpc::error::SignatureError),
+    #[error("no session for topic {0}")]
+    UnknownSession(String),
+    #[error("chain {0} was not approved for this session")]
+    UnsupportedChain(String),
+    #[error("invalid params for {0}")]
+    InvalidParams(&'static str),
+}
+
+#[derive(Debug, Error)]
+pub enum AuthenticateError {
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Expiry(#[from] ExpiryError),
+    #[error(transparent)]
+    Relayer(#[from] RelayerError),
+    #[error(transparent)]
+    Peer(#[from] PeerError),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+    #[error(transparent)]
+    Type(#[from] crate::rpc::error::TypeError),
+    #[error(transparent)]
+    Cacao(#[from] crate::rpc::error::CacaoError),
+    #[error("authentication request was cancelled before the wallet responded")]
+    Cancelled,
+    #[error("wallet responded without any CACAO")]
+    NoCacaos,
+    #[error("CACAO issued by {0} does not match the authentication request")]
+    PayloadMismatch(String),
+    #[error("CACAO issued by {0} is for a chain the authentication request did not ask for")]
+    UnrequestedChain(String),
+}
+
+#[derive(Debug, Error)]
+pub enum IdentityKeysError {
+    #[error(transparent)]
+    Table(#[from] redb::TableError),
+    #[error(transparent)]
+    Db(#[from] redb::TransactionError),
+    #[error(transparent)]
+    Storage(#[from] redb::StorageError),
+    #[error(transparent)]
+    Commit(#[from] redb::CommitError),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+    #[error(transparent)]
+    Type(#[from] crate::rpc::error::TypeError),
+    #[error(transparent)]
+    Keys(#[from] crate::rpc::error::IdentityError),
+    #[error("failed to sign the identity key CACAO: {0}")]
+    Sign(crate::rpc::BoxError),
+}
+
+#[derive(Debug, Error)]
+pub enum NotifyError {
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Relayer(#[from] RelayerError),
+    #[error(transparent)]
+    Peer(#[from] PeerError),
+    #[error(transparent)]
+    Identity(#[from] IdentityKeysError),
+    #[error(transparent)]
+    Auth(#[from] crate::rpc::error::AuthError),
+    #[error(transparent)]
+    Key(#[from] ed25519_dalek::SignatureError),
+    #[error(transparent)]
+    Table(#[from] redb::TableError),
+    #[error(transparent)]
+    Db(#[from] redb::TransactionError),
+    #[error(transparent)]
+    Storage(#[from] redb::StorageError),
+    #[error(transparent)]
+    Commit(#[from] redb::CommitError),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+    #[error("failed to fetch dapp documents: {0}")]
+    Fetch(crate::rpc::BoxError),
+    #[error("{0} does not publish a notify key")]
+    MissingKey(String),
+    #[error("{0} has no registered identity key")]
+    NotRegistered(crate::rpc::types::AccountId),
+    #[error("no notify subscription on topic {0}")]
+    UnknownSubscription(String),
+    #[error("unexpected JWT act {0}")]
+    UnexpectedAct(String),
+    #[error("notify message JWT without a message")]
+    MissingMessage,
+    #[error("notify message JWT has an invalid {0} claim")]
+    InvalidClaim(&'static str),
+}
+
+#[derive(Debug, Error)]
+pub enum WalletError {
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Expiry(#[from] ExpiryError),
+    #[error(transparent)]
+    Peer(#[from] PeerError),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    Pairing(#[from] PairingError),
+    #[error(transparent)]
+    Relayer(#[from] RelayerError),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+    #[error("no pending session proposal with id {0}")]
+    UnknownProposal(u64),
+    #[error("session proposal {0} has expired")]
+    ExpiredProposal(u64),
+    #[error("no approved namespace for account {0}")]
+    UnsupportedAccount(String),
+    #[error("required namespace {0} is not satisfied")]
+    UnsatisfiedNamespace(String),
+}
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum EventsError {
+    #[error("event stream lagged, {0} events were dropped")]
+    Lagged(u64),
+}
+
+#[derive(Debug, Error)]
+pub enum CoreError {
+    #[error(transparent)]
+    WalletConnect(#[from] WalletConnectError),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Expiry(#[from] ExpiryError),
+    #[error(transparent)]
+    Outbox(#[from] OutboxError),
+    #[error(transparent)]
+    Pairing(#[from] PairingError),
+    #[error(transparent)]
+    Peer(#[from] PeerError),
+    #[error(transparent)]
+    Relayer(#[from] RelayerError),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    Wallet(#[from] WalletError),
+    #[error(transparent)]
+    Authenticate(#[from] AuthenticateError),
 }
@@ -1,22 +1,243 @@
-use crate::GlobalEvent;
+//! The event bus. Listeners [`subscribe`](GlobalEvents::subscribe) to a [`Stream`] of the events
+//! matching an [`EventFilter`]; dropping the stream unsubscribes. A listener which falls more
+//! than [`MESSAGE_CAPACITY`] events behind is told how many it missed instead of panicking.
 
+use std::{
+    collections::HashSet,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::sync::broadcast;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream,
+};
+
+use crate::{
+    error::EventsError,
+    pairing::PairingEvent,
+    rpc::types::{
+        payload::Request, SessionExtendParams, SessionProposeParams, SessionSettleParams,
+        SessionUpdateParams, WC_PAIRING_DELETE, WC_PAIRING_PING, WC_SESSION_DELETE,
+        WC_SESSION_EVENT, WC_SESSION_EXTEND, WC_SESSION_PING, WC_SESSION_PROPOSE,
+        WC_SESSION_REQUEST, WC_SESSION_SETTLE, WC_SESSION_UPDATE,
+    },
+    session::SessionEvent,
+    types::Topic,
+    EventPayload, GlobalEvent, GlobalEventKind, MESSAGE_CAPACITY,
+};
+
+pub type Result<T> = std::result::Result<T, EventsError>;
+
+#[derive(Clone, Debug)]
 pub struct GlobalEvents {
-    sender: tokio::sync::broadcast::Sender<crate::GlobalEvent>,
+    sender: broadcast::Sender<GlobalEvent>,
+}
+
+impl Default for GlobalEvents {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GlobalEvents {
-    pub fn emit<E: Into<super::GlobalEvent>>(&self, event: E) {
-        let _ = self.sender.send(event.into());
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(MESSAGE_CAPACITY);
+        Self { sender }
+    }
+
+    /// Emit `event`, stamped with the current time
+    pub fn emit<E: Into<EventPayload>>(&self, event: E) {
+        let _ = self.sender.send(GlobalEvent::new(event));
+    }
+
+    /// Listen to the events matching `filter`, until the stream is dropped
+    pub fn subscribe(&self, filter: EventFilter) -> EventStream {
+        EventStream { inner: BroadcastStream::new(self.sender.subscribe()), filter }
+    }
+
+    /// Number of live subscriptions
+    pub fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+/// Which events a subscription receives. An empty filter matches every event.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    kinds: HashSet<GlobalEventKind>,
+    topics: HashSet<Topic<'static>>,
+}
+
+impl EventFilter {
+    /// Match every event
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Also match events of `kind`. Once a kind is set, events of other kinds are filtered out.
+    pub fn kind(mut self, kind: GlobalEventKind) -> Self {
+        self.kinds.insert(kind);
+        self
+    }
+
+    /// Also match events on `topic`. Once a topic is set, events on other topics and events
+    /// without a topic are filtered out.
+    pub fn topic(mut self, topic: Topic<'static>) -> Self {
+        self.topics.insert(topic);
+        self
+    }
+
+    pub fn matches(&self, event: &GlobalEvent) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&event.kind()))
+            && (self.topics.is_empty() || event.topic().is_some_and(|t| self.topics.contains(t)))
     }
+}
+
+/// A subscription to the event bus. Yields [`EventsError::Lagged`] when events were dropped
+/// because the listener fell behind, then resumes with the oldest event still buffered.
+pub struct EventStream {
+    inner: BroadcastStream<GlobalEvent>,
+    filter: EventFilter,
+}
+
+impl Stream for EventStream {
+    type Item = Result<GlobalEvent>;
 
-    pub fn register<F: Fn(&GlobalEvent) + Send + 'static>(&self, f: F) {
-        let mut receiver = self.sender.subscribe();
-        tokio::spawn(async move {
-            loop {
-                //TODO: Handle lagging
-                let ev = receiver.recv().await.unwrap();
-                f(&ev)
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(event))) if !self.filter.matches(&event) => continue,
+                Poll::Ready(Some(Ok(event))) => return Poll::Ready(Some(Ok(event))),
+                Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(n)))) => {
+                    return Poll::Ready(Some(Err(EventsError::Lagged(n))))
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
             }
-        });
+        }
+    }
+}
+
+/// The event a request received from a peer on `topic` is reported as, if any
+pub(crate) fn inbound_event(
+    topic: &Topic<'static>,
+    request: &Request,
+) -> serde_json::Result<Option<EventPayload>> {
+    let topic = topic.clone();
+    let params = || request.params.clone();
+    let request = request.clone();
+    let event: EventPayload = match request.method.as_str() {
+        WC_PAIRING_PING => PairingEvent::Ping { topic, request }.into(),
+        WC_PAIRING_DELETE => {
+            let reason = serde_json::from_value(params())?;
+            PairingEvent::Delete { topic, request, reason }.into()
+        }
+        WC_SESSION_PROPOSE => {
+            let SessionProposeParams { proposer, .. } = serde_json::from_value(params())?;
+            SessionEvent::Proposal { pairing_topic: topic, request, proposer }.into()
+        }
+        WC_SESSION_SETTLE => {
+            let SessionSettleParams { controller, namespaces, expiry, .. } =
+                serde_json::from_value(params())?;
+            let request = Some(request);
+            let expiry = expiry * 1000;
+            SessionEvent::Settle { topic, request, peer: controller, namespaces, expiry }.into()
+        }
+        WC_SESSION_UPDATE => {
+            let SessionUpdateParams { namespaces } = serde_json::from_value(params())?;
+            SessionEvent::Update { topic, request, namespaces }.into()
+        }
+        WC_SESSION_EXTEND => {
+            let SessionExtendParams { expiry } = serde_json::from_value(params())?;
+            SessionEvent::Extend { topic, request, expiry: expiry * 1000 }.into()
+        }
+        WC_SESSION_REQUEST => {
+            let params = serde_json::from_value(params())?;
+            SessionEvent::Request { topic, request, params }.into()
+        }
+        WC_SESSION_EVENT => {
+            let params = serde_json::from_value(params())?;
+            SessionEvent::Event { topic, request, params }.into()
+        }
+        WC_SESSION_PING => SessionEvent::Ping { topic, request }.into(),
+        WC_SESSION_DELETE => {
+            let reason = serde_json::from_value(params())?;
+            SessionEvent::Delete { topic, request, reason }.into()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::rpc::types::DeleteParams;
+
+    fn ping(topic: &Topic<'static>) -> PairingEvent {
+        PairingEvent::Ping {
+            topic: topic.clone(),
+            request: Request::new(1, WC_PAIRING_PING, json!({})),
+        }
+    }
+
+    fn delete(topic: &Topic<'static>) -> PairingEvent {
+        PairingEvent::Delete {
+            topic: topic.clone(),
+            request: Request::new(2, WC_PAIRING_DELETE, json!({})),
+            reason: DeleteParams { code: 6000, message: "User disconnected.".into() },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let events = GlobalEvents::new();
+        let topic: Topic<'static> = "topic".to_string().into();
+        let mut filtered = events.subscribe(
+            EventFilter::all().kind(GlobalEventKind::PairingDelete).topic(topic.clone()),
+        );
+        let mut lagging = events.subscribe(EventFilter::all());
+        assert_eq!(events.subscribers(), 2);
+
+        events.emit(delete(&"other".to_string().into()));
+        events.emit(ping(&topic));
+        events.emit(delete(&topic));
+
+        let event = filtered.next().await.unwrap().unwrap();
+        assert_eq!(event.topic(), Some(&topic));
+        assert_eq!(event.kind(), GlobalEventKind::PairingDelete);
+        assert_eq!(event.request().unwrap().method, WC_PAIRING_DELETE);
+
+        for _ in 0..MESSAGE_CAPACITY {
+            events.emit(ping(&topic));
+        }
+        assert!(matches!(lagging.next().await, Some(Err(EventsError::Lagged(3)))));
+        assert!(lagging.next().await.unwrap().is_ok());
+
+        drop(lagging);
+        assert_eq!(events.subscribers(), 1);
+    }
+
+    #[test]
+    fn test_inbound_event() {
+        let topic: Topic<'static> = "topic".to_string().into();
+        let request = Request::new(1, WC_SESSION_EXTEND, json!({ "expiry": 1_700_000_000 }));
+        let event = GlobalEvent::new(inbound_event(&topic, &request).unwrap().unwrap());
+        assert_eq!(event.kind(), GlobalEventKind::SessionExtend);
+        assert_eq!(event.topic(), Some(&topic));
+        assert!(matches!(
+            event.payload,
+            EventPayload::Session(SessionEvent::Extend { expiry: 1_700_000_000_000, .. })
+        ));
+
+        let malformed = Request::new(2, WC_SESSION_EXTEND, json!({}));
+        assert!(inbound_event(&topic, &malformed).is_err());
+        let other = Request::new(3, "wc_notifyMessage", json!({}));
+        assert!(inbound_event(&topic, &other).unwrap().is_none());
     }
 }
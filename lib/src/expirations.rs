@@ -2,7 +2,7 @@
 use std::sync::Arc;
 
 use const_format::concatcp;
-use redb::TableDefinition;
+use redb::{ReadableTable, TableDefinition};
 
 use crate::{types::Topic, WalletConnect, STORAGE_PREFIX};
 
@@ -34,12 +34,59 @@ impl ExpiryManager {
 
     pub fn get_expiry(&self, topic: &Topic<'static>) -> Result<Option<i64>> {
         let read_txn = self.db.begin_read()?;
-        let table = read_txn.open_table(TABLE)?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
         Ok(table.get(topic)?.map(|v| v.value()))
     }
+
+    /// Every topic whose expiry is at or before the millisecond timestamp `now`, with its expiry
+    pub fn expired(&self, now: i64) -> Result<Vec<(Topic<'static>, i64)>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut expired = Vec::new();
+        for entry in table.iter()? {
+            let (topic, expiry) = entry?;
+            if expiry.value() <= now {
+                expired.push((topic.value(), expiry.value()));
+            }
+        }
+        Ok(expired)
+    }
+
+    pub fn delete_expiry(&self, topic: &Topic<'static>) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            let _value_guard = table.remove(topic)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
+
+/// The expiry set for a topic passed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpirationEvent {
+    pub topic: Topic<'static>,
+    /// millisecond timestamp the topic expired at
+    pub expiry: i64,
 }
 
-#[derive(Clone, Copy)]
-pub enum ExpirationEvent {
-    TestEvent,
+impl ExpirationEvent {
+    pub fn topic(&self) -> &Topic<'static> {
+        &self.topic
+    }
+}
+
+impl From<ExpirationEvent> for crate::EventPayload {
+    fn from(event: ExpirationEvent) -> crate::EventPayload {
+        crate::EventPayload::Expiration(event)
+    }
 }
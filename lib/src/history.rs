@@ -0,0 +1,263 @@
+//! JSON-RPC history. Every request sent or received is recorded by topic and id along with the
+//! response that answered it, and every inbound relay message is remembered by hash, since relays
+//! deliver at-least-once and peers may replay request ids.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use const_format::concatcp;
+use redb::{ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use sha2::Digest as _;
+
+use crate::{
+    error::HistoryError,
+    rpc::types::payload::{Request, Response},
+    time,
+    types::Topic,
+    WalletConnect, STORAGE_PREFIX,
+};
+
+pub type Result<T> = std::result::Result<T, HistoryError>;
+
+pub const HISTORY: &str = "history";
+pub const MESSAGES: &str = "messages";
+pub const VERSION: u16 = 1;
+pub const NAMESPACE: &str = concatcp!(STORAGE_PREFIX, ":", VERSION, "//", HISTORY);
+pub const MESSAGES_NAMESPACE: &str = concatcp!(STORAGE_PREFIX, ":", VERSION, "//", MESSAGES);
+/// (topic the response travels on, request id) -> record
+const TABLE: TableDefinition<(&str, u64), &[u8]> = TableDefinition::new(NAMESPACE);
+/// hex sha256 of an inbound message -> millisecond timestamp it was first received at
+const MESSAGES_TABLE: TableDefinition<&str, i64> = TableDefinition::new(MESSAGES_NAMESPACE);
+
+/// How long records and message hashes are kept. Relays keep messages for 30 days at most, so
+/// older messages cannot be delivered again.
+pub const RETENTION: Duration = time::MONTH;
+
+/// How often records and message hashes past [`RETENTION`] are pruned
+pub const PRUNE_INTERVAL: Duration = time::HOUR;
+
+/// A request and, once answered, its response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonRpcRecord {
+    pub id: u64,
+    pub topic: Topic<'static>,
+    pub request: Request,
+    pub response: Option<Response>,
+    /// millisecond timestamp the request was sent or received at
+    pub created_at: i64,
+    /// millisecond timestamp the response was sent or received at
+    pub answered_at: Option<i64>,
+}
+
+impl JsonRpcRecord {
+    pub fn is_pending(&self) -> bool {
+        self.response.is_none()
+    }
+}
+
+/// Persistent JSON-RPC history and inbound message hashes
+#[derive(Clone)]
+pub struct History {
+    db: Arc<redb::Database>,
+}
+
+impl History {
+    pub fn new(context: &WalletConnect) -> Result<Self> {
+        Ok(Self { db: context.db.clone() })
+    }
+
+    /// Record a request sent or received on `topic`.
+    /// Returns `false` without overwriting anything if a request with the same id is already
+    /// recorded on the topic.
+    pub fn set(&self, topic: &Topic<'static>, request: &Request) -> Result<bool> {
+        self.set_answered_on(topic, topic, request)
+    }
+
+    /// Record a request sent on `topic` which the peer answers on `response_topic`, i.e in a type
+    /// 1 envelope.
+    /// Returns `false` without overwriting anything if a request with the same id is already
+    /// recorded on the response topic.
+    pub fn set_answered_on(
+        &self,
+        topic: &Topic<'static>,
+        response_topic: &Topic<'static>,
+        request: &Request,
+    ) -> Result<bool> {
+        let record = JsonRpcRecord {
+            id: request.id,
+            topic: topic.clone(),
+            request: request.clone(),
+            response: None,
+            created_at: Utc::now().timestamp_millis(),
+            answered_at: None,
+        };
+        let bytes = serde_json::to_vec(&record)?;
+        let key = (response_topic.as_str(), request.id);
+        let write_txn = self.db.begin_write()?;
+        let is_new = {
+            let mut table = write_txn.open_table(TABLE)?;
+            let is_new = table.get(key)?.is_none();
+            if is_new {
+                table.insert(key, bytes.as_slice())?;
+            }
+            is_new
+        };
+        write_txn.commit()?;
+        Ok(is_new)
+    }
+
+    /// Record the response to a request, sent or received on `topic`.
+    /// Returns `false` if the request is unknown on the topic or already answered.
+    pub fn resolve(&self, topic: &Topic<'static>, response: &Response) -> Result<bool> {
+        let key = (topic.as_str(), response.id);
+        let write_txn = self.db.begin_write()?;
+        let resolved = {
+            let mut table = write_txn.open_table(TABLE)?;
+            let record = table
+                .get(key)?
+                .map(|v| serde_json::from_slice::<JsonRpcRecord>(v.value()))
+                .transpose()?;
+            match record {
+                Some(mut record) if record.is_pending() => {
+                    record.response = Some(response.clone());
+                    record.answered_at = Some(Utc::now().timestamp_millis());
+                    table.insert(key, serde_json::to_vec(&record)?.as_slice())?;
+                    true
+                }
+                _ => false,
+            }
+        };
+        write_txn.commit()?;
+        Ok(resolved)
+    }
+
+    /// Get the record of request `id`, answered on `topic`
+    pub fn get(&self, topic: &Topic<'static>, id: u64) -> Result<Option<JsonRpcRecord>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let record = table
+            .get((topic.as_str(), id))?
+            .map(|v| serde_json::from_slice(v.value()))
+            .transpose()?;
+        Ok(record)
+    }
+
+    /// Every request sent or received on `topic`, oldest first
+    pub fn history(&self, topic: &Topic<'static>) -> Result<Vec<JsonRpcRecord>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut records = Vec::new();
+        for entry in table.iter()? {
+            let (_, value) = entry?;
+            let record: JsonRpcRecord = serde_json::from_slice(value.value())?;
+            if &record.topic == topic {
+                records.push(record);
+            }
+        }
+        records.sort_by_key(|record| record.created_at);
+        Ok(records)
+    }
+
+    /// Remember an inbound relay message.
+    /// Returns `false` if the same message was received before.
+    pub fn receive_message(&self, message: &str) -> Result<bool> {
+        let hash = hex::encode(sha2::Sha256::digest(message.as_bytes()));
+        let write_txn = self.db.begin_write()?;
+        let is_new = {
+            let mut table = write_txn.open_table(MESSAGES_TABLE)?;
+            let is_new = table.get(hash.as_str())?.is_none();
+            if is_new {
+                table.insert(hash.as_str(), Utc::now().timestamp_millis())?;
+            }
+            is_new
+        };
+        write_txn.commit()?;
+        Ok(is_new)
+    }
+
+    /// Forget requests answered, or left unanswered since, and message hashes older than the
+    /// millisecond timestamp `before`
+    pub fn prune(&self, before: i64) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            table.retain(|_, value| {
+                serde_json::from_slice::<JsonRpcRecord>(value)
+                    .map(|record| record.answered_at.unwrap_or(record.created_at) >= before)
+                    .unwrap_or(false)
+            })?;
+            let mut messages = write_txn.open_table(MESSAGES_TABLE)?;
+            messages.retain(|_, received_at| received_at >= before)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redb::backends::InMemoryBackend;
+    use serde_json::json;
+
+    use super::*;
+
+    fn history() -> History {
+        let db = redb::Database::builder().create_with_backend(InMemoryBackend::new()).unwrap();
+        History { db: Arc::new(db) }
+    }
+
+    #[test]
+    fn test_history() {
+        let history = history();
+        let topic: Topic<'static> = "topic".to_string().into();
+        let request = Request::new(1, "wc_sessionPing", json!({}));
+
+        assert!(history.set(&topic, &request).unwrap());
+        assert!(!history.set(&topic, &request).unwrap());
+        assert!(history.history(&topic).unwrap()[0].is_pending());
+
+        // the same id on another topic is another request
+        let other: Topic<'static> = "other".to_string().into();
+        let response = Response::success(1, json!(true));
+        assert!(!history.resolve(&other, &response).unwrap());
+        assert!(history.resolve(&topic, &response).unwrap());
+        assert!(!history.resolve(&topic, &response).unwrap());
+        let records = history.history(&topic).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].response, Some(response));
+        assert!(history.history(&other).unwrap().is_empty());
+        assert!(history.set(&other, &request).unwrap());
+
+        // a type 1 request is answered on another topic
+        let request = Request::new(2, "wc_notifySubscribe", json!({}));
+        assert!(history.set_answered_on(&topic, &other, &request).unwrap());
+        assert!(history.get(&topic, 2).unwrap().is_none());
+        assert!(history.resolve(&other, &Response::success(2, json!({}))).unwrap());
+        assert_eq!(history.history(&topic).unwrap().len(), 2);
+
+        history.prune(Utc::now().timestamp_millis() + 1).unwrap();
+        assert!(history.history(&topic).unwrap().is_empty());
+        assert!(history.history(&other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_receive_message() {
+        let history = history();
+        assert!(history.receive_message("message").unwrap());
+        assert!(!history.receive_message("message").unwrap());
+        assert!(history.receive_message("other").unwrap());
+
+        history.prune(Utc::now().timestamp_millis() + 1).unwrap();
+        assert!(history.receive_message("message").unwrap());
+    }
+}
@@ -0,0 +1,238 @@
+//! Identity keys of blockchain accounts, registered with the keys server and persisted so
+//! higher-level protocols like Notify can sign as the account.
+
+use std::{fmt, future::Future, sync::Arc};
+
+use const_format::concatcp;
+use ed25519_dalek::SigningKey;
+use redb::TableDefinition;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::IdentityKeysError,
+    rpc::{
+        api::core::KeysClient,
+        auth::cacao::EIP191,
+        types::{AccountId, Cacao, CacaoHeader, CacaoSignature, CACAO_TYPE_EIP4361},
+        BoxError,
+    },
+    WalletConnect, STORAGE_PREFIX,
+};
+
+pub type Result<T> = std::result::Result<T, IdentityKeysError>;
+
+pub const IDENTITY: &str = "identityKeys";
+pub const VERSION: u16 = 1;
+pub const NAMESPACE: &str = concatcp!(STORAGE_PREFIX, ":", VERSION, "//", IDENTITY);
+const TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new(NAMESPACE);
+
+/// An identity key and the CACAO binding it to its account
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Identity {
+    pub account: AccountId,
+    #[serde(with = "hex::serde")]
+    identity_key: [u8; 32],
+    pub cacao: Cacao,
+}
+
+impl Identity {
+    /// The key signing as the account
+    pub fn signing_key(&self) -> SigningKey {
+        SigningKey::from_bytes(&self.identity_key)
+    }
+
+    /// The hex-encoded public identity key
+    pub fn public_key(&self) -> String {
+        hex::encode(self.signing_key().verifying_key().as_bytes())
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("account", &self.account)
+            .field("identity_key", &"<redacted>")
+            .field("cacao", &self.cacao)
+            .finish()
+    }
+}
+
+/// Identity keys stored by account
+pub struct IdentityKeys {
+    db: Arc<redb::Database>,
+    keys: KeysClient,
+}
+
+impl IdentityKeys {
+    pub fn new(context: &WalletConnect, keys: KeysClient) -> Result<Self> {
+        Ok(Self { db: context.db.clone(), keys })
+    }
+
+    /// Generate and register an identity key for `account`, on behalf of the app at `domain`.
+    /// `on_sign` signs the SIWE message binding the key to the account with the account's wallet,
+    /// returning the hex-encoded `eip191` signature.
+    /// If `account` already has an identity key, it is returned instead.
+    pub async fn register<F, Fut>(
+        &self,
+        account: &AccountId,
+        domain: &str,
+        statement: &str,
+        on_sign: F,
+    ) -> Result<Identity>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = std::result::Result<String, BoxError>>,
+    {
+        if let Some(identity) = self.get(account)? {
+            return Ok(identity);
+        }
+
+        let identity_key = SigningKey::generate(&mut rand::thread_rng());
+        let payload = self.keys.identity_payload(account, &identity_key, domain, statement);
+        let signature = on_sign(payload.siwe_message()?).await.map_err(IdentityKeysError::Sign)?;
+        let cacao = Cacao {
+            h: CacaoHeader { t: CACAO_TYPE_EIP4361.into() },
+            p: payload,
+            s: CacaoSignature { t: EIP191.into(), s: signature, m: None },
+        };
+        self.keys.register(cacao.clone()).await?;
+
+        let identity =
+            Identity { account: account.clone(), identity_key: identity_key.to_bytes(), cacao };
+        self.set(&identity)?;
+        Ok(identity)
+    }
+
+    /// Unregister the identity key of `account` with the keys server and forget it
+    pub async fn unregister(&self, account: &AccountId) -> Result<()> {
+        let Some(identity) = self.get(account)? else {
+            return Ok(());
+        };
+        self.keys.unregister(account, &identity.signing_key()).await?;
+        self.delete(account)
+    }
+
+    /// The keys server identity keys are registered with
+    pub fn keys(&self) -> &KeysClient {
+        &self.keys
+    }
+
+    /// Resolve the CACAO of any identity key with the keys server
+    pub async fn resolve(&self, public_key: &str) -> Result<Option<Cacao>> {
+        Ok(self.keys.resolve(public_key).await?)
+    }
+
+    /// Get the persisted identity of `account`
+    pub fn get(&self, account: &AccountId) -> Result<Option<Identity>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let identity = table
+            .get(account.to_string().as_str())?
+            .map(|v| serde_json::from_slice(v.value()))
+            .transpose()?;
+        Ok(identity)
+    }
+
+    fn set(&self, identity: &Identity) -> Result<()> {
+        let bytes = serde_json::to_vec(identity)?;
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            table.insert(identity.account.to_string().as_str(), bytes.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn delete(&self, account: &AccountId) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            let _value_guard = table.remove(account.to_string().as_str())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        rpc::{transport::LoopbackRelay, types::ChainId},
+        testing::{self, keys_server},
+    };
+
+    fn account(address: &str) -> AccountId {
+        AccountId::new(ChainId::new("eip155", "1"), address)
+    }
+
+    async fn sign(message: String) -> std::result::Result<String, BoxError> {
+        assert!(message.starts_with("app.example wants you to sign in"));
+        Ok("0x00".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_register_resolve_unregister() {
+        let relay = LoopbackRelay::new();
+        let (keys, registry) = keys_server().await;
+        let identities = IdentityKeys::new(&testing::context(&relay), keys).unwrap();
+        let account = account("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+
+        let identity =
+            identities.register(&account, "app.example", "statement", sign).await.unwrap();
+        assert_eq!(identity.account, account);
+        assert_eq!(identity.cacao.p.account().unwrap(), account);
+        assert_eq!(identity.cacao.s.s, "0x00");
+        assert_eq!(identity.cacao.h.t, CACAO_TYPE_EIP4361);
+        let debug = format!("{identity:?}");
+        let secret = hex::encode(identity.signing_key().to_bytes());
+        assert!(debug.contains("<redacted>") && !debug.contains(&secret), "{debug}");
+        assert_eq!(registry.lock().unwrap().len(), 1);
+        assert_eq!(
+            identities.resolve(&identity.public_key()).await.unwrap(),
+            Some(identity.cacao.clone())
+        );
+
+        // the account keeps its identity key
+        let again = identities
+            .register(&account, "app.example", "statement", |_| async { unreachable!() })
+            .await
+            .unwrap();
+        assert_eq!(again, identity);
+        assert_eq!(registry.lock().unwrap().len(), 1);
+
+        identities.unregister(&account).await.unwrap();
+        assert!(registry.lock().unwrap().is_empty());
+        assert_eq!(identities.get(&account).unwrap(), None);
+        assert_eq!(identities.resolve(&identity.public_key()).await.unwrap(), None);
+        identities.unregister(&account).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_persistence() {
+        let relay = LoopbackRelay::new();
+        let context = testing::context(&relay);
+        let (keys, _registry) = keys_server().await;
+        let (account, other) = (
+            account("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"),
+            account("0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"),
+        );
+
+        let identities = IdentityKeys::new(&context, keys.clone()).unwrap();
+        assert_eq!(identities.get(&account).unwrap(), None);
+        let identity =
+            identities.register(&account, "app.example", "statement", sign).await.unwrap();
+
+        // a new instance on the same database signs with the same key
+        let reopened = IdentityKeys::new(&context, keys).unwrap();
+        let persisted = reopened.get(&account).unwrap().unwrap();
+        assert_eq!(persisted, identity);
+        assert_eq!(reopened.get(&other).unwrap(), None);
+    }
+}
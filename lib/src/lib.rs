@@ -1,18 +1,45 @@
 #![feature(trivial_bounds)]
-use std::{path::Path, sync::Arc};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+};
 
 use chrono::Utc;
-use rpc::Client;
+use rpc::{
+    api::core::VerifyClient,
+    transport::{RelayTransport, WsTransport},
+    types::SubscriptionData,
+    Client,
+};
+use tokio::sync::{broadcast, broadcast::error::RecvError};
 
-use crate::error::WalletConnectError;
+use crate::{
+    error::WalletConnectError, events::GlobalEvents, outbox::Outbox, peer::PeerRpc,
+    relayer::Relayer, supervisor::Supervisor,
+};
 
+pub mod authenticate;
+pub mod core;
 pub mod crypto;
+pub mod emulator;
 pub mod error;
-mod events;
+pub mod events;
 mod expirations;
+pub mod history;
+pub mod identity;
+pub mod notify;
+pub mod outbox;
 pub mod pairing;
+pub mod peer;
+pub mod provider;
 mod relayer;
+pub mod session;
+pub mod supervisor;
+#[cfg(test)]
+mod testing;
 pub mod types;
+pub mod wallet;
+pub mod watch;
 pub use self::types::*;
 
 /// RPC Api Re-Export
@@ -23,6 +50,9 @@ pub mod rpc {
 
 pub const STORAGE_PREFIX: &str = "wc@2:core-rs";
 
+/// Number of inbound relay messages buffered for slow consumers
+pub const MESSAGE_CAPACITY: usize = 256;
+
 /// The global Events loop
 // static EVENTS: LazyLock<events::Events> = LazyLock::new(events::Events::new);
 
@@ -32,15 +62,158 @@ pub type Result<T> = std::result::Result<T, WalletConnectError>;
 #[derive(Clone, Debug)]
 pub struct WalletConnect {
     db: Arc<redb::Database>,
-    pub rpc: Arc<rpc::Client>,
+    /// The websocket transport, unless the context was built on another transport
+    websocket: Option<Arc<WsTransport>>,
+    /// How messages are published to and received from the relay
+    transport: Arc<dyn RelayTransport>,
+    /// Messages delivered by the relay on any subscribed topic
+    messages: broadcast::Sender<SubscriptionData>,
+    /// Verify server used to attest outgoing and verify incoming requests, read by the PeerRpc
+    /// on every message
+    verify: Arc<RwLock<Option<VerifyClient>>>,
+    /// Messages waiting to be published on the relay
+    outbox: Arc<Outbox>,
+    /// Subscriptions of every component built on this context
+    relayer: Arc<Relayer>,
+    /// The PeerRpc shared by every component built on this context, created by the first of them
+    peer: Arc<Mutex<Option<PeerRpc>>>,
+    events: GlobalEvents,
+    /// Background tasks of every component built on this context
+    tasks: Supervisor,
 }
 
 impl WalletConnect {
     pub async fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         let project_id = "684fc89c60a55ca93cd98576c86a73c9";
         let url = "https://github.com/insipx/walletconnect-rs-new";
-        let rpc = Arc::new(Client::new(project_id, url).await?);
-        Ok(Self { db: Arc::new(redb::Database::create(path)?), rpc })
+        let rpc = Client::new(project_id, url).await?;
+        Self::with_client(path, rpc)
+    }
+
+    /// Create a context on a relay connection configured with
+    /// [`Client::builder`](rpc::Client::builder), i.e to reach the relay through a proxy
+    pub fn with_client<P: AsRef<Path>>(path: P, rpc: Client) -> Result<Self> {
+        let db = redb::Database::create(path)?;
+        let tasks = Supervisor::new();
+        let websocket = WsTransport::supervised(rpc, tasks.token(), tasks.tracker());
+        Ok(Self::build(db, Some(websocket.clone()), websocket, tasks))
+    }
+
+    /// Create a context reaching the relay through `transport` instead of a websocket, i.e a
+    /// [`LoopbackTransport`](rpc::transport::LoopbackTransport) in tests
+    pub fn with_transport(db: redb::Database, transport: impl RelayTransport + 'static) -> Self {
+        Self::build(db, None, Arc::new(transport), Supervisor::new())
+    }
+
+    fn build(
+        db: redb::Database,
+        websocket: Option<Arc<WsTransport>>,
+        transport: Arc<dyn RelayTransport>,
+        tasks: Supervisor,
+    ) -> Self {
+        let db = Arc::new(db);
+        let (messages, _) = broadcast::channel(MESSAGE_CAPACITY);
+        let outbox = Arc::new(Outbox::new(db.clone()));
+        outbox.spawn(transport.clone(), &tasks);
+        let relayer =
+            Arc::new(Relayer::new(db.clone(), transport.clone(), outbox.clone(), messages.clone()));
+
+        let mut incoming = transport.incoming();
+        let inbound = messages.clone();
+        tasks.spawn(async move {
+            loop {
+                match incoming.recv().await {
+                    Ok(data) => {
+                        let _ = inbound.send(data);
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        log::warn!("Relay transport lagged by {n} messages")
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let events = GlobalEvents::new();
+        let (mut health, monitor, reconnected) =
+            (transport.health(), events.clone(), outbox.clone());
+        let mut connected = health.borrow_and_update().connected;
+        if connected {
+            events.emit(RelayEvent::Connect);
+        }
+        tasks.spawn(async move {
+            while health.changed().await.is_ok() {
+                let current = health.borrow_and_update().clone();
+                match (connected, current.connected) {
+                    (true, false) => {
+                        let reason = current.error.clone().unwrap_or_default();
+                        monitor.emit(RelayEvent::Disconnect { reason })
+                    }
+                    (false, true) => {
+                        // publish what failed while the connection was down right away, instead
+                        // of waiting for the outbox to retry
+                        reconnected.flush();
+                        monitor.emit(RelayEvent::Connect)
+                    }
+                    _ => {}
+                }
+                connected = current.connected;
+                monitor.emit(RelayEvent::Health(current));
+            }
+        });
+
+        Self {
+            db,
+            websocket,
+            transport,
+            messages,
+            verify: Default::default(),
+            outbox,
+            relayer,
+            peer: Default::default(),
+            events,
+            tasks,
+        }
+    }
+
+    /// The event bus of this context
+    pub fn events(&self) -> &GlobalEvents {
+        &self.events
+    }
+
+    /// The websocket connection in use, unless the context was built on another transport.
+    /// The connection is replaced whenever the transport reconnects or renews its auth token, so
+    /// get it again for every call instead of keeping it.
+    pub fn client(&self) -> Option<Client> {
+        self.websocket.as_ref().map(|websocket| websocket.client())
+    }
+
+    /// The transport messages are published and received through
+    pub fn transport(&self) -> &Arc<dyn RelayTransport> {
+        &self.transport
+    }
+
+    /// The queue of messages waiting to be published on the relay
+    pub fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    /// The relayer every component built on this context subscribes through
+    pub(crate) fn relayer(&self) -> &Arc<Relayer> {
+        &self.relayer
+    }
+
+    /// The supervisor of the background tasks of this context
+    pub fn tasks(&self) -> &Supervisor {
+        &self.tasks
+    }
+
+    /// Verify the origin of requests with `verify`.
+    /// If the client has an origin, attestations of outgoing requests are registered for it.
+    /// Applies to every component built on this context, including those built before.
+    pub fn with_verify(self, verify: VerifyClient) -> Self {
+        *self.verify.write().expect("verify lock poisoned") = Some(verify);
+        self
     }
 }
 
@@ -66,3 +239,35 @@ mod time {
     pub const MONTH: Duration = DAY.saturating_mul(30);
     pub const YEAR: Duration = DAY.saturating_mul(365);
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::{events::EventFilter, rpc::transport::LoopbackRelay, testing};
+
+    #[tokio::test]
+    async fn test_health_events() {
+        let transport = Arc::new(LoopbackRelay::new().connect());
+        let context = WalletConnect::with_transport(testing::db(), transport.clone());
+        let mut events = context.events().subscribe(
+            EventFilter::all()
+                .kind(GlobalEventKind::RelayDisconnect)
+                .kind(GlobalEventKind::RelayHealth),
+        );
+
+        transport.disconnect("gone");
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event.payload,
+            EventPayload::Relay(RelayEvent::Disconnect { reason: "gone".into() })
+        );
+        let event = events.next().await.unwrap().unwrap();
+        let EventPayload::Relay(RelayEvent::Health(health)) = event.payload else {
+            panic!("expected a health event, got {event:?}");
+        };
+        assert!(!health.connected);
+        assert_eq!(health.error.as_deref(), Some("gone"));
+    }
+}
@@ -0,0 +1,834 @@
+//! Wallet side of the Notify API, described [here](https://specs.walletconnect.com/2.0/specs/clients/notify).
+//! The wallet discovers a dapp through its `/.well-known` documents and subscribes by sending a
+//! type 1 envelope to `sha256(dapp key agreement key)`. Notifications arrive on the topic of the
+//! key derived from the subscription keypair, as JWTs signed by the dapp, and are acknowledged
+//! with JWTs signed by the identity key of the account.
+
+use std::{collections::BTreeSet, future::Future, sync::Arc};
+
+use chrono::Utc;
+use const_format::concatcp;
+use ed25519_dalek::VerifyingKey;
+use redb::{ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, broadcast::error::RecvError};
+use url::Url;
+
+use crate::{
+    crypto::{hash_sha256, Crypto},
+    error::NotifyError,
+    identity::{Identity, IdentityKeys},
+    peer::{PeerRequest, PeerRpc},
+    relayer::Relayer,
+    rpc::{
+        auth::{decode_jwt, encode_jwt, encode_key_as_did, encode_public_key_as_did},
+        types::{
+            AccountId, DidDocument, NotifyClaims, NotifyConfig, NotifyDeleteParams, NotifyMessage,
+            NotifyMessageParams, NotifyResponse, NotifySubscribeParams, NotifyUpdateParams, Scope,
+            ScopeSetting, DID_JSON_PATH, DID_PKH_PREFIX, DID_WEB_PREFIX, NOTIFY_CONFIG_PATH,
+            NOTIFY_DELETE, NOTIFY_DELETE_RESPONSE, NOTIFY_MESSAGE, NOTIFY_MESSAGE_RESPONSE,
+            NOTIFY_SUBSCRIPTION, NOTIFY_SUBSCRIPTION_RESPONSE, NOTIFY_UPDATE,
+            NOTIFY_UPDATE_RESPONSE, WC_NOTIFY_DELETE, WC_NOTIFY_MESSAGE, WC_NOTIFY_SUBSCRIBE,
+            WC_NOTIFY_UPDATE,
+        },
+        BoxError, BoxFuture,
+    },
+    time,
+    types::Topic,
+    WalletConnect, MESSAGE_CAPACITY, STORAGE_PREFIX,
+};
+
+pub type Result<T> = std::result::Result<T, NotifyError>;
+
+pub const NOTIFY_SUBSCRIPTIONS: &str = "notifySubscription";
+pub const VERSION: u16 = 1;
+pub const NAMESPACE: &str = concatcp!(STORAGE_PREFIX, ":", VERSION, "//", NOTIFY_SUBSCRIPTIONS);
+const TABLE: TableDefinition<&Topic, &[u8]> = TableDefinition::new(NAMESPACE);
+
+/// How long the JWTs sent to a dapp are valid for
+pub const JWT_TTL: std::time::Duration = time::DAY;
+/// How long a subscription lasts
+pub const SUBSCRIPTION_TTL: std::time::Duration = time::MONTH;
+
+/// Fetches documents over HTTP. Discovery goes through a fetcher so it can be served from fixtures.
+pub trait HttpFetcher: Send + Sync {
+    /// GET `url`, returning the body
+    fn fetch<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, std::result::Result<Vec<u8>, BoxError>>;
+}
+
+impl<F, Fut> HttpFetcher for F
+where
+    F: Fn(Url) -> Fut + Send + Sync,
+    Fut: Future<Output = std::result::Result<Vec<u8>, BoxError>> + Send + 'static,
+{
+    fn fetch<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, std::result::Result<Vec<u8>, BoxError>> {
+        Box::pin(self(url.clone()))
+    }
+}
+
+/// [`HttpFetcher`] over reqwest
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestFetcher(reqwest::Client);
+
+impl HttpFetcher for ReqwestFetcher {
+    fn fetch<'a>(&'a self, url: &'a Url) -> BoxFuture<'a, std::result::Result<Vec<u8>, BoxError>> {
+        Box::pin(async move {
+            let response = self.0.get(url.clone()).send().await?.error_for_status()?;
+            Ok(response.bytes().await?.to_vec())
+        })
+    }
+}
+
+/// A dapp discovered through its `/.well-known` documents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotifyApp {
+    pub domain: String,
+    /// X25519 key the dapp accepts subscriptions with
+    pub key_agreement: [u8; 32],
+    /// Ed25519 key the dapp signs its JWTs with
+    pub authentication: [u8; 32],
+    pub config: NotifyConfig,
+}
+
+/// Discover the dapp at `domain`
+pub async fn discover(fetcher: &dyn HttpFetcher, domain: &str) -> Result<NotifyApp> {
+    let base = Url::parse(&format!("https://{domain}/"))?;
+    let did = fetcher.fetch(&base.join(DID_JSON_PATH)?).await.map_err(NotifyError::Fetch)?;
+    let did: DidDocument = serde_json::from_slice(&did)?;
+    let config =
+        fetcher.fetch(&base.join(NOTIFY_CONFIG_PATH)?).await.map_err(NotifyError::Fetch)?;
+    let config: NotifyConfig = serde_json::from_slice(&config)?;
+
+    Ok(NotifyApp {
+        domain: domain.to_string(),
+        key_agreement: did
+            .key_agreement_key()
+            .ok_or_else(|| NotifyError::MissingKey(did.id.clone()))?,
+        authentication: did
+            .authentication_key()
+            .ok_or_else(|| NotifyError::MissingKey(did.id.clone()))?,
+        config,
+    })
+}
+
+/// A subscription of an account to the notifications of a dapp
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifySubscription {
+    /// Topic notifications are delivered on
+    pub topic: Topic<'static>,
+    pub account: AccountId,
+    pub app_domain: String,
+    #[serde(with = "hex::serde")]
+    pub app_authentication_key: [u8; 32],
+    pub scope: Scope,
+    /// expiry as a millisecond timestamp
+    pub expiry: i64,
+}
+
+impl NotifySubscription {
+    /// Ids of the enabled notification types
+    pub fn enabled(&self) -> impl Iterator<Item = &str> {
+        self.scope.iter().filter(|(_, s)| s.enabled).map(|(id, _)| id.as_str())
+    }
+}
+
+/// A notification received on a subscription
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub topic: Topic<'static>,
+    pub account: AccountId,
+    pub app_domain: String,
+    pub message: NotifyMessage,
+}
+
+/// Notify subscriptions stored by their topic
+pub struct NotifySubscriptions {
+    db: Arc<redb::Database>,
+}
+
+impl NotifySubscriptions {
+    pub fn new(context: &WalletConnect) -> Result<Self> {
+        Ok(Self { db: context.db.clone() })
+    }
+
+    /// Persist a subscription to the database
+    pub fn set(&self, subscription: &NotifySubscription) -> Result<()> {
+        let bytes = serde_json::to_vec(subscription)?;
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            table.insert(&subscription.topic, bytes.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Get a persisted subscription
+    pub fn get(&self, topic: &Topic<'static>) -> Result<Option<NotifySubscription>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let subscription =
+            table.get(topic)?.map(|v| serde_json::from_slice(v.value())).transpose()?;
+        Ok(subscription)
+    }
+
+    /// All persisted subscriptions
+    pub fn all(&self) -> Result<Vec<NotifySubscription>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut subscriptions = Vec::new();
+        for entry in table.iter()? {
+            let (_, value) = entry?;
+            subscriptions.push(serde_json::from_slice(value.value())?);
+        }
+        Ok(subscriptions)
+    }
+
+    /// Delete a persisted subscription
+    pub fn delete(&self, topic: &Topic<'static>) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            let _value_guard = table.remove(topic)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct NotifyClient {
+    crypto: Arc<Crypto>,
+    peer: PeerRpc,
+    /// Relayer holding the subscriptions to notify topics
+    listener: Arc<Relayer>,
+    identities: Arc<IdentityKeys>,
+    subscriptions: Arc<NotifySubscriptions>,
+    fetcher: Arc<dyn HttpFetcher>,
+    notifications: broadcast::Sender<Notification>,
+}
+
+impl NotifyClient {
+    /// Create a Notify client signing with the identity keys in `identities`, and resubscribe to
+    /// persisted subscriptions
+    pub async fn new(
+        context: &WalletConnect,
+        identities: IdentityKeys,
+        fetcher: impl HttpFetcher + 'static,
+    ) -> Result<Self> {
+        let subscriptions = NotifySubscriptions::new(context)?;
+        let (notifications, _) = broadcast::channel(MESSAGE_CAPACITY);
+        let client = Self {
+            crypto: Arc::new(Crypto::new(context)?),
+            peer: PeerRpc::new(context)?,
+            listener: context.relayer().clone(),
+            identities: Arc::new(identities),
+            subscriptions: Arc::new(subscriptions),
+            fetcher: Arc::new(fetcher),
+            notifications,
+        };
+
+        let mut requests = client.peer.requests();
+        let this = client.clone();
+        context.tasks().spawn(async move {
+            loop {
+                match requests.recv().await {
+                    Ok(request) => {
+                        if let Err(e) = this.handle_request(request).await {
+                            log::warn!("Failed to handle notify request: {e}");
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => log::warn!("NotifyClient lagged by {n} requests"),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        // subscribe only once the handler listens, so queued notifications are not missed
+        let topics: Vec<_> = client.subscriptions.all()?.into_iter().map(|s| s.topic).collect();
+        client.listener.start(&topics).await?;
+
+        Ok(client)
+    }
+
+    /// Subscribe to notifications of enabled types
+    pub fn notifications(&self) -> broadcast::Receiver<Notification> {
+        self.notifications.subscribe()
+    }
+
+    /// All subscriptions
+    pub fn subscriptions(&self) -> Result<Vec<NotifySubscription>> {
+        self.subscriptions.all()
+    }
+
+    /// Subscribe `account` to every type of notification of the dapp at `domain`.
+    /// `account` must have a registered identity key.
+    pub async fn subscribe(&self, account: &AccountId, domain: &str) -> Result<NotifySubscription> {
+        let identity = self.identity(account)?;
+        let app = discover(self.fetcher.as_ref(), domain).await?;
+
+        let scope: Scope = app
+            .config
+            .notification_types
+            .iter()
+            .map(|ty| {
+                let setting = ScopeSetting {
+                    name: ty.name.clone(),
+                    description: ty.description.clone(),
+                    enabled: true,
+                };
+                (ty.id.clone(), setting)
+            })
+            .collect();
+        let scp = scope.keys().cloned().collect::<Vec<_>>().join(" ");
+
+        let self_public = self.crypto.generate_keypair()?;
+        let subscribe_topic: Topic<'static> = hex::encode(hash_sha256(&app.key_agreement)).into();
+        let topic = self.crypto.generate_shared_key(&self_public, &app.key_agreement)?;
+        self.listener.subscribe(&topic).await?;
+
+        let claims = self.claims(&identity, &app.authentication, NOTIFY_SUBSCRIPTION, domain)?;
+        let params = NotifySubscribeParams {
+            subscription_auth: encode_jwt(
+                &identity.signing_key(),
+                &NotifyClaims {
+                    ksu: Some(self.identities.keys().url().as_str().trim_end_matches('/').into()),
+                    scp: Some(scp),
+                    ..claims
+                },
+            )?,
+        };
+        let result = self
+            .peer
+            .request_type1(
+                &subscribe_topic,
+                &topic,
+                &self_public,
+                &app.key_agreement,
+                WC_NOTIFY_SUBSCRIBE,
+                params,
+            )
+            .await?;
+        self.verify_response(result, &app.authentication, NOTIFY_SUBSCRIPTION_RESPONSE)?;
+
+        let subscription = NotifySubscription {
+            topic,
+            account: account.clone(),
+            app_domain: domain.to_string(),
+            app_authentication_key: app.authentication,
+            scope,
+            expiry: (Utc::now() + SUBSCRIPTION_TTL).timestamp_millis(),
+        };
+        self.subscriptions.set(&subscription)?;
+        Ok(subscription)
+    }
+
+    /// Receive only the notification types in `enabled` on the subscription at `topic`
+    pub async fn update(
+        &self,
+        topic: &Topic<'static>,
+        enabled: impl IntoIterator<Item = String>,
+    ) -> Result<NotifySubscription> {
+        let mut subscription = self.subscription(topic)?;
+        let identity = self.identity(&subscription.account)?;
+
+        let enabled: BTreeSet<String> = enabled.into_iter().collect();
+        for (id, setting) in subscription.scope.iter_mut() {
+            setting.enabled = enabled.contains(id);
+        }
+        let scp = subscription.enabled().collect::<Vec<_>>().join(" ");
+
+        let claims = self.claims(
+            &identity,
+            &subscription.app_authentication_key,
+            NOTIFY_UPDATE,
+            &subscription.app_domain,
+        )?;
+        let params = NotifyUpdateParams {
+            update_auth: encode_jwt(
+                &identity.signing_key(),
+                &NotifyClaims { scp: Some(scp), ..claims },
+            )?,
+        };
+        let result = self.peer.request(topic, WC_NOTIFY_UPDATE, params).await?;
+        self.verify_response(result, &subscription.app_authentication_key, NOTIFY_UPDATE_RESPONSE)?;
+
+        self.subscriptions.set(&subscription)?;
+        Ok(subscription)
+    }
+
+    /// Unsubscribe from the dapp at `topic`
+    pub async fn delete(&self, topic: &Topic<'static>) -> Result<()> {
+        let subscription = self.subscription(topic)?;
+        let identity = self.identity(&subscription.account)?;
+
+        let claims = self.claims(
+            &identity,
+            &subscription.app_authentication_key,
+            NOTIFY_DELETE,
+            &subscription.app_domain,
+        )?;
+        let params =
+            NotifyDeleteParams { delete_auth: encode_jwt(&identity.signing_key(), &claims)? };
+        let result = self.peer.request(topic, WC_NOTIFY_DELETE, params).await?;
+        self.verify_response(result, &subscription.app_authentication_key, NOTIFY_DELETE_RESPONSE)?;
+
+        self.subscriptions.delete(topic)?;
+        self.listener.unsubscribe(topic).await?;
+        self.crypto.delete_symkey(topic.clone())?;
+        Ok(())
+    }
+
+    async fn handle_request(&self, PeerRequest { topic, request, .. }: PeerRequest) -> Result<()> {
+        if request.method != WC_NOTIFY_MESSAGE {
+            return Ok(());
+        }
+        let Some(subscription) = self.subscriptions.get(&topic)? else {
+            return Ok(());
+        };
+        let identity = self.identity(&subscription.account)?;
+
+        let params: NotifyMessageParams = serde_json::from_value(request.params)?;
+        let key = VerifyingKey::from_bytes(&subscription.app_authentication_key)?;
+        let claims: NotifyClaims = decode_jwt(&params.message_auth, &key)?;
+        if claims.act != NOTIFY_MESSAGE {
+            return Err(NotifyError::UnexpectedAct(claims.act));
+        }
+        if claims.exp < Utc::now().timestamp() {
+            return Err(NotifyError::InvalidClaim("exp"));
+        }
+        if claims.sub != format!("{DID_PKH_PREFIX}{}", subscription.account) {
+            return Err(NotifyError::InvalidClaim("sub"));
+        }
+        if claims.app != format!("{DID_WEB_PREFIX}{}", subscription.app_domain) {
+            return Err(NotifyError::InvalidClaim("app"));
+        }
+        let message = claims.msg.ok_or(NotifyError::MissingMessage)?;
+
+        let receipt = self.claims(
+            &identity,
+            &subscription.app_authentication_key,
+            NOTIFY_MESSAGE_RESPONSE,
+            &subscription.app_domain,
+        )?;
+        let response =
+            NotifyResponse { response_auth: encode_jwt(&identity.signing_key(), &receipt)? };
+        self.peer.respond(&topic, request.id, response).await?;
+
+        if subscription.enabled().any(|ty| ty == message.ty) {
+            let _ = self.notifications.send(Notification {
+                topic,
+                account: subscription.account,
+                app_domain: subscription.app_domain,
+                message,
+            });
+        }
+        Ok(())
+    }
+
+    /// Claims of a JWT from the identity key of `identity` to the dapp
+    fn claims(
+        &self,
+        identity: &Identity,
+        app_authentication_key: &[u8; 32],
+        act: &str,
+        domain: &str,
+    ) -> Result<NotifyClaims> {
+        let iat = Utc::now();
+        Ok(NotifyClaims {
+            iat: iat.timestamp(),
+            exp: (iat + JWT_TTL).timestamp(),
+            iss: encode_key_as_did(&identity.signing_key()),
+            aud: encode_public_key_as_did(&VerifyingKey::from_bytes(app_authentication_key)?),
+            act: act.to_string(),
+            sub: format!("{DID_PKH_PREFIX}{}", identity.account),
+            app: format!("{DID_WEB_PREFIX}{domain}"),
+            ksu: None,
+            scp: None,
+            msg: None,
+        })
+    }
+
+    /// Check the dapp signed the response to a request
+    fn verify_response(
+        &self,
+        result: serde_json::Value,
+        app_authentication_key: &[u8; 32],
+        act: &str,
+    ) -> Result<NotifyClaims> {
+        let response: NotifyResponse = serde_json::from_value(result)?;
+        let key = VerifyingKey::from_bytes(app_authentication_key)?;
+        let claims: NotifyClaims = decode_jwt(&response.response_auth, &key)?;
+        if claims.act != act {
+            return Err(NotifyError::UnexpectedAct(claims.act));
+        }
+        Ok(claims)
+    }
+
+    fn identity(&self, account: &AccountId) -> Result<Identity> {
+        self.identities.get(account)?.ok_or_else(|| NotifyError::NotRegistered(account.clone()))
+    }
+
+    fn subscription(&self, topic: &Topic<'static>) -> Result<NotifySubscription> {
+        self.subscriptions
+            .get(topic)?
+            .ok_or_else(|| NotifyError::UnknownSubscription(topic.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use ed25519_dalek::SigningKey;
+
+    use super::*;
+    use crate::{
+        peer,
+        rpc::{
+            transport::LoopbackRelay,
+            types::{ChainId, NotificationType},
+        },
+        testing::{context, keys_server},
+    };
+
+    const DOMAIN: &str = "app.example";
+
+    fn account() -> AccountId {
+        AccountId::new(ChainId::new("eip155", "1"), "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")
+    }
+
+    fn config() -> NotifyConfig {
+        let ty = |id: &str| NotificationType {
+            id: id.into(),
+            name: id.into(),
+            description: String::new(),
+        };
+        NotifyConfig {
+            name: "App".into(),
+            description: "An app".into(),
+            icons: vec![],
+            notification_types: vec![ty("alerts"), ty("news")],
+        }
+    }
+
+    /// A fetcher serving the `/.well-known` documents of the dapp at [`DOMAIN`]
+    fn fetcher(key_agreement: [u8; 32], authentication: [u8; 32]) -> impl HttpFetcher {
+        let base = format!("https://{DOMAIN}/");
+        let fixtures: HashMap<String, Vec<u8>> = [
+            (
+                format!("{base}{DID_JSON_PATH}"),
+                serde_json::to_vec(&DidDocument::notify(DOMAIN, &key_agreement, &authentication))
+                    .unwrap(),
+            ),
+            (format!("{base}{NOTIFY_CONFIG_PATH}"), serde_json::to_vec(&config()).unwrap()),
+        ]
+        .into_iter()
+        .collect();
+        let fixtures = Arc::new(fixtures);
+        move |url: Url| {
+            let fixtures = fixtures.clone();
+            async move {
+                fixtures
+                    .get(url.as_str())
+                    .cloned()
+                    .ok_or_else(|| BoxError::from(format!("404 {url}")))
+            }
+        }
+    }
+
+    /// The notify server of a dapp, holding a subscription of [`account`] with the wallet.
+    /// Unlike [`testing::Dapp`](crate::testing::Dapp) it does not pair, the subscription is
+    /// shared out of band.
+    struct NotifyDapp {
+        peer: PeerRpc,
+        authentication: SigningKey,
+        subscription: NotifySubscription,
+    }
+
+    impl NotifyDapp {
+        /// Share a subscription key with `wallet` and persist the subscription on its side, as
+        /// `wc_notifySubscribe` would
+        async fn subscribed(relay: &LoopbackRelay, wallet: &WalletConnect) -> Self {
+            let context = context(relay);
+            let authentication = SigningKey::generate(&mut rand::thread_rng());
+            let sym_key = [3u8; 32];
+            let topic = Crypto::new(&context).unwrap().set_symkey(sym_key, None).unwrap();
+            Crypto::new(wallet).unwrap().set_symkey(sym_key, Some(&topic)).unwrap();
+            context.relayer().subscribe(&topic).await.unwrap();
+
+            let scope = config()
+                .notification_types
+                .into_iter()
+                .map(|ty| {
+                    let setting =
+                        ScopeSetting { name: ty.name, description: ty.description, enabled: true };
+                    (ty.id, setting)
+                })
+                .collect();
+            let subscription = NotifySubscription {
+                topic,
+                account: account(),
+                app_domain: DOMAIN.into(),
+                app_authentication_key: authentication.verifying_key().to_bytes(),
+                scope,
+                expiry: (Utc::now() + SUBSCRIPTION_TTL).timestamp_millis(),
+            };
+            NotifySubscriptions::new(wallet).unwrap().set(&subscription).unwrap();
+
+            Self { peer: PeerRpc::new(&context).unwrap(), authentication, subscription }
+        }
+
+        fn claims(&self, act: &str) -> NotifyClaims {
+            let iat = Utc::now();
+            NotifyClaims {
+                iat: iat.timestamp(),
+                exp: (iat + JWT_TTL).timestamp(),
+                iss: encode_key_as_did(&self.authentication),
+                aud: String::new(),
+                act: act.into(),
+                sub: format!("{DID_PKH_PREFIX}{}", account()),
+                app: format!("{DID_WEB_PREFIX}{DOMAIN}"),
+                ksu: None,
+                scp: None,
+                msg: None,
+            }
+        }
+
+        fn response(&self, act: &str) -> NotifyResponse {
+            NotifyResponse {
+                response_auth: encode_jwt(&self.authentication, &self.claims(act)).unwrap(),
+            }
+        }
+
+        /// Send a notification of type `ty`, returning the receipt of the wallet
+        async fn notify(&self, ty: &str) -> NotifyResponse {
+            let result = self.send(self.message(ty)).await.unwrap();
+            serde_json::from_value(result).unwrap()
+        }
+
+        /// Claims of a notification of type `ty`
+        fn message(&self, ty: &str) -> NotifyClaims {
+            let message = NotifyMessage {
+                id: format!("{ty}-1"),
+                ty: ty.into(),
+                title: "Title".into(),
+                body: "Body".into(),
+                icon: None,
+                url: None,
+                sent_at: None,
+            };
+            NotifyClaims { msg: Some(message), ..self.claims(NOTIFY_MESSAGE) }
+        }
+
+        /// Send a notification signed with `claims`, waiting a second for the receipt
+        async fn send(&self, claims: NotifyClaims) -> peer::Result<serde_json::Value> {
+            let params = NotifyMessageParams {
+                message_auth: encode_jwt(&self.authentication, &claims).unwrap(),
+            };
+            self.peer
+                .request_with_timeout(
+                    &self.subscription.topic,
+                    WC_NOTIFY_MESSAGE,
+                    params,
+                    Duration::from_secs(1),
+                )
+                .await
+        }
+    }
+
+    /// A Notify client of `wallet`, with an identity key registered for [`account`]
+    async fn client(wallet: &WalletConnect, dapp: &NotifyDapp) -> (NotifyClient, Identity) {
+        let (keys, _) = keys_server().await;
+        let identities = IdentityKeys::new(wallet, keys).unwrap();
+        let identity = identities
+            .register(&account(), DOMAIN, "statement", |_| async { Ok("0x00".to_string()) })
+            .await
+            .unwrap();
+        let authentication = dapp.authentication.verifying_key().to_bytes();
+        let client = NotifyClient::new(wallet, identities, fetcher([1u8; 32], authentication))
+            .await
+            .unwrap();
+        (client, identity)
+    }
+
+    #[tokio::test]
+    async fn test_discover() {
+        let fetcher = fetcher([1u8; 32], [2u8; 32]);
+
+        let app = discover(&fetcher, DOMAIN).await.unwrap();
+        assert_eq!(app.key_agreement, [1u8; 32]);
+        assert_eq!(app.authentication, [2u8; 32]);
+        assert_eq!(app.config.notification_types[0].id, "alerts");
+
+        assert!(matches!(discover(&fetcher, "other.example").await, Err(NotifyError::Fetch(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let relay = LoopbackRelay::new();
+        let wallet = context(&relay);
+        let dapp = NotifyDapp::subscribed(&relay, &wallet).await;
+        let topic = dapp.subscription.topic.clone();
+        assert!(!wallet.relayer().is_subscribed(&topic));
+
+        let (client, _) = client(&wallet, &dapp).await;
+        assert_eq!(client.subscriptions().unwrap(), vec![dapp.subscription.clone()]);
+        assert!(wallet.relayer().is_subscribed(&topic));
+    }
+
+    #[tokio::test]
+    async fn test_message() {
+        let relay = LoopbackRelay::new();
+        let wallet = context(&relay);
+        let dapp = NotifyDapp::subscribed(&relay, &wallet).await;
+        let (client, identity) = client(&wallet, &dapp).await;
+        let mut notifications = client.notifications();
+
+        let response = dapp.notify("alerts").await;
+        let receipt: NotifyClaims =
+            decode_jwt(&response.response_auth, &identity.signing_key().verifying_key()).unwrap();
+        assert_eq!(receipt.act, NOTIFY_MESSAGE_RESPONSE);
+        assert_eq!(receipt.sub, format!("{DID_PKH_PREFIX}{}", account()));
+        let expected = encode_public_key_as_did(&dapp.authentication.verifying_key());
+        assert_eq!(receipt.aud, expected);
+
+        let notification = notifications.recv().await.unwrap();
+        assert_eq!(notification.topic, dapp.subscription.topic);
+        assert_eq!(notification.app_domain, DOMAIN);
+        assert_eq!(
+            (notification.message.ty.as_str(), notification.message.title.as_str()),
+            ("alerts", "Title")
+        );
+
+        // notifications of disabled types are acknowledged, not delivered
+        client
+            .subscriptions
+            .set(&NotifySubscription {
+                scope: dapp
+                    .subscription
+                    .scope
+                    .clone()
+                    .into_iter()
+                    .map(|(id, mut setting)| {
+                        setting.enabled = id != "news";
+                        (id, setting)
+                    })
+                    .collect(),
+                ..dapp.subscription.clone()
+            })
+            .unwrap();
+        dapp.notify("news").await;
+        assert!(notifications.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_message() {
+        let relay = LoopbackRelay::new();
+        let wallet = context(&relay);
+        let dapp = NotifyDapp::subscribed(&relay, &wallet).await;
+        let (client, _) = client(&wallet, &dapp).await;
+        let mut notifications = client.notifications();
+
+        let message = dapp.message("alerts");
+        let other = AccountId::new(
+            ChainId::new("eip155", "1"),
+            "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB",
+        );
+        let invalid = [
+            NotifyClaims { exp: Utc::now().timestamp() - 1, ..message.clone() },
+            NotifyClaims { sub: format!("{DID_PKH_PREFIX}{other}"), ..message.clone() },
+            NotifyClaims { app: format!("{DID_WEB_PREFIX}other.example"), ..message.clone() },
+        ];
+        for claims in invalid {
+            // the wallet neither acknowledges nor delivers the notification
+            assert!(dapp.send(claims).await.is_err());
+            assert!(notifications.try_recv().is_err());
+        }
+
+        dapp.send(message).await.unwrap();
+        assert_eq!(notifications.recv().await.unwrap().message.ty, "alerts");
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_response() {
+        let relay = LoopbackRelay::new();
+        let wallet = context(&relay);
+        let dapp = NotifyDapp::subscribed(&relay, &wallet).await;
+        let (client, _) = client(&wallet, &dapp).await;
+        let topic = dapp.subscription.topic.clone();
+
+        // a response acting on the request itself, rather than answering it, is rejected
+        let mut requests = dapp.peer.requests();
+        let (peer, response) = (dapp.peer.clone(), dapp.response(NOTIFY_UPDATE));
+        tokio::spawn(async move {
+            let PeerRequest { topic, request, .. } = requests.recv().await.unwrap();
+            peer.respond(&topic, request.id, response).await.unwrap();
+        });
+        let error = client.update(&topic, ["alerts".to_string()]).await.unwrap_err();
+        assert!(matches!(error, NotifyError::UnexpectedAct(act) if act == NOTIFY_UPDATE));
+        assert_eq!(client.subscriptions().unwrap(), vec![dapp.subscription.clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete() {
+        let relay = LoopbackRelay::new();
+        let wallet = context(&relay);
+        let dapp = NotifyDapp::subscribed(&relay, &wallet).await;
+        let (client, identity) = client(&wallet, &dapp).await;
+        let topic = dapp.subscription.topic.clone();
+
+        let mut requests = dapp.peer.requests();
+        let (peer, responses) = (
+            dapp.peer.clone(),
+            [dapp.response(NOTIFY_UPDATE_RESPONSE), dapp.response(NOTIFY_DELETE_RESPONSE)],
+        );
+        let identity_key = identity.signing_key().verifying_key();
+        let answered = tokio::spawn(async move {
+            let mut claims = vec![];
+            for response in responses {
+                let PeerRequest { topic, request, .. } = requests.recv().await.unwrap();
+                let auth = match request.method.as_str() {
+                    WC_NOTIFY_UPDATE => {
+                        serde_json::from_value::<NotifyUpdateParams>(request.params)
+                            .unwrap()
+                            .update_auth
+                    }
+                    WC_NOTIFY_DELETE => {
+                        serde_json::from_value::<NotifyDeleteParams>(request.params)
+                            .unwrap()
+                            .delete_auth
+                    }
+                    method => panic!("unexpected {method}"),
+                };
+                claims.push(decode_jwt::<NotifyClaims>(&auth, &identity_key).unwrap());
+                peer.respond(&topic, request.id, response).await.unwrap();
+            }
+            claims
+        });
+
+        let updated = client.update(&topic, ["alerts".to_string()]).await.unwrap();
+        assert_eq!(updated.enabled().collect::<Vec<_>>(), vec!["alerts"]);
+        assert_eq!(client.subscriptions().unwrap(), vec![updated]);
+
+        client.delete(&topic).await.unwrap();
+        assert!(client.subscriptions().unwrap().is_empty());
+        assert!(!wallet.relayer().is_subscribed(&topic));
+
+        let claims = answered.await.unwrap();
+        assert_eq!(
+            (claims[0].act.as_str(), claims[0].scp.as_deref()),
+            (NOTIFY_UPDATE, Some("alerts"))
+        );
+        assert_eq!(claims[1].act, NOTIFY_DELETE);
+    }
+}
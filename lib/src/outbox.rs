@@ -0,0 +1,486 @@
+//! Durable queue of outgoing relay messages. Messages are persisted before they are published, so
+//! they survive a dropped socket or a restart. Messages on a topic are sent in the order they were
+//! enqueued, and a topic which fails to publish does not hold back the others.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use const_format::concatcp;
+use redb::{ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{broadcast, Notify},
+    task::JoinHandle,
+};
+
+use crate::{
+    error::OutboxError,
+    rpc::{error::TransportError, transport::RelayTransport, types::Policy},
+    supervisor::Supervisor,
+    types::Topic,
+    MESSAGE_CAPACITY, STORAGE_PREFIX,
+};
+
+pub type Result<T> = std::result::Result<T, OutboxError>;
+
+pub const OUTBOX: &str = "outbox";
+pub const VERSION: u16 = 1;
+pub const NAMESPACE: &str = concatcp!(STORAGE_PREFIX, ":", VERSION, "//", OUTBOX);
+/// sequence number -> message
+const TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new(NAMESPACE);
+pub const SEQUENCE_NAMESPACE: &str = concatcp!(NAMESPACE, "Sequence");
+/// [`NEXT_ID`] -> sequence number of the next message, so numbers are never reused
+const SEQUENCE: TableDefinition<&str, u64> = TableDefinition::new(SEQUENCE_NAMESPACE);
+const NEXT_ID: &str = "next";
+
+/// How many messages are read, and removed once handled, at a time while flushing
+const BATCH_SIZE: usize = 64;
+
+/// Delay before the first retry after a transport error
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound of the delay between retries
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A message waiting to be published
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxMessage {
+    /// sequence number, in enqueue order
+    pub id: u64,
+    pub topic: Topic<'static>,
+    pub message: String,
+    pub policy: Policy,
+    /// millisecond timestamp after which the message is dropped, from the TTL of its policy
+    pub expiry: i64,
+}
+
+/// What happened to an enqueued message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutboxEvent {
+    Sent {
+        id: u64,
+        topic: Topic<'static>,
+    },
+    /// The TTL of the message passed before it could be sent
+    Expired {
+        id: u64,
+        topic: Topic<'static>,
+    },
+    /// The relay refused the message
+    Rejected {
+        id: u64,
+        topic: Topic<'static>,
+        reason: String,
+    },
+}
+
+impl OutboxEvent {
+    /// Sequence number of the message
+    pub fn id(&self) -> u64 {
+        match self {
+            OutboxEvent::Sent { id, .. }
+            | OutboxEvent::Expired { id, .. }
+            | OutboxEvent::Rejected { id, .. } => *id,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Outbox {
+    db: Arc<redb::Database>,
+    events: broadcast::Sender<OutboxEvent>,
+    notify: Notify,
+}
+
+impl Outbox {
+    pub(crate) fn new(db: Arc<redb::Database>) -> Self {
+        let (events, _) = broadcast::channel(MESSAGE_CAPACITY);
+        Self { db, events, notify: Notify::new() }
+    }
+
+    /// Spawn the task publishing enqueued messages through `transport`. Messages left over from a
+    /// previous run are sent right away.
+    pub(crate) fn spawn(
+        self: &Arc<Self>,
+        transport: Arc<dyn RelayTransport>,
+        tasks: &Supervisor,
+    ) -> JoinHandle<()> {
+        let this = self.clone();
+        tasks.spawn(async move {
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                match this.send_pending(transport.as_ref()).await {
+                    Ok(()) => {
+                        backoff = INITIAL_BACKOFF;
+                        this.notify.notified().await;
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to flush outbox, retrying in {backoff:?}: {e}");
+                        tokio::select! {
+                            _ = tokio::time::sleep(backoff) => {}
+                            _ = this.notify.notified() => {}
+                        }
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Persist a message to be published on `topic`, returning its sequence number
+    pub fn enqueue(&self, topic: &Topic<'static>, message: String, policy: Policy) -> Result<u64> {
+        let expiry = Utc::now().timestamp_millis() + (policy.ttl as i64) * 1000;
+        let write_txn = self.db.begin_write()?;
+        let id = {
+            let mut table = write_txn.open_table(TABLE)?;
+            let mut sequence = write_txn.open_table(SEQUENCE)?;
+            // outboxes persisted before the sequence table continue after their last message
+            let last = table.last()?.map_or(0, |(id, _)| id.value() + 1);
+            let id = sequence.get(NEXT_ID)?.map_or(0, |id| id.value()).max(last);
+            sequence.insert(NEXT_ID, id + 1)?;
+            let message = OutboxMessage { id, topic: topic.clone(), message, policy, expiry };
+            table.insert(id, serde_json::to_vec(&message)?.as_slice())?;
+            id
+        };
+        write_txn.commit()?;
+        self.flush();
+        Ok(id)
+    }
+
+    /// Publish every pending message now, i.e after reconnecting
+    pub fn flush(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Wait up to `timeout` for every pending message to be published or dropped, i.e before
+    /// shutting down.
+    /// Returns the number of messages still pending.
+    pub async fn drain(&self, timeout: Duration) -> Result<usize> {
+        let mut events = self.events();
+        self.flush();
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let pending = self.pending()?.len();
+            if pending == 0 {
+                return Ok(0);
+            }
+            if tokio::time::timeout_at(deadline, events.recv()).await.is_err() {
+                return Ok(pending);
+            }
+        }
+    }
+
+    /// Listen to what happens to enqueued messages
+    pub fn events(&self) -> broadcast::Receiver<OutboxEvent> {
+        self.events.subscribe()
+    }
+
+    /// Messages waiting to be published, in order
+    pub fn pending(&self) -> Result<Vec<OutboxMessage>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut messages = Vec::new();
+        for entry in table.iter()? {
+            let (_, value) = entry?;
+            messages.push(serde_json::from_slice(value.value())?);
+        }
+        Ok(messages)
+    }
+
+    /// Publish pending messages in order, dropping those whose TTL has passed. A topic is skipped
+    /// after its first transport error, so its remaining messages keep their order, and the error
+    /// is returned once every other topic is sent.
+    /// Handled messages are removed a batch at a time, so a crash mid-batch may publish some of
+    /// them again, which peers drop as duplicates.
+    async fn send_pending(&self, transport: &dyn RelayTransport) -> Result<()> {
+        let mut failed = HashSet::new();
+        let mut error = None;
+        let mut after = None;
+        loop {
+            let batch = self.batch(after)?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            after = Some(last.id);
+
+            let now = Utc::now().timestamp_millis();
+            let mut handled = Vec::with_capacity(batch.len());
+            for OutboxMessage { id, topic, message, policy, expiry } in batch {
+                if expiry <= now {
+                    handled.push(OutboxEvent::Expired { id, topic });
+                    continue;
+                }
+                if failed.contains(&topic) {
+                    continue;
+                }
+                match transport.publish(&topic.to_string(), message, policy).await {
+                    Ok(()) => handled.push(OutboxEvent::Sent { id, topic }),
+                    Err(TransportError::Rejected(reason)) => {
+                        log::warn!("Relay rejected message {id} on topic {topic}: {reason}");
+                        handled.push(OutboxEvent::Rejected { id, topic, reason });
+                    }
+                    Err(e) => {
+                        failed.insert(topic);
+                        error = Some(e);
+                    }
+                }
+            }
+
+            self.remove(handled.iter().map(OutboxEvent::id))?;
+            for event in handled {
+                let _ = self.events.send(event);
+            }
+        }
+        error.map_or(Ok(()), |e| Err(e.into()))
+    }
+
+    /// Up to [`BATCH_SIZE`] pending messages enqueued after the message `after`, in order
+    fn batch(&self, after: Option<u64>) -> Result<Vec<OutboxMessage>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut messages = Vec::new();
+        for entry in table.range(after.map_or(0, |id| id + 1)..)?.take(BATCH_SIZE) {
+            let (_, value) = entry?;
+            messages.push(serde_json::from_slice(value.value())?);
+        }
+        Ok(messages)
+    }
+
+    /// Remove the messages `ids` in one transaction
+    fn remove(&self, ids: impl IntoIterator<Item = u64>) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            for id in ids {
+                let _value_guard = table.remove(id)?;
+            }
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use redb::backends::InMemoryBackend;
+    use tokio::sync::watch;
+
+    use super::*;
+    use crate::{
+        rpc::{
+            transport::{ConnectionHealth, LoopbackRelay, LoopbackTransport},
+            types::{FetchMessagesResponse, SubscriptionData},
+            BoxFuture,
+        },
+        testing, WalletConnect,
+    };
+
+    fn database() -> Arc<redb::Database> {
+        Arc::new(redb::Database::builder().create_with_backend(InMemoryBackend::new()).unwrap())
+    }
+
+    fn outbox() -> Outbox {
+        Outbox::new(database())
+    }
+
+    /// A loopback connection failing to publish on some topics, or on all of them while down
+    #[derive(Debug)]
+    struct Flaky {
+        inner: LoopbackTransport,
+        down: AtomicBool,
+        failing: Mutex<HashSet<String>>,
+        attempts: AtomicUsize,
+        health: watch::Sender<ConnectionHealth>,
+    }
+
+    impl Flaky {
+        fn new(relay: &LoopbackRelay) -> Arc<Self> {
+            Arc::new(Self {
+                inner: relay.connect(),
+                down: AtomicBool::new(false),
+                failing: Default::default(),
+                attempts: AtomicUsize::new(0),
+                health: watch::Sender::new(ConnectionHealth::connected()),
+            })
+        }
+
+        fn set_down(&self, down: bool) {
+            self.down.store(down, Ordering::SeqCst);
+            self.health.send_modify(|health| health.connected = !down);
+        }
+
+        fn set_failing(&self, topic: &Topic<'static>, failing: bool) {
+            let mut topics = self.failing.lock().unwrap();
+            if failing {
+                topics.insert(topic.to_string());
+            } else {
+                topics.remove(&topic.to_string());
+            }
+        }
+    }
+
+    impl RelayTransport for Flaky {
+        fn publish<'a>(
+            &'a self,
+            topic: &'a str,
+            message: String,
+            policy: Policy,
+        ) -> BoxFuture<'a, std::result::Result<(), TransportError>> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) || self.failing.lock().unwrap().contains(topic) {
+                return Box::pin(async { Err(TransportError::Closed) });
+            }
+            self.inner.publish(topic, message, policy)
+        }
+
+        fn subscribe<'a>(
+            &'a self,
+            topic: &'a str,
+        ) -> BoxFuture<'a, std::result::Result<String, TransportError>> {
+            self.inner.subscribe(topic)
+        }
+
+        fn unsubscribe<'a>(
+            &'a self,
+            topic: &'a str,
+            id: &'a str,
+        ) -> BoxFuture<'a, std::result::Result<bool, TransportError>> {
+            self.inner.unsubscribe(topic, id)
+        }
+
+        fn fetch(
+            &self,
+            topics: Vec<String>,
+        ) -> BoxFuture<'_, std::result::Result<FetchMessagesResponse, TransportError>> {
+            self.inner.fetch(topics)
+        }
+
+        fn incoming(&self) -> broadcast::Receiver<SubscriptionData> {
+            self.inner.incoming()
+        }
+
+        fn health(&self) -> watch::Receiver<ConnectionHealth> {
+            self.health.subscribe()
+        }
+
+        fn close(&self) -> BoxFuture<'_, ()> {
+            self.inner.close()
+        }
+    }
+
+    fn context(transport: Arc<Flaky>) -> WalletConnect {
+        WalletConnect::with_transport(testing::db(), transport)
+    }
+
+    async fn sent(events: &mut broadcast::Receiver<OutboxEvent>, within: Duration) -> OutboxEvent {
+        tokio::time::timeout(within, events.recv()).await.expect("no outbox event").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_outbox_order_and_expiry() {
+        let relay = LoopbackRelay::new();
+        let (sender, receiver) = (relay.connect(), relay.connect());
+        let outbox = outbox();
+        let mut events = outbox.events();
+        let topic: Topic<'static> = "topic".to_string().into();
+        let mut delivered = receiver.incoming();
+        receiver.subscribe(&topic.to_string()).await.unwrap();
+
+        assert_eq!(outbox.enqueue(&topic, "expired".into(), Policy::new(0, 0)).unwrap(), 0);
+        let count = BATCH_SIZE as u64 + 2;
+        for id in 1..=count {
+            assert_eq!(outbox.enqueue(&topic, id.to_string(), Policy::new(300, 0)).unwrap(), id);
+        }
+
+        outbox.send_pending(&sender).await.unwrap();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            OutboxEvent::Expired { id: 0, topic: topic.clone() }
+        );
+        for id in 1..=count {
+            assert_eq!(events.try_recv().unwrap(), OutboxEvent::Sent { id, topic: topic.clone() });
+            assert_eq!(delivered.try_recv().unwrap().message, id.to_string());
+        }
+        assert!(outbox.pending().unwrap().is_empty());
+
+        // sequence numbers are not reused once the outbox is empty, nor after a restart
+        assert_eq!(outbox.enqueue(&topic, "next".into(), Policy::new(300, 0)).unwrap(), count + 1);
+        outbox.send_pending(&sender).await.unwrap();
+        let reopened = Outbox::new(outbox.db.clone());
+        assert_eq!(
+            reopened.enqueue(&topic, "last".into(), Policy::new(300, 0)).unwrap(),
+            count + 2
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drain() {
+        let outbox = Arc::new(outbox());
+        let topic: Topic<'static> = "topic".to_string().into();
+        outbox.enqueue(&topic, "message".into(), Policy::new(300, 0)).unwrap();
+        assert_eq!(outbox.drain(Duration::from_millis(10)).await.unwrap(), 1);
+
+        let publisher = outbox.clone();
+        tokio::spawn(async move {
+            publisher.send_pending(&LoopbackRelay::new().connect()).await.unwrap();
+        });
+        assert_eq!(outbox.drain(Duration::from_secs(5)).await.unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_per_topic() {
+        let transport = Flaky::new(&LoopbackRelay::new());
+        let context = context(transport.clone());
+        let mut events = context.outbox().events();
+        let (a, b): (Topic<'static>, Topic<'static>) = ("a".to_string().into(), "b".into());
+        transport.set_failing(&a, true);
+
+        let outbox = context.outbox();
+        outbox.enqueue(&a, "a1".into(), Policy::new(300, 0)).unwrap();
+        outbox.enqueue(&b, "b1".into(), Policy::new(300, 0)).unwrap();
+        outbox.enqueue(&a, "a2".into(), Policy::new(300, 0)).unwrap();
+        // the failing topic does not hold back the others
+        let event = sent(&mut events, Duration::from_secs(1)).await;
+        assert_eq!(event, OutboxEvent::Sent { id: 1, topic: b });
+
+        // the failing topic is retried after the backoff, in order
+        transport.set_failing(&a, false);
+        let event = sent(&mut events, INITIAL_BACKOFF * 3).await;
+        assert_eq!(event, OutboxEvent::Sent { id: 0, topic: a.clone() });
+        let event = sent(&mut events, Duration::from_secs(1)).await;
+        assert_eq!(event, OutboxEvent::Sent { id: 2, topic: a });
+        assert!(outbox.pending().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flush_on_reconnect() {
+        let transport = Flaky::new(&LoopbackRelay::new());
+        let context = context(transport.clone());
+        let mut events = context.outbox().events();
+        let topic: Topic<'static> = "topic".to_string().into();
+
+        transport.set_down(true);
+        context.outbox().enqueue(&topic, "message".into(), Policy::new(300, 0)).unwrap();
+        // let the first retry fail, after which the outbox backs off for twice as long
+        tokio::time::sleep(INITIAL_BACKOFF + Duration::from_millis(10)).await;
+        assert_eq!(transport.attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(context.outbox().pending().unwrap().len(), 1);
+
+        transport.set_down(false);
+        let event = sent(&mut events, INITIAL_BACKOFF).await;
+        assert_eq!(event, OutboxEvent::Sent { id: 0, topic });
+    }
+}
@@ -1,4 +1,4 @@
-//! Pairing modulecratei for the WalletConnect pairing protocol.
+//! Pairing module for the WalletConnect pairing protocol.
 
 mod types;
 mod uri;
@@ -8,55 +8,119 @@ use std::sync::Arc;
 use chrono::Utc;
 use const_format::concatcp;
 use rand::{rngs::OsRng, RngCore};
-use redb::TableDefinition;
+use redb::{ReadableTable, TableDefinition};
 use speedy::{Readable, Writable};
 pub use uri::*;
 
 pub use self::types::*;
 use crate::{
-    crypto::Crypto, error::PairingError, expirations::ExpiryManager, relayer::Relayer,
-    rpc::prelude::RelayClient, time, types::Topic, WalletConnect, STORAGE_PREFIX,
+    crypto::Crypto, error::PairingError, expirations::ExpiryManager, relayer::Relayer, time,
+    types::Topic, WalletConnect, STORAGE_PREFIX,
 };
 
 pub type Result<T> = std::result::Result<T, PairingError>;
 
 pub const PAIRING: &str = "pairing";
-pub const VERSION: u16 = 1;
+/// version 1 stored the metadata truncated to 32 bytes, so it could not be read back
+pub const VERSION: u16 = 2;
 pub const NAMESPACE: &str = concatcp!(STORAGE_PREFIX, ":", VERSION, "//", PAIRING);
-const TABLE: TableDefinition<&Topic, [u8; 32]> = TableDefinition::new(NAMESPACE);
+const TABLE: TableDefinition<&Topic, &[u8]> = TableDefinition::new(NAMESPACE);
 
-fn to_fixed_bytes32<V: AsRef<[u8]>>(v: V) -> [u8; 32] {
-    let mut fixed = [0u8; 32];
-    let slice = v.as_ref();
-    fixed.copy_from_slice(&slice[0..32]);
-    fixed
+/// Pairings stored by their topic
+pub struct Pairings {
+    db: Arc<redb::Database>,
 }
 
+impl Pairings {
+    pub fn new(context: &WalletConnect) -> Self {
+        Self { db: context.db.clone() }
+    }
+
+    /// Persist a pairing to the database
+    pub fn set(&self, topic: &Topic<'static>, pairing: &PairingMetadata) -> Result<()> {
+        let bytes = pairing.write_to_vec()?;
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            table.insert(topic, bytes.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Get a persisted pairing
+    pub fn get(&self, topic: &Topic<'static>) -> Result<Option<PairingMetadata<'static>>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let pairing = match table.get(topic)? {
+            Some(bytes) => Some(PairingMetadata::read_from_buffer(bytes.value())?.into_owned()),
+            None => None,
+        };
+        Ok(pairing)
+    }
+
+    /// Every persisted pairing, by topic
+    pub fn all(&self) -> Result<Vec<(Topic<'static>, PairingMetadata<'static>)>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut pairings = Vec::new();
+        for entry in table.iter()? {
+            let (topic, bytes) = entry?;
+            let pairing = PairingMetadata::read_from_buffer(bytes.value())?.into_owned();
+            pairings.push((topic.value(), pairing));
+        }
+        Ok(pairings)
+    }
+
+    /// Delete a persisted pairing
+    pub fn delete(&self, topic: &Topic<'static>) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            let _value_guard = table.remove(topic)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
+
+/// Creates and joins pairings. Every operation takes `&self`, so one pairing can be shared
+/// between tasks.
 pub struct Pairing {
-    db: Arc<redb::Database>,
-    crypto: Crypto,
-    relayer: Relayer,
-    expirer: ExpiryManager,
+    pairings: Pairings,
+    crypto: Arc<Crypto>,
+    relayer: Arc<Relayer>,
+    expirer: Arc<ExpiryManager>,
     events: crate::events::GlobalEvents,
-    rpc: Arc<crate::rpc::Client>,
 }
 
 impl Pairing {
     pub fn new(context: &WalletConnect, events: crate::events::GlobalEvents) -> Result<Self> {
-        let db = context.db.clone();
-        let expirer = ExpiryManager::new(context)?;
-        let relayer = Relayer::new(context);
-        Ok(Self {
-            db,
-            relayer,
-            crypto: Crypto::new(context)?,
-            expirer,
-            events,
-            rpc: context.rpc.clone(),
-        })
-    }
-
-    pub async fn create(&mut self) -> Result<(Topic<'static>, PairingUri)> {
+        let relayer = context.relayer().clone();
+        let expirer = Arc::new(ExpiryManager::new(context)?);
+        Self::with_parts(context, Arc::new(Crypto::new(context)?), relayer, expirer, events)
+    }
+
+    /// Create a pairing sharing its crypto, relayer and expirer with other components
+    pub(crate) fn with_parts(
+        context: &WalletConnect,
+        crypto: Arc<Crypto>,
+        relayer: Arc<Relayer>,
+        expirer: Arc<ExpiryManager>,
+        events: crate::events::GlobalEvents,
+    ) -> Result<Self> {
+        Ok(Self { pairings: Pairings::new(context), crypto, relayer, expirer, events })
+    }
+
+    pub async fn create(&self) -> Result<(Topic<'static>, PairingUri)> {
         let sym_key: [u8; 32] = {
             let mut bytes = [0u8; 32];
             let mut rng = OsRng;
@@ -75,8 +139,10 @@ impl Pairing {
             .build();
 
         self.expirer.set_expiry(&topic, ttl.timestamp_millis())?;
-        self.set(&topic, PairingMetadata::new(uri.clone(), false, None, Default::default()))?;
+        self.set(&topic, &PairingMetadata::new(uri.clone(), false, None, Default::default()))?;
         self.relayer.subscribe(&topic).await?;
+        let expiry = ttl.timestamp_millis();
+        self.events.emit(PairingEvent::Create { topic: topic.clone(), expiry });
 
         Ok((topic, uri))
     }
@@ -114,29 +180,19 @@ impl Pairing {
     //  };
     //
 
+    /// The persisted pairings
+    pub fn pairings(&self) -> &Pairings {
+        &self.pairings
+    }
+
     /// Persist a pairing to the database
-    pub fn set(&self, topic: &Topic<'static>, pairing: PairingMetadata) -> Result<()> {
-        let write_txn = self.db.begin_write()?;
-        let bytes = pairing.write_to_vec()?;
-        {
-            let mut table = write_txn.open_table(TABLE)?;
-            let _ret = table.insert(topic, to_fixed_bytes32(bytes))?;
-            // ret.map(|v| v.value())
-        };
-        write_txn.commit()?;
-        Ok(())
+    pub fn set(&self, topic: &Topic<'static>, pairing: &PairingMetadata) -> Result<()> {
+        self.pairings.set(topic, pairing)
     }
 
     /// Get a persisted pairing
-    pub fn get(&self, topic: &Topic<'static>) -> Result<Option<PairingMetadata>> {
-        let read_txn = self.db.begin_read()?;
-        let table = read_txn.open_table(TABLE)?;
-        let bytes = table.get(topic)?.map(|v| v.value());
-
-        Ok(match bytes {
-            Some(bytes) => Some(PairingMetadata::read_from_buffer(&bytes)?.into_owned()),
-            None => None,
-        })
+    pub fn get(&self, topic: &Topic<'static>) -> Result<Option<PairingMetadata<'static>>> {
+        self.pairings.get(topic)
     }
 
     pub async fn pair(
@@ -151,37 +207,49 @@ impl Pairing {
         // is valid pair
 
         let (topic, sym_key, timestamp, _relay, _) = uri.decompose();
-        if let Ok(Some(pairing)) = self.get(&topic) {
-            if pairing.is_active() {
-                // TODO: Error
-                panic!("Pairing already exists");
-            }
+        if self.get(&topic)?.is_some_and(|pairing| pairing.is_active()) {
+            return Err(PairingError::AlreadyExists(topic.to_string()));
         }
 
         let default = crate::default_timestamp();
-        let expiry = timestamp.unwrap_or(default);
+        let mut expiry = timestamp.unwrap_or(default);
         self.expirer.set_expiry(&topic, expiry)?;
         let pairing = PairingMetadata::new(uri.clone(), false, None, Default::default());
-        self.set(&topic, pairing)?;
+        self.set(&topic, &pairing)?;
 
         if activate {
-            self.activate(&topic).await?;
+            expiry = self.activate(&topic).await?;
         }
-        self.events.emit(PairingEvent::Create);
+        self.events.emit(PairingEvent::Create { topic: topic.clone(), expiry });
 
         if !self.crypto.keychain().contains(&topic)? {
             self.crypto
                 .set_symkey(*sym_key.ok_or(PairingError::Missing(SymKeyParam))?, Some(&topic))?;
         }
 
-        let subscription = self.rpc.client.relay_subscribe(topic.to_string());
+        self.relayer.subscribe(&topic).await?;
         Ok(())
     }
 
-    pub async fn activate(&self, topic: &Topic<'static>) -> Result<()> {
+    /// Mark the pairing on `topic` active and extend it to 30 days.
+    /// Returns the millisecond timestamp it now expires at.
+    pub async fn activate(&self, topic: &Topic<'static>) -> Result<i64> {
         // isInitialized? not sure if needed
-        let expiry = Utc::now() + (crate::time::DAY * 30);
-        self.expirer.set_expiry(topic, expiry.timestamp_millis())?;
+        let mut pairing =
+            self.get(topic)?.ok_or_else(|| PairingError::NotFound(topic.to_string()))?;
+        pairing.activate();
+        self.set(topic, &pairing)?;
+        let expiry = (Utc::now() + (crate::time::DAY * 30)).timestamp_millis();
+        self.expirer.set_expiry(topic, expiry)?;
+        Ok(expiry)
+    }
+
+    /// Unsubscribe from a pairing and forget it, along with its key and expiry
+    pub async fn delete(&self, topic: &Topic<'static>) -> Result<()> {
+        self.relayer.unsubscribe(topic).await?;
+        self.pairings.delete(topic)?;
+        self.crypto.delete_symkey(topic.clone())?;
+        self.expirer.delete_expiry(topic)?;
         Ok(())
     }
 }
@@ -190,13 +258,156 @@ impl Pairing {
 mod tests {
     use std::str::FromStr;
 
+    use tokio_stream::StreamExt;
+
     use super::*;
+    use crate::{
+        events::EventFilter,
+        rpc::{prelude::RelayClient, transport::LoopbackRelay},
+        testing, EventPayload, GlobalEventKind,
+    };
+
+    #[test]
+    fn test_pairings() {
+        let db = redb::Database::builder()
+            .create_with_backend(redb::backends::InMemoryBackend::new())
+            .unwrap();
+        let pairings = Pairings { db: Arc::new(db) };
+        let uri = PairingUri::from_str("wc:60f9a6f502ea7e82a4e9ad87e3f2e19b404a4905626f4df597f0cea588ee8a69@2?expiryTimestamp=1727121081&relay-protocol=irn&symKey=1c509d8c0c62dbe9c1ca93e2f6a021a13daf040562f438ee937bd483a8c9f983").unwrap();
+        let topic = uri.topic.clone().into_owned();
+        let pairing =
+            PairingMetadata::new(uri.into_owned(), true, None, vec!["wc_sessionPropose".into()]);
+
+        assert!(pairings.get(&topic).unwrap().is_none());
+        pairings.set(&topic, &pairing).unwrap();
+        let stored = pairings.get(&topic).unwrap().unwrap();
+        assert_eq!(stored.uri(), pairing.uri());
+        assert!(stored.is_active());
+        pairings.set(&"other".to_string().into(), &pairing).unwrap();
+        assert_eq!(pairings.all().unwrap().len(), 2);
+
+        pairings.delete(&topic).unwrap();
+        assert_eq!(pairings.all().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_pairings() {
+        fn assert_send<T: Send>(_: &T) {}
+        fn assert_sync<T: Sync>(_: &T) {}
+        let _ = |pairing: &Pairing, uri: PairingUri<'static>, topic: &Topic<'static>| {
+            assert_sync(pairing);
+            assert_send(&pairing.create());
+            assert_send(&pairing.pair(uri, false, true));
+            assert_send(&pairing.delete(topic));
+        };
+
+        let db = redb::Database::builder()
+            .create_with_backend(redb::backends::InMemoryBackend::new())
+            .unwrap();
+        let pairings = Pairings { db: Arc::new(db) };
+        let uri = PairingUri::from_str("wc:60f9a6f502ea7e82a4e9ad87e3f2e19b404a4905626f4df597f0cea588ee8a69@2?expiryTimestamp=1727121081&relay-protocol=irn&symKey=1c509d8c0c62dbe9c1ca93e2f6a021a13daf040562f438ee937bd483a8c9f983").unwrap().into_owned();
+        let metadata = || PairingMetadata::new(uri.clone(), false, None, Default::default());
+
+        std::thread::scope(|s| {
+            for thread in 0..8 {
+                let (pairings, metadata) = (&pairings, &metadata);
+                s.spawn(move || {
+                    for i in 0..50 {
+                        let topic: Topic<'static> = format!("{thread}-{i}").into();
+                        pairings.set(&topic, &metadata()).unwrap();
+                        assert!(pairings.get(&topic).unwrap().is_some());
+                        if i % 2 == 0 {
+                            pairings.delete(&topic).unwrap();
+                        }
+                        pairings.all().unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(pairings.all().unwrap().len(), 8 * 25);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_pair_create_delete() {
+        let relay = LoopbackRelay::new();
+        let (dapp, wallet) = (testing::context(&relay), testing::context(&relay));
+        let proposer = Arc::new(Pairing::new(&dapp, dapp.events().clone()).unwrap());
+        let responder = Arc::new(Pairing::new(&wallet, wallet.events().clone()).unwrap());
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for i in 0..32 {
+            let (proposer, responder) = (proposer.clone(), responder.clone());
+            tasks.spawn(async move {
+                let (topic, uri) = proposer.create().await.unwrap();
+                responder.pair(uri.into_owned(), false, true).await.unwrap();
+                if i % 2 == 0 {
+                    let (first, second) =
+                        tokio::join!(proposer.delete(&topic), responder.delete(&topic));
+                    first.unwrap();
+                    second.unwrap();
+                }
+            });
+        }
+        while let Some(task) = tasks.join_next().await {
+            task.unwrap();
+        }
+
+        for pairing in [&proposer, &responder] {
+            assert_eq!(pairing.pairings.all().unwrap().len(), 16);
+            assert_eq!(pairing.relayer.topics().len(), 16);
+            assert_eq!(pairing.relayer.persisted().unwrap().len(), 16);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pair_active() {
+        let context = testing::context(&LoopbackRelay::new());
+        let pairing = Pairing::new(&context, context.events().clone()).unwrap();
+        let uri = PairingUri::from_str("wc:60f9a6f502ea7e82a4e9ad87e3f2e19b404a4905626f4df597f0cea588ee8a69@2?expiryTimestamp=1727121081&relay-protocol=irn&symKey=1c509d8c0c62dbe9c1ca93e2f6a021a13daf040562f438ee937bd483a8c9f983").unwrap().into_owned();
+        let topic = uri.topic.clone();
+
+        pairing.pair(uri.clone(), false, false).await.unwrap();
+        pairing
+            .set(&topic, &PairingMetadata::new(uri.clone(), true, None, Default::default()))
+            .unwrap();
+        assert!(matches!(
+            pairing.pair(uri, false, false).await,
+            Err(PairingError::AlreadyExists(t)) if t == topic.to_string()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pair_activate() {
+        let context = testing::context(&LoopbackRelay::new());
+        let pairing = Pairing::new(&context, context.events().clone()).unwrap();
+        let mut events =
+            context.events().subscribe(EventFilter::all().kind(GlobalEventKind::PairingCreate));
+        let uri = PairingUri::from_str("wc:60f9a6f502ea7e82a4e9ad87e3f2e19b404a4905626f4df597f0cea588ee8a69@2?expiryTimestamp=1727121081&relay-protocol=irn&symKey=1c509d8c0c62dbe9c1ca93e2f6a021a13daf040562f438ee937bd483a8c9f983").unwrap().into_owned();
+        let topic = uri.topic.clone();
+
+        pairing.pair(uri, false, true).await.unwrap();
+        assert!(pairing.get(&topic).unwrap().unwrap().is_active());
+
+        // the event carries the extended expiry, not the one of the URI
+        let expiry = pairing.expirer.get_expiry(&topic).unwrap().unwrap();
+        assert!(expiry > (Utc::now() + crate::time::DAY * 29).timestamp_millis());
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event.payload,
+            EventPayload::Pairing(PairingEvent::Create { topic: topic.clone(), expiry })
+        );
+    }
 
     #[tokio::test]
     async fn test_subscribe() {
         let pairing_uri = PairingUri::from_str("wc:60f9a6f502ea7e82a4e9ad87e3f2e19b404a4905626f4df597f0cea588ee8a69@2?expiryTimestamp=1727121081&relay-protocol=irn&symKey=1c509d8c0c62dbe9c1ca93e2f6a021a13daf040562f438ee937bd483a8c9f983").unwrap();
         let wc = crate::WalletConnect::new("./test-db").await.unwrap();
-        let mut subscription =
-            wc.rpc.client.relay_subscribe(pairing_uri.topic.to_string()).await.unwrap();
+        let mut subscription = wc
+            .client()
+            .unwrap()
+            .client
+            .relay_subscribe(pairing_uri.topic.to_string())
+            .await
+            .unwrap();
     }
 }
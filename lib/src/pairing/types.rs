@@ -1,6 +1,10 @@
 use speedy::{Readable, Writable};
 
-use crate::{pairing::uri::PairingUri, types::Metadata};
+use crate::{
+    pairing::uri::PairingUri,
+    rpc::types::{payload::Request, DeleteParams},
+    types::{Metadata, Topic},
+};
 
 #[derive(Readable, Writable)]
 pub struct PairingMetadata<'a> {
@@ -10,7 +14,7 @@ pub struct PairingMetadata<'a> {
     methods: Vec<String>,
 }
 
-impl PairingMetadata<'_> {
+impl<'a> PairingMetadata<'a> {
     pub fn new(
         uri: PairingUri<'static>,
         is_active: bool,
@@ -24,21 +28,74 @@ impl PairingMetadata<'_> {
         self.is_active
     }
 
+    /// Mark the pairing active, once its peer is known
+    pub fn activate(&mut self) {
+        self.is_active = true;
+    }
+
+    pub fn uri(&self) -> &PairingUri<'a> {
+        &self.uri
+    }
+
     pub fn into_owned(self) -> PairingMetadata<'static> {
         PairingMetadata { uri: self.uri.into_owned(), ..self }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+/// Something that happened to the pairing on a topic
+#[derive(Clone, Debug, PartialEq)]
 pub enum PairingEvent {
-    Create,
-    Expire,
-    Delete,
-    Ping,
+    Create {
+        topic: Topic<'static>,
+        /// millisecond timestamp the pairing expires at
+        expiry: i64,
+    },
+    Expire {
+        topic: Topic<'static>,
+    },
+    /// The pairing was loaded from storage and resubscribed on startup
+    Restore {
+        topic: Topic<'static>,
+        /// millisecond timestamp the pairing expires at
+        expiry: i64,
+    },
+    /// The peer deleted the pairing with `wc_pairingDelete`
+    Delete {
+        topic: Topic<'static>,
+        request: Request,
+        reason: DeleteParams,
+    },
+    Ping {
+        topic: Topic<'static>,
+        request: Request,
+    },
+}
+
+impl PairingEvent {
+    pub fn topic(&self) -> &Topic<'static> {
+        match self {
+            PairingEvent::Create { topic, .. }
+            | PairingEvent::Expire { topic }
+            | PairingEvent::Restore { topic, .. }
+            | PairingEvent::Delete { topic, .. }
+            | PairingEvent::Ping { topic, .. } => topic,
+        }
+    }
+
+    pub fn request(&self) -> Option<&Request> {
+        match self {
+            PairingEvent::Delete { request, .. } | PairingEvent::Ping { request, .. } => {
+                Some(request)
+            }
+            PairingEvent::Create { .. }
+            | PairingEvent::Expire { .. }
+            | PairingEvent::Restore { .. } => None,
+        }
+    }
 }
 
-impl From<PairingEvent> for crate::GlobalEvent {
-    fn from(event: PairingEvent) -> crate::GlobalEvent {
-        crate::GlobalEvent::Pairing(event)
+impl From<PairingEvent> for crate::EventPayload {
+    fn from(event: PairingEvent) -> crate::EventPayload {
+        crate::EventPayload::Pairing(event)
     }
 }
@@ -0,0 +1,521 @@
+//! JSON-RPC between peers. Payloads are encrypted with the symmetric key of their topic and
+//! published on the relay; responses are matched to their request by topic and id.
+//! A [`WalletConnect`] context has one [`PeerRpc`], shared by every component built on it, so
+//! each inbound message is decrypted, deduplicated and routed once.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{broadcast, broadcast::error::RecvError, oneshot};
+use url::Url;
+
+use crate::{
+    crypto::Crypto,
+    error::PeerError,
+    events::{inbound_event, GlobalEvents},
+    history::{self, History, JsonRpcRecord},
+    relayer::Relayer,
+    rpc::{
+        api::core::VerifyClient,
+        payload_id,
+        types::{
+            payload::{ErrorData, Payload, Request, Response},
+            policy, Policy, PolicyKind, SubscriptionData, VerifyContext, WC_SESSION_AUTHENTICATE,
+            WC_SESSION_PROPOSE, WC_SESSION_REQUEST,
+        },
+    },
+    session::Sessions,
+    supervisor::Supervisor,
+    time,
+    types::Topic,
+    WalletConnect, MESSAGE_CAPACITY,
+};
+
+pub type Result<T> = std::result::Result<T, PeerError>;
+
+/// How long to wait for a peer to respond to a request
+pub const RESPONSE_TIMEOUT: Duration = time::MINUTE.saturating_mul(5);
+
+/// A request received from a peer
+#[derive(Clone, Debug)]
+pub struct PeerRequest {
+    pub topic: Topic<'static>,
+    pub request: Request,
+    /// The verified origin of the request, if a verify server is configured and the method is
+    /// attested
+    pub verify_context: Option<VerifyContext>,
+}
+
+/// Methods a dapp attests with the verify server
+pub const VERIFIED_METHODS: [&str; 3] =
+    [WC_SESSION_PROPOSE, WC_SESSION_AUTHENTICATE, WC_SESSION_REQUEST];
+
+/// Overrides the publish policy of outgoing messages, given the method of the request, the kind of
+/// message and the policy the spec defines for it
+pub trait PolicyOverride: Send + Sync {
+    fn policy(&self, method: &str, kind: PolicyKind, policy: Policy) -> Policy;
+}
+
+impl<F> PolicyOverride for F
+where
+    F: Fn(&str, PolicyKind, Policy) -> Policy + Send + Sync,
+{
+    fn policy(&self, method: &str, kind: PolicyKind, policy: Policy) -> Policy {
+        self(method, kind, policy)
+    }
+}
+
+/// Requests waiting for a response, by the topic the response travels on and id
+type Pending = HashMap<(Topic<'static>, u64), oneshot::Sender<Response>>;
+
+#[derive(Clone)]
+pub struct PeerRpc {
+    crypto: Arc<Crypto>,
+    relayer: Arc<Relayer>,
+    sessions: Arc<Sessions>,
+    history: History,
+    verify: Arc<RwLock<Option<VerifyClient>>>,
+    policy_override: Arc<RwLock<Option<Arc<dyn PolicyOverride>>>>,
+    pending: Arc<Mutex<Pending>>,
+    requests: broadcast::Sender<PeerRequest>,
+    events: GlobalEvents,
+    tasks: Supervisor,
+}
+
+impl fmt::Debug for PeerRpc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerRpc").field("relayer", &self.relayer).finish_non_exhaustive()
+    }
+}
+
+impl PeerRpc {
+    /// The PeerRpc of the [`WalletConnect`] context. The first call creates it, publishing
+    /// through the relayer of the context and listening to every message delivered to it; later
+    /// calls return a handle to the same PeerRpc.
+    pub fn new(context: &WalletConnect) -> Result<Self> {
+        let mut shared = context.peer.lock().expect("peer lock poisoned");
+        if let Some(peer) = shared.as_ref() {
+            return Ok(peer.clone());
+        }
+        let peer = Self::spawn(context)?;
+        *shared = Some(peer.clone());
+        Ok(peer)
+    }
+
+    fn spawn(context: &WalletConnect) -> Result<Self> {
+        let (requests, _) = broadcast::channel(MESSAGE_CAPACITY);
+        let peer = Self {
+            crypto: Arc::new(Crypto::new(context)?),
+            relayer: context.relayer().clone(),
+            sessions: Arc::new(Sessions::new(context)?),
+            history: History::new(context)?,
+            verify: context.verify.clone(),
+            policy_override: Default::default(),
+            pending: Default::default(),
+            requests,
+            events: context.events.clone(),
+            tasks: context.tasks.clone(),
+        };
+
+        let mut messages = context.messages.subscribe();
+        let this = peer.clone();
+        peer.tasks.spawn(async move {
+            loop {
+                match messages.recv().await {
+                    Ok(data) => {
+                        if let Err(e) = this.handle_message(&data) {
+                            log::warn!("Dropping message on topic {}: {e}", data.topic);
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => log::warn!("PeerRpc lagged by {n} messages"),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let history = peer.history.clone();
+        peer.tasks.spawn(async move {
+            let mut interval = tokio::time::interval(history::PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let before = Utc::now() - history::RETENTION;
+                if let Err(e) = history.prune(before.timestamp_millis()) {
+                    log::warn!("Failed to prune JSON-RPC history: {e}");
+                }
+            }
+        });
+
+        Ok(peer)
+    }
+
+    /// Subscribe to requests sent by peers
+    pub fn requests(&self) -> broadcast::Receiver<PeerRequest> {
+        self.requests.subscribe()
+    }
+
+    /// Override the publish policy of every message sent through the context of this PeerRpc
+    pub fn set_policy_override(&self, policy_override: impl PolicyOverride + 'static) {
+        *self.policy_override.write().expect("policy lock poisoned") =
+            Some(Arc::new(policy_override));
+    }
+
+    /// Handle messages the relay queued for `topics` while we were offline.
+    /// Call once listeners of [`PeerRpc::requests`] are in place.
+    pub async fn drain_mailbox(&self, topics: &[Topic<'static>]) -> Result<usize> {
+        Ok(self.relayer.drain_mailbox(topics).await?)
+    }
+
+    /// Every request sent or received on `topic`, answered or pending, oldest first
+    pub fn history(&self, topic: &Topic<'static>) -> Result<Vec<JsonRpcRecord>> {
+        Ok(self.history.history(topic)?)
+    }
+
+    /// Send a request to the peer on `topic` and wait for its response
+    pub async fn request<P: Serialize>(
+        &self,
+        topic: &Topic<'static>,
+        method: &str,
+        params: P,
+    ) -> Result<Value> {
+        self.request_with_timeout(topic, method, params, RESPONSE_TIMEOUT).await
+    }
+
+    /// Send a request to the peer on `topic` and wait up to `timeout` for its response
+    pub async fn request_with_timeout<P: Serialize>(
+        &self,
+        topic: &Topic<'static>,
+        method: &str,
+        params: P,
+        timeout: Duration,
+    ) -> Result<Value> {
+        self.request_answered_on(topic, topic, method, params, timeout).await
+    }
+
+    /// Send a request to the peer on `topic` and wait up to `timeout` for its response on
+    /// `response_topic`, i.e a `wc_sessionAuthenticate` the wallet answers in a type 1 envelope
+    pub async fn request_answered_on<P: Serialize>(
+        &self,
+        topic: &Topic<'static>,
+        response_topic: &Topic<'static>,
+        method: &str,
+        params: P,
+        timeout: Duration,
+    ) -> Result<Value> {
+        let request = Request::new(payload_id(), method, serde_json::to_value(params)?);
+        let message = self.crypto.encode(topic, &serde_json::to_vec(&request)?)?;
+        self.send_request(topic, response_topic, request, message, timeout).await
+    }
+
+    /// Send a request in a type 1 envelope to a peer we do not share a symmetric key with yet.
+    /// The peer responds on `response_topic`, the topic of the key derived from `self_public` and
+    /// `peer_public`.
+    pub async fn request_type1<P: Serialize>(
+        &self,
+        topic: &Topic<'static>,
+        response_topic: &Topic<'static>,
+        self_public: &[u8; 32],
+        peer_public: &[u8; 32],
+        method: &str,
+        params: P,
+    ) -> Result<Value> {
+        let request = Request::new(payload_id(), method, serde_json::to_value(params)?);
+        let message =
+            self.crypto.encode_type1(self_public, peer_public, &serde_json::to_vec(&request)?)?;
+        self.send_request(topic, response_topic, request, message, RESPONSE_TIMEOUT).await
+    }
+
+    /// Send a request to the peer on `topic` without waiting for its response, i.e when the
+    /// topic is about to be deleted. Returns the id of the request.
+    pub async fn send<P: Serialize>(
+        &self,
+        topic: &Topic<'static>,
+        method: &str,
+        params: P,
+    ) -> Result<u64> {
+        let request = Request::new(payload_id(), method, serde_json::to_value(params)?);
+        let message = self.crypto.encode(topic, &serde_json::to_vec(&request)?)?;
+        let id = request.id;
+        self.history.set(topic, &request)?;
+        self.publish_message(topic, &Payload::Request(request), message).await?;
+        Ok(id)
+    }
+
+    async fn send_request(
+        &self,
+        topic: &Topic<'static>,
+        response_topic: &Topic<'static>,
+        request: Request,
+        message: String,
+        timeout: Duration,
+    ) -> Result<Value> {
+        let key = (response_topic.clone(), request.id);
+        self.history.set_answered_on(topic, response_topic, &request)?;
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().expect("pending lock poisoned").insert(key.clone(), tx);
+
+        if let Err(e) = self.publish_message(topic, &Payload::Request(request), message).await {
+            self.pending.lock().expect("pending lock poisoned").remove(&key);
+            return Err(e);
+        }
+
+        let response = tokio::time::timeout(timeout, rx).await;
+        self.pending.lock().expect("pending lock poisoned").remove(&key);
+        match response {
+            Ok(Ok(response)) => response.into_result().map_err(PeerError::Response),
+            Ok(Err(_)) => Err(PeerError::Closed),
+            Err(_) => Err(PeerError::Timeout(timeout)),
+        }
+    }
+
+    /// Respond to the request `id` received on `topic`
+    pub async fn respond<R: Serialize>(
+        &self,
+        topic: &Topic<'static>,
+        id: u64,
+        result: R,
+    ) -> Result<()> {
+        let response = Response::success(id, serde_json::to_value(result)?);
+        self.publish_response(topic, response).await
+    }
+
+    /// Respond to the request `id` received on `topic` with an error
+    pub async fn respond_error(
+        &self,
+        topic: &Topic<'static>,
+        id: u64,
+        error: ErrorData,
+    ) -> Result<()> {
+        self.publish_response(topic, Response::error(id, error)).await
+    }
+
+    /// Decrypt a relay message and route it to a pending request or to request listeners.
+    /// Messages delivered more than once and requests replaying a known id are dropped. A message
+    /// is only remembered once it could be decrypted, so it is handled if the relay delivers it
+    /// again after its key is stored.
+    pub fn handle_message(&self, data: &SubscriptionData) -> Result<()> {
+        let topic: Topic<'static> = data.topic.clone().into();
+        let plaintext = self.crypto.decode(&topic, &data.message)?;
+        if !self.history.receive_message(&data.message)? {
+            log::debug!("Dropping duplicate message on topic {topic}");
+            return Ok(());
+        }
+        let payload: Payload = serde_json::from_slice(&plaintext)?;
+
+        match payload {
+            Payload::Request(request) if !self.history.set(&topic, &request)? => {
+                log::warn!("Dropping replayed request {} on topic {topic}", request.id);
+            }
+            Payload::Request(request) => {
+                self.emit(&topic, &request);
+                match self.verify() {
+                    Some(verify) if VERIFIED_METHODS.contains(&request.method.as_str()) => {
+                        let this = self.clone();
+                        let message = data.message.clone();
+                        self.tasks.spawn(async move {
+                            let verify_context = match this.claimed_origin(&topic, &request) {
+                                Some(expected) => verify.verify(&message, &expected).await,
+                                None => VerifyContext::unknown(verify.url()),
+                            };
+                            let request = PeerRequest {
+                                topic,
+                                request,
+                                verify_context: Some(verify_context),
+                            };
+                            let _ = this.requests.send(request);
+                        });
+                    }
+                    _ => {
+                        let _ = self.requests.send(PeerRequest {
+                            topic,
+                            request,
+                            verify_context: None,
+                        });
+                    }
+                }
+            }
+            Payload::Response(response) => {
+                self.history.resolve(&topic, &response)?;
+                let key = (topic, response.id);
+                let pending = self.pending.lock().expect("pending lock poisoned").remove(&key);
+                if let Some(tx) = pending {
+                    let _ = tx.send(response);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Report a request received from the peer on the event bus
+    fn emit(&self, topic: &Topic<'static>, request: &Request) {
+        match inbound_event(topic, request) {
+            Ok(Some(event)) => self.events.emit(event),
+            Ok(None) => (),
+            Err(e) => log::warn!("Malformed {} params on topic {topic}: {e}", request.method),
+        }
+    }
+
+    /// The origin the sender of `request` claims in its metadata
+    /// The verify server of the context, as last set by
+    /// [`WalletConnect::with_verify`]
+    fn verify(&self) -> Option<VerifyClient> {
+        self.verify.read().expect("verify lock poisoned").clone()
+    }
+
+    fn claimed_origin(&self, topic: &Topic<'static>, request: &Request) -> Option<Url> {
+        let url = match request.method.as_str() {
+            WC_SESSION_PROPOSE => request.params.pointer("/proposer/metadata/url"),
+            WC_SESSION_AUTHENTICATE => request.params.pointer("/requester/metadata/url"),
+            _ => {
+                let session = self.sessions.get(topic).ok().flatten()?;
+                return Some(session.peer.metadata.url().clone());
+            }
+        };
+        Url::parse(url?.as_str()?).ok()
+    }
+
+    async fn publish_response(&self, topic: &Topic<'static>, response: Response) -> Result<()> {
+        let payload = Payload::Response(response.clone());
+        let message = self.crypto.encode(topic, &serde_json::to_vec(&payload)?)?;
+        self.publish_message(topic, &payload, message).await?;
+        self.history.resolve(topic, &response)?;
+        Ok(())
+    }
+
+    /// Publish the encrypted `payload`, registering its attestation if it is a request
+    async fn publish_message(
+        &self,
+        topic: &Topic<'static>,
+        payload: &Payload,
+        message: String,
+    ) -> Result<()> {
+        if let (Some(verify), Payload::Request(request)) = (self.verify(), payload) {
+            if verify.registered_origin().is_some()
+                && VERIFIED_METHODS.contains(&request.method.as_str())
+            {
+                if let Err(e) = verify.register(&message).await {
+                    log::warn!("Failed to register attestation for {}: {e}", request.method);
+                }
+            }
+        }
+        self.relayer.publish(topic, message, self.policy(topic, payload)).await?;
+        Ok(())
+    }
+
+    /// The publish policy of `payload`. Responses take the policy of the method of the request
+    /// they answer.
+    fn policy(&self, topic: &Topic<'static>, payload: &Payload) -> Policy {
+        let (method, kind) = match payload {
+            Payload::Request(request) => (Some(request.method.clone()), PolicyKind::Request),
+            Payload::Response(response) => {
+                let record = self.history.get(topic, response.id).ok().flatten();
+                let method = record.map(|r| r.request.method);
+                let kind = if response.error.is_some() {
+                    PolicyKind::Reject
+                } else {
+                    PolicyKind::Response
+                };
+                (method, kind)
+            }
+        };
+        let method = method.unwrap_or_default();
+        let default = policy(&method, kind);
+        match self.policy_override.read().expect("policy lock poisoned").as_ref() {
+            Some(policy_override) => policy_override.policy(&method, kind, default),
+            None => default,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{rpc::transport::LoopbackRelay, testing::context};
+
+    #[tokio::test]
+    async fn test_shared_peer() {
+        let relay = LoopbackRelay::new();
+        let (dapp, wallet) = (context(&relay), context(&relay));
+        let topic: Topic<'static> = hex::encode([7u8; 32]).into();
+        let sym_key = [1u8; 32];
+        Crypto::new(&dapp).unwrap().set_symkey(sym_key, Some(&topic)).unwrap();
+
+        // every component of a context shares one PeerRpc
+        let (first, second) = (PeerRpc::new(&wallet).unwrap(), PeerRpc::new(&wallet).unwrap());
+        assert!(Arc::ptr_eq(&first.pending, &second.pending));
+        let mut requests = second.requests();
+
+        // a message arriving before its key is handled once the relay delivers it again
+        let request = Request::new(1, "wc_sessionPing", json!({}));
+        let message = Crypto::new(&dapp)
+            .unwrap()
+            .encode(&topic, &serde_json::to_vec(&request).unwrap())
+            .unwrap();
+        let data = SubscriptionData {
+            topic: topic.to_string(),
+            message,
+            published_at: Utc::now().timestamp_millis(),
+            tag: 1114,
+        };
+        assert!(first.handle_message(&data).is_err());
+        Crypto::new(&wallet).unwrap().set_symkey(sym_key, Some(&topic)).unwrap();
+        first.handle_message(&data).unwrap();
+        assert_eq!(requests.recv().await.unwrap().request, request);
+        first.handle_message(&data).unwrap();
+        assert!(requests.try_recv().is_err());
+
+        // the response reaches the request whichever component sent it
+        dapp.relayer().subscribe(&topic).await.unwrap();
+        wallet.relayer().subscribe(&topic).await.unwrap();
+        let _core = PeerRpc::new(&dapp).unwrap();
+        let responder = first.clone();
+        tokio::spawn(async move {
+            let PeerRequest { topic, request, .. } = requests.recv().await.unwrap();
+            responder.respond(&topic, request.id, "pong").await.unwrap();
+        });
+        let result = PeerRpc::new(&dapp)
+            .unwrap()
+            .request_with_timeout(&topic, "wc_sessionPing", json!({}), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(result, json!("pong"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_after_peer() {
+        let relay = LoopbackRelay::new();
+        let (dapp, wallet) = (context(&relay), context(&relay));
+        let topic: Topic<'static> = hex::encode([7u8; 32]).into();
+        for context in [&dapp, &wallet] {
+            Crypto::new(context).unwrap().set_symkey([1u8; 32], Some(&topic)).unwrap();
+        }
+        let peer = PeerRpc::new(&wallet).unwrap();
+        let mut requests = peer.requests();
+
+        // a verify server set once the PeerRpc exists still applies to it
+        let url = Url::parse("http://127.0.0.1:1/").unwrap();
+        let _wallet = wallet.with_verify(VerifyClient::new(url.clone()));
+        let request = Request::new(1, WC_SESSION_REQUEST, json!({}));
+        let message = Crypto::new(&dapp)
+            .unwrap()
+            .encode(&topic, &serde_json::to_vec(&request).unwrap())
+            .unwrap();
+        let data = SubscriptionData {
+            topic: topic.to_string(),
+            message,
+            published_at: Utc::now().timestamp_millis(),
+            tag: 1108,
+        };
+        peer.handle_message(&data).unwrap();
+        let received = requests.recv().await.unwrap();
+        assert_eq!(received.verify_context, Some(VerifyContext::unknown(&url)));
+    }
+}
@@ -0,0 +1,461 @@
+//! An [EIP-1193](https://eips.ethereum.org/EIPS/eip-1193) style provider over a Sign session.
+//! Requests are forwarded to the wallet through `wc_sessionRequest`, except for the few methods
+//! which can be answered from the session itself.
+
+use std::sync::{Arc, RwLock};
+
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, broadcast::error::RecvError};
+
+use crate::{
+    error::ProviderError,
+    peer::{PeerRequest, PeerRpc},
+    relayer::Relayer,
+    rpc::{
+        signature::{self, EIP155},
+        types::{
+            AccountId, Caip27Request, ChainId, DeleteParams, Event, NamespaceMap,
+            SessionEventParams, SessionRequestParams, WC_SESSION_DELETE, WC_SESSION_EVENT,
+            WC_SESSION_REQUEST,
+        },
+    },
+    session::Sessions,
+    types::Topic,
+    WalletConnect,
+};
+
+pub type Result<T> = std::result::Result<T, ProviderError>;
+
+pub const ETH_CHAIN_ID: &str = "eth_chainId";
+pub const ETH_ACCOUNTS: &str = "eth_accounts";
+pub const ETH_REQUEST_ACCOUNTS: &str = "eth_requestAccounts";
+pub const WALLET_SWITCH_ETHEREUM_CHAIN: &str = "wallet_switchEthereumChain";
+pub const CHAIN_CHANGED: &str = "chainChanged";
+pub const ACCOUNTS_CHANGED: &str = "accountsChanged";
+
+/// Events emitted by a [`Provider`], mirroring the EIP-1193 provider events
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProviderEvent {
+    ChainChanged(ChainId),
+    AccountsChanged(Vec<AccountId>),
+    Disconnect {
+        code: i64,
+        message: String,
+    },
+    /// Any other event the wallet emitted on the session
+    Other(Event),
+}
+
+struct ProviderState {
+    chain_id: ChainId,
+    namespaces: NamespaceMap,
+}
+
+/// A provider bound to a session topic and an active chain
+#[derive(Clone)]
+pub struct Provider {
+    topic: Topic<'static>,
+    peer: PeerRpc,
+    sessions: Arc<Sessions>,
+    relayer: Arc<Relayer>,
+    state: Arc<RwLock<ProviderState>>,
+    events: broadcast::Sender<ProviderEvent>,
+}
+
+impl Provider {
+    /// Create a provider for the persisted session on `topic`, starting on `chain_id`
+    pub async fn new(
+        context: &WalletConnect,
+        topic: Topic<'static>,
+        chain_id: ChainId,
+    ) -> Result<Self> {
+        let sessions = Arc::new(Sessions::new(context)?);
+        let session = sessions
+            .get(&topic)?
+            .ok_or_else(|| ProviderError::UnknownSession(topic.to_string()))?;
+        if !session.supports_chain(&chain_id) {
+            return Err(ProviderError::UnsupportedChain(chain_id.to_string()));
+        }
+
+        context.relayer().subscribe(&topic).await?;
+        let peer = PeerRpc::new(context)?;
+
+        let (events, _) = broadcast::channel(16);
+        let provider = Self {
+            topic,
+            peer,
+            sessions,
+            relayer: context.relayer().clone(),
+            state: Arc::new(RwLock::new(ProviderState {
+                chain_id,
+                namespaces: session.namespaces,
+            })),
+            events,
+        };
+
+        let mut requests = provider.peer.requests();
+        let this = provider.clone();
+        context.tasks().spawn(async move {
+            loop {
+                match requests.recv().await {
+                    Ok(request) if request.topic == this.topic => {
+                        if let Err(e) = this.handle_request(request).await {
+                            log::warn!("Failed to handle session request: {e}");
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(RecvError::Lagged(n)) => log::warn!("Provider lagged by {n} requests"),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        provider.peer.drain_mailbox(std::slice::from_ref(&provider.topic)).await?;
+
+        Ok(provider)
+    }
+
+    /// Send a JSON-RPC request to the wallet.
+    /// Signatures returned for `personal_sign` and `eth_signTypedData_v4` are verified against
+    /// the session accounts.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        match method {
+            ETH_CHAIN_ID => {
+                let chain_id = self.chain_id();
+                return Ok(match chain_id.reference().parse::<u64>() {
+                    Ok(reference) if chain_id.namespace() == EIP155 => {
+                        json!(format!("0x{reference:x}"))
+                    }
+                    _ => json!(chain_id.to_string()),
+                });
+            }
+            ETH_ACCOUNTS | ETH_REQUEST_ACCOUNTS => {
+                let accounts: Vec<_> =
+                    self.accounts().iter().map(|a| a.address().to_string()).collect();
+                return Ok(json!(accounts));
+            }
+            WALLET_SWITCH_ETHEREUM_CHAIN => {
+                let chain_id = params
+                    .get(0)
+                    .and_then(|p| p.get("chainId"))
+                    .and_then(parse_chain_id)
+                    .ok_or(ProviderError::InvalidParams(WALLET_SWITCH_ETHEREUM_CHAIN))?;
+                self.switch_chain(chain_id).await?;
+                return Ok(Value::Null);
+            }
+            _ => (),
+        }
+
+        let chain_id = self.chain_id();
+        let request = SessionRequestParams {
+            request: Caip27Request::new(method, params.clone()),
+            chain_id: chain_id.clone(),
+        };
+        let result = self.peer.request(&self.topic, WC_SESSION_REQUEST, request).await?;
+
+        let namespaces = self.state.read().expect("provider lock poisoned").namespaces.clone();
+        signature::verify_session_response(&namespaces, &chain_id, method, &params, &result)?;
+        Ok(result)
+    }
+
+    /// Switch the active chain, asking the wallet to switch as well for `eip155` chains
+    pub async fn switch_chain(&self, chain_id: ChainId) -> Result<()> {
+        if !self.supports_chain(&chain_id) {
+            return Err(ProviderError::UnsupportedChain(chain_id.to_string()));
+        }
+        let current = self.chain_id();
+        if current == chain_id {
+            return Ok(());
+        }
+
+        if let (EIP155, Ok(reference)) = (chain_id.namespace(), chain_id.reference().parse::<u64>())
+        {
+            let request = SessionRequestParams {
+                request: Caip27Request::new(
+                    WALLET_SWITCH_ETHEREUM_CHAIN,
+                    json!([{ "chainId": format!("0x{reference:x}") }]),
+                ),
+                chain_id: current,
+            };
+            self.peer.request(&self.topic, WC_SESSION_REQUEST, request).await?;
+        }
+        self.set_chain(chain_id);
+        Ok(())
+    }
+
+    /// Subscribe to provider events
+    pub fn subscribe(&self) -> broadcast::Receiver<ProviderEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn topic(&self) -> &Topic<'static> {
+        &self.topic
+    }
+
+    /// The active chain
+    pub fn chain_id(&self) -> ChainId {
+        self.state.read().expect("provider lock poisoned").chain_id.clone()
+    }
+
+    /// Accounts approved for the active chain
+    pub fn accounts(&self) -> Vec<AccountId> {
+        let state = self.state.read().expect("provider lock poisoned");
+        state
+            .namespaces
+            .values()
+            .flat_map(|namespace| namespace.accounts.iter())
+            .filter(|account| account.chain_id() == &state.chain_id)
+            .cloned()
+            .collect()
+    }
+
+    fn supports_chain(&self, chain_id: &ChainId) -> bool {
+        let state = self.state.read().expect("provider lock poisoned");
+        state.namespaces.get(chain_id.namespace()).is_some_and(|ns| ns.supports_chain(chain_id))
+    }
+
+    fn set_chain(&self, chain_id: ChainId) {
+        let mut state = self.state.write().expect("provider lock poisoned");
+        if state.chain_id != chain_id {
+            state.chain_id = chain_id.clone();
+            let _ = self.events.send(ProviderEvent::ChainChanged(chain_id));
+        }
+    }
+
+    async fn handle_request(&self, PeerRequest { topic, request, .. }: PeerRequest) -> Result<()> {
+        match request.method.as_str() {
+            WC_SESSION_EVENT => {
+                let params: SessionEventParams = serde_json::from_value(request.params)
+                    .map_err(|_| ProviderError::InvalidParams(WC_SESSION_EVENT))?;
+                self.handle_event(params.event, params.chain_id);
+                self.peer.respond(&topic, request.id, true).await?;
+            }
+            WC_SESSION_DELETE => {
+                let DeleteParams { code, message } = serde_json::from_value(request.params)
+                    .map_err(|_| ProviderError::InvalidParams(WC_SESSION_DELETE))?;
+                self.peer.respond(&topic, request.id, true).await?;
+                self.sessions.delete(&topic)?;
+                self.relayer.unsubscribe(&topic).await?;
+                let _ = self.events.send(ProviderEvent::Disconnect { code, message });
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Apply a session event emitted by the wallet
+    pub fn handle_event(&self, event: Event, chain_id: ChainId) {
+        match event.name.as_str() {
+            CHAIN_CHANGED => {
+                let chain_id = parse_chain_id(&event.data).unwrap_or(chain_id);
+                if self.supports_chain(&chain_id) {
+                    self.set_chain(chain_id);
+                } else {
+                    log::warn!("Wallet switched to unapproved chain {chain_id}");
+                }
+            }
+            ACCOUNTS_CHANGED => {
+                let Some(addresses) = event.data.as_array() else {
+                    log::warn!("Malformed accountsChanged event {}", event.data);
+                    return;
+                };
+                let accounts: Vec<AccountId> = addresses
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(|a| {
+                        AccountId::parse(a).unwrap_or_else(|_| AccountId::new(chain_id.clone(), a))
+                    })
+                    .collect();
+
+                let mut state = self.state.write().expect("provider lock poisoned");
+                if let Some(namespace) = state.namespaces.get_mut(chain_id.namespace()) {
+                    namespace.accounts.retain(|a| a.chain_id() != &chain_id);
+                    namespace.accounts.extend(accounts.iter().cloned());
+                }
+                let _ = self.events.send(ProviderEvent::AccountsChanged(accounts));
+            }
+            _ => {
+                let _ = self.events.send(ProviderEvent::Other(event));
+            }
+        }
+    }
+}
+
+/// Parse a chain id given as a number, a hex string (EIP-155) or a CAIP-2 string
+fn parse_chain_id(value: &Value) -> Option<ChainId> {
+    match value {
+        Value::Number(n) => n.as_u64().map(|n| ChainId::new(EIP155, n.to_string())),
+        Value::String(s) => match s.strip_prefix("0x") {
+            Some(hex) => {
+                u64::from_str_radix(hex, 16).ok().map(|n| ChainId::new(EIP155, n.to_string()))
+            }
+            None => ChainId::parse(s).ok(),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::*;
+    use crate::{
+        rpc::{
+            transport::LoopbackRelay,
+            types::{Metadata, Namespace},
+        },
+        testing::{self, Dapp},
+        wallet::{SessionRequest, WalletClient, WalletEvent},
+    };
+
+    const ADDRESS: &str = "0xaAaAaAaaAaAaAaaAaAAAAAAAAaaaAaAaAaaAaaAa";
+
+    /// A provider on eip155:1 for a session the wallet approved on eip155:1 and eip155:10
+    async fn connected(relay: &LoopbackRelay) -> (Dapp, WalletConnect, WalletClient, Provider) {
+        let (dapp, context) = (Dapp::new(relay), testing::context(relay));
+        let wallet = WalletClient::new(&context, Metadata::default()).unwrap();
+        let mut events = wallet.events();
+        let chains = [ChainId::new(EIP155, "1"), ChainId::new(EIP155, "10")];
+
+        let required = HashMap::from([(
+            EIP155.to_string(),
+            Namespace {
+                chains: chains.iter().map(ToString::to_string).collect(),
+                methods: vec!["eth_sendTransaction".into(), WALLET_SWITCH_ETHEREUM_CHAIN.into()],
+                events: vec![CHAIN_CHANGED.into(), ACCOUNTS_CHANGED.into()],
+                accounts: vec![],
+            },
+        )]);
+        let (uri, proposal) = dapp.propose(required.clone(), None).await;
+        wallet.pair(uri).await.unwrap();
+        let WalletEvent::SessionProposal(proposal_event) = events.recv().await.unwrap() else {
+            panic!("expected a session proposal");
+        };
+        let accounts = chains.iter().map(|c| AccountId::new(c.clone(), ADDRESS)).collect();
+        wallet.approve(proposal_event.id, required, accounts).await.unwrap();
+        let session = proposal.await.unwrap().unwrap();
+
+        let provider =
+            Provider::new(&dapp.context, session.topic, chains[0].clone()).await.unwrap();
+        (dapp, context, wallet, provider)
+    }
+
+    /// Answer the next session request with `result`
+    async fn answer(
+        wallet: &WalletClient,
+        events: &mut broadcast::Receiver<WalletEvent>,
+        result: Value,
+    ) -> SessionRequest {
+        loop {
+            if let WalletEvent::SessionRequest(request) = events.recv().await.unwrap() {
+                wallet.respond(&request.topic, request.id, result).await.unwrap();
+                return *request;
+            }
+        }
+    }
+
+    async fn next(events: &mut broadcast::Receiver<ProviderEvent>) -> ProviderEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_request() {
+        let relay = LoopbackRelay::new();
+        let (_dapp, _context, wallet, provider) = connected(&relay).await;
+        let mut requests = wallet.events();
+
+        // answered from the session
+        assert_eq!(provider.request(ETH_CHAIN_ID, json!([])).await.unwrap(), json!("0x1"));
+        assert_eq!(provider.request(ETH_ACCOUNTS, json!([])).await.unwrap(), json!([ADDRESS]));
+
+        // forwarded to the wallet on the active chain
+        let params = json!([{ "from": ADDRESS, "to": ADDRESS, "value": "0x1" }]);
+        let (result, request) = tokio::join!(
+            provider.request("eth_sendTransaction", params.clone()),
+            answer(&wallet, &mut requests, json!("0xhash")),
+        );
+        assert_eq!(result.unwrap(), json!("0xhash"));
+        assert_eq!(request.topic, *provider.topic());
+        assert_eq!(request.params.chain_id, ChainId::new(EIP155, "1"));
+        assert_eq!(request.params.request, Caip27Request::new("eth_sendTransaction", params));
+    }
+
+    #[tokio::test]
+    async fn test_switch_chain() {
+        let relay = LoopbackRelay::new();
+        let (_dapp, _context, wallet, provider) = connected(&relay).await;
+        let (mut requests, mut events) = (wallet.events(), provider.subscribe());
+
+        let params = json!([{ "chainId": "0xa" }]);
+        let (result, request) = tokio::join!(
+            provider.request(WALLET_SWITCH_ETHEREUM_CHAIN, params.clone()),
+            answer(&wallet, &mut requests, Value::Null),
+        );
+        assert_eq!(result.unwrap(), Value::Null);
+        assert_eq!(request.params.chain_id, ChainId::new(EIP155, "1"));
+        assert_eq!(
+            request.params.request,
+            Caip27Request::new(WALLET_SWITCH_ETHEREUM_CHAIN, params)
+        );
+        assert_eq!(provider.chain_id(), ChainId::new(EIP155, "10"));
+        assert_eq!(next(&mut events).await, ProviderEvent::ChainChanged(provider.chain_id()));
+        assert_eq!(provider.accounts(), vec![AccountId::new(provider.chain_id(), ADDRESS)]);
+
+        // switching to the active chain or to an unapproved one never reaches the wallet
+        provider.switch_chain(ChainId::new(EIP155, "10")).await.unwrap();
+        let error = provider.switch_chain(ChainId::new(EIP155, "137")).await.unwrap_err();
+        assert!(matches!(error, ProviderError::UnsupportedChain(_)));
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_events() {
+        let relay = LoopbackRelay::new();
+        let (_dapp, context, _wallet, provider) = connected(&relay).await;
+        let (peer, mut events) = (PeerRpc::new(&context).unwrap(), provider.subscribe());
+        let emit = |name: &str, data: Value, chain_id: ChainId| {
+            let params = SessionEventParams { event: Event::new(name, data), chain_id };
+            peer.request(provider.topic(), WC_SESSION_EVENT, params)
+        };
+
+        let optimism = ChainId::new(EIP155, "10");
+        assert_eq!(emit(CHAIN_CHANGED, json!(10), optimism.clone()).await.unwrap(), json!(true));
+        assert_eq!(next(&mut events).await, ProviderEvent::ChainChanged(optimism.clone()));
+        assert_eq!(provider.chain_id(), optimism);
+
+        let other = "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB";
+        emit(ACCOUNTS_CHANGED, json!([other]), optimism.clone()).await.unwrap();
+        let accounts = vec![AccountId::new(optimism.clone(), other)];
+        assert_eq!(next(&mut events).await, ProviderEvent::AccountsChanged(accounts.clone()));
+        assert_eq!(provider.accounts(), accounts);
+
+        // a chain outside the session is ignored
+        emit(CHAIN_CHANGED, json!(137), optimism.clone()).await.unwrap();
+        emit("custom", json!({}), optimism.clone()).await.unwrap();
+        assert_eq!(next(&mut events).await, ProviderEvent::Other(Event::new("custom", json!({}))));
+        assert_eq!(provider.chain_id(), optimism);
+    }
+
+    #[tokio::test]
+    async fn test_session_delete() {
+        let relay = LoopbackRelay::new();
+        let (dapp, _context, wallet, provider) = connected(&relay).await;
+        let mut events = provider.subscribe();
+        let topic = provider.topic().clone();
+        assert!(dapp.context.relayer().is_subscribed(&topic));
+
+        wallet.disconnect(&topic).await.unwrap();
+        assert!(matches!(next(&mut events).await, ProviderEvent::Disconnect { .. }));
+        assert_eq!(Sessions::new(&dapp.context).unwrap().get(&topic).unwrap(), None);
+        assert!(!dapp.context.relayer().is_subscribed(&topic));
+    }
+
+    #[test]
+    fn test_parse_chain_id() {
+        let sepolia = ChainId::new(EIP155, "11155111");
+        assert_eq!(parse_chain_id(&json!(11155111)), Some(sepolia.clone()));
+        assert_eq!(parse_chain_id(&json!("0xaa36a7")), Some(sepolia.clone()));
+        assert_eq!(parse_chain_id(&json!("eip155:11155111")), Some(sepolia));
+        assert_eq!(parse_chain_id(&json!(true)), None);
+    }
+}
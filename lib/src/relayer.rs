@@ -1,27 +1,476 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use const_format::concatcp;
+use redb::{ReadableTable, TableDefinition};
+use tokio::sync::{broadcast, OwnedMutexGuard};
 
 use crate::{
     error::RelayerError,
-    rpc::{api::core::RelayClient, prelude::Subscription, Client},
+    outbox::Outbox,
+    rpc::{
+        transport::RelayTransport,
+        types::{FetchMessagesResponse, Policy, SubscriptionData},
+    },
     types::Topic,
-    WalletConnect,
+    STORAGE_PREFIX,
 };
 
 pub type Result<T> = std::result::Result<T, RelayerError>;
 
+pub const SUBSCRIPTION: &str = "subscription";
+pub const VERSION: u16 = 1;
+pub const NAMESPACE: &str = concatcp!(STORAGE_PREFIX, ":", VERSION, "//", SUBSCRIPTION);
+/// topic -> relay subscription id
+const TABLE: TableDefinition<&Topic, &str> = TableDefinition::new(NAMESPACE);
+
+/// Upper bound on `irn_batchFetchMessages` round-trips when draining the mailbox, in case the
+/// relay keeps reporting more messages
+pub const MAX_FETCH_ROUNDS: usize = 100;
+
+/// Subscribes to topics on the relay. Every operation takes `&self`, so the one relayer of a
+/// [`WalletConnect`](crate::WalletConnect) context is shared between all of its components and
+/// tasks.
+#[derive(Debug)]
 pub struct Relayer {
-    subscriptions: Vec<Subscription<serde_json::Value>>,
-    rpc: Client,
+    db: Arc<redb::Database>,
+    /// live subscription ids, by topic
+    subscriptions: Mutex<HashMap<Topic<'static>, String>>,
+    /// held while (un)subscribing to a topic, so concurrent calls for it reach the relay once
+    changing: Mutex<HashMap<Topic<'static>, Arc<tokio::sync::Mutex<()>>>>,
+    transport: Arc<dyn RelayTransport>,
+    outbox: Arc<Outbox>,
+    messages: broadcast::Sender<SubscriptionData>,
 }
 
 impl Relayer {
-    pub fn new(context: &WalletConnect) -> Self {
-        Self { rpc: Arc::unwrap_or_clone(context.rpc.clone()), subscriptions: vec![] }
+    pub(crate) fn new(
+        db: Arc<redb::Database>,
+        transport: Arc<dyn RelayTransport>,
+        outbox: Arc<Outbox>,
+        messages: broadcast::Sender<SubscriptionData>,
+    ) -> Self {
+        Self {
+            db,
+            transport,
+            subscriptions: Default::default(),
+            changing: Default::default(),
+            outbox,
+            messages,
+        }
+    }
+
+    /// Subscribe to a topic, forwarding every message the relay delivers on it to the
+    /// [`WalletConnect`](crate::WalletConnect) inbound message channel.
+    /// Returns the relay subscription id. Subscribing to a topic this relayer is already
+    /// subscribed to returns the existing id.
+    pub async fn subscribe(&self, topic: &Topic<'static>) -> Result<String> {
+        let _changing = self.changing(topic).await;
+        if let Some(id) = self.subscriptions().get(topic) {
+            return Ok(id.clone());
+        }
+
+        let id = self.transport.subscribe(&topic.to_string()).await?;
+        self.set(topic, &id)?;
+        self.subscriptions().insert(topic.clone(), id.clone());
+        Ok(id)
+    }
+
+    /// Unsubscribe from a topic and forget its subscription.
+    /// Returns whether the topic was subscribed to.
+    pub async fn unsubscribe(&self, topic: &Topic<'static>) -> Result<bool> {
+        let _changing = self.changing(topic).await;
+        let live = self.subscriptions().get(topic).cloned();
+        let id = match live {
+            Some(id) => Some(id),
+            None => self.get(topic)?,
+        };
+        let Some(id) = id else {
+            return Ok(false);
+        };
+        self.transport.unsubscribe(&topic.to_string(), &id).await?;
+        self.subscriptions().remove(topic);
+        self.delete(topic)?;
+        Ok(true)
+    }
+
+    /// Whether this relayer is subscribed to a topic
+    pub fn is_subscribed(&self, topic: &Topic<'static>) -> bool {
+        self.subscriptions().contains_key(topic)
+    }
+
+    /// Topics this relayer is subscribed to
+    pub fn topics(&self) -> Vec<Topic<'static>> {
+        self.subscriptions().keys().cloned().collect()
+    }
+
+    /// Resubscribe to `topics` and every persisted topic after a reconnect or restart, since
+    /// subscriptions do not outlive the connection they were made on, then drain their mailbox
+    /// and flush the outbox.
+    /// Returns the number of queued messages.
+    pub async fn restore(&self, topics: &[Topic<'static>]) -> Result<usize> {
+        let mut topics =
+            self.persisted()?.into_keys().chain(topics.iter().cloned()).collect::<Vec<_>>();
+        topics.sort();
+        topics.dedup();
+        {
+            let mut subscriptions = self.subscriptions();
+            for topic in &topics {
+                subscriptions.remove(topic);
+            }
+        }
+        let drained = self.start(&topics).await?;
+        self.outbox.flush();
+        Ok(drained)
+    }
+
+    /// Subscribe to `topics` on startup, then drain the messages the relay queued for them while
+    /// we were offline into the [`WalletConnect`](crate::WalletConnect) inbound message channel.
+    /// Returns the number of queued messages.
+    pub async fn start(&self, topics: &[Topic<'static>]) -> Result<usize> {
+        for topic in topics {
+            self.subscribe(topic).await?;
+        }
+        self.drain_mailbox(topics).await
+    }
+
+    /// Feed messages queued for `topics` through the inbound pipeline, as if the relay had
+    /// delivered them on a subscription
+    pub async fn drain_mailbox(&self, topics: &[Topic<'static>]) -> Result<usize> {
+        if topics.is_empty() {
+            return Ok(0);
+        }
+        let topics: Vec<String> = topics.iter().map(ToString::to_string).collect();
+        let mut drained = 0;
+        for _ in 0..MAX_FETCH_ROUNDS {
+            let FetchMessagesResponse { messages, has_more } =
+                self.transport.fetch(topics.clone()).await?;
+            drained += messages.len();
+            for message in messages {
+                let _ = self.messages.send(message);
+            }
+            if !has_more {
+                break;
+            }
+        }
+        Ok(drained)
+    }
+
+    /// Enqueue an encoded message to be published on a topic. The message is persisted in the
+    /// [`Outbox`] and retried until it is sent or its TTL passes.
+    pub async fn publish(
+        &self,
+        topic: &Topic<'static>,
+        message: String,
+        policy: Policy,
+    ) -> Result<()> {
+        self.outbox.enqueue(topic, message, policy)?;
+        Ok(())
     }
 
-    pub async fn subscribe(&mut self, topic: &Topic<'static>) -> Result<()> {
-        let subscription = self.rpc.inner().relay_subscribe(hex::encode(topic)).await?;
-        self.subscriptions.push(subscription);
+    /// Every persisted subscription id, by topic
+    pub fn persisted(&self) -> Result<HashMap<Topic<'static>, String>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(HashMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut subscriptions = HashMap::new();
+        for entry in table.iter()? {
+            let (topic, id) = entry?;
+            subscriptions.insert(topic.value(), id.value().to_string());
+        }
+        Ok(subscriptions)
+    }
+
+    fn subscriptions(&self) -> MutexGuard<'_, HashMap<Topic<'static>, String>> {
+        self.subscriptions.lock().expect("subscriptions lock poisoned")
+    }
+
+    /// Wait for the lock of a topic, serializing (un)subscribing to it
+    async fn changing(&self, topic: &Topic<'static>) -> Changing<'_> {
+        let lock = self.locks().entry(topic.clone()).or_default().clone();
+        let guard = lock.lock_owned().await;
+        Changing { relayer: self, topic: topic.clone(), guard: Some(guard) }
+    }
+
+    fn locks(&self) -> MutexGuard<'_, HashMap<Topic<'static>, Arc<tokio::sync::Mutex<()>>>> {
+        self.changing.lock().expect("changing lock poisoned")
+    }
+
+    fn get(&self, topic: &Topic<'static>) -> Result<Option<String>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(table.get(topic)?.map(|id| id.value().to_string()))
+    }
+
+    fn set(&self, topic: &Topic<'static>, id: &str) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            table.insert(topic, id)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    fn delete(&self, topic: &Topic<'static>) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            let _value_guard = table.remove(topic)?;
+        }
+        write_txn.commit()?;
         Ok(())
     }
 }
+
+/// Lock of a topic being (un)subscribed to. Dropping it forgets the lock once no other call
+/// waits for it.
+struct Changing<'a> {
+    relayer: &'a Relayer,
+    topic: Topic<'static>,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for Changing<'_> {
+    fn drop(&mut self) {
+        let mut locks = self.relayer.locks();
+        drop(self.guard.take());
+        if locks.get(&self.topic).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+            locks.remove(&self.topic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, time::Duration};
+
+    use redb::backends::InMemoryBackend;
+    use tokio::sync::watch;
+
+    use super::*;
+    use crate::rpc::{
+        error::TransportError,
+        transport::{
+            ConnectionHealth, LoopbackRelay, LoopbackTransport, RecordingTransport, TransportCall,
+        },
+        BoxFuture,
+    };
+
+    type Recorded<T> = Arc<RecordingTransport<T>>;
+
+    fn database() -> Arc<redb::Database> {
+        Arc::new(redb::Database::builder().create_with_backend(InMemoryBackend::new()).unwrap())
+    }
+
+    /// A relayer on `db` calling `transport`, and the inbound channel it feeds
+    fn relayer<T: RelayTransport + 'static>(
+        db: &Arc<redb::Database>,
+        transport: &Recorded<T>,
+    ) -> (Relayer, broadcast::Receiver<SubscriptionData>) {
+        let (messages, inbound) = broadcast::channel(16);
+        let outbox = Arc::new(Outbox::new(db.clone()));
+        (Relayer::new(db.clone(), transport.clone(), outbox, messages), inbound)
+    }
+
+    fn topic(n: u8) -> Topic<'static> {
+        hex::encode([n; 32]).into()
+    }
+
+    fn subscribe(topic: &Topic<'static>) -> TransportCall {
+        TransportCall::Subscribe { topic: topic.to_string() }
+    }
+
+    fn message(topic: &Topic<'static>, n: usize) -> SubscriptionData {
+        SubscriptionData {
+            topic: topic.to_string(),
+            message: n.to_string(),
+            published_at: 0,
+            tag: 0,
+        }
+    }
+
+    /// A loopback connection serving `irn_batchFetchMessages` from `pages`, then reporting more
+    /// messages forever
+    #[derive(Debug)]
+    struct Paged {
+        inner: LoopbackTransport,
+        pages: Mutex<VecDeque<FetchMessagesResponse>>,
+    }
+
+    impl RelayTransport for Paged {
+        fn publish<'a>(
+            &'a self,
+            topic: &'a str,
+            message: String,
+            policy: Policy,
+        ) -> BoxFuture<'a, std::result::Result<(), TransportError>> {
+            self.inner.publish(topic, message, policy)
+        }
+
+        fn subscribe<'a>(
+            &'a self,
+            topic: &'a str,
+        ) -> BoxFuture<'a, std::result::Result<String, TransportError>> {
+            self.inner.subscribe(topic)
+        }
+
+        fn unsubscribe<'a>(
+            &'a self,
+            topic: &'a str,
+            id: &'a str,
+        ) -> BoxFuture<'a, std::result::Result<bool, TransportError>> {
+            self.inner.unsubscribe(topic, id)
+        }
+
+        fn fetch(
+            &self,
+            _: Vec<String>,
+        ) -> BoxFuture<'_, std::result::Result<FetchMessagesResponse, TransportError>> {
+            let page = self.pages.lock().unwrap().pop_front();
+            let page = page.unwrap_or(FetchMessagesResponse { messages: vec![], has_more: true });
+            Box::pin(async move { Ok(page) })
+        }
+
+        fn incoming(&self) -> broadcast::Receiver<SubscriptionData> {
+            self.inner.incoming()
+        }
+
+        fn health(&self) -> watch::Receiver<ConnectionHealth> {
+            self.inner.health()
+        }
+
+        fn close(&self) -> BoxFuture<'_, ()> {
+            self.inner.close()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let transport = Arc::new(RecordingTransport::new(LoopbackRelay::new().connect()));
+        let (relayer, _) = relayer(&database(), &transport);
+        let topic = topic(1);
+
+        let (first, second) = tokio::join!(relayer.subscribe(&topic), relayer.subscribe(&topic));
+        let id = first.unwrap();
+        assert_eq!(second.unwrap(), id);
+        assert_eq!(relayer.subscribe(&topic).await.unwrap(), id);
+
+        // the relay is asked once, and the id is persisted
+        assert_eq!(transport.calls(), vec![subscribe(&topic)]);
+        assert!(relayer.is_subscribed(&topic));
+        assert_eq!(relayer.persisted().unwrap(), HashMap::from([(topic, id)]));
+        assert!(relayer.locks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_topics_concurrently() {
+        let transport = Arc::new(RecordingTransport::new(LoopbackRelay::new().connect()));
+        let (relayer, _) = relayer(&database(), &transport);
+
+        // a call stuck on one topic does not hold up the others
+        let (first, second) = (topic(1), topic(2));
+        let stuck = relayer.changing(&first).await;
+        let subscribe = relayer.subscribe(&second);
+        tokio::time::timeout(Duration::from_secs(1), subscribe).await.unwrap().unwrap();
+        let subscribe = relayer.subscribe(&first);
+        assert!(tokio::time::timeout(Duration::from_millis(50), subscribe).await.is_err());
+        drop(stuck);
+        relayer.subscribe(&first).await.unwrap();
+        assert!(relayer.locks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe() {
+        let db = database();
+        let transport = Arc::new(RecordingTransport::new(LoopbackRelay::new().connect()));
+        let topic = topic(1);
+        let id = relayer(&db, &transport).0.subscribe(&topic).await.unwrap();
+
+        // after a restart the subscription is only known from the database
+        let (relayer, _) = relayer(&db, &transport);
+        assert!(!relayer.is_subscribed(&topic));
+        assert!(relayer.unsubscribe(&topic).await.unwrap());
+        assert!(!relayer.unsubscribe(&topic).await.unwrap());
+
+        let unsubscribe = TransportCall::Unsubscribe { topic: topic.to_string(), id };
+        assert_eq!(transport.calls(), vec![subscribe(&topic), unsubscribe]);
+        assert!(relayer.persisted().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_unsubscribe() {
+        let connection = Arc::new(LoopbackRelay::new().connect());
+        let transport = Arc::new(RecordingTransport::new(connection.clone()));
+        let (relayer, _) = relayer(&database(), &transport);
+        let topic = topic(1);
+        let id = relayer.subscribe(&topic).await.unwrap();
+
+        // the subscription is kept until the relay confirms it is gone
+        connection.disconnect("gone");
+        assert!(relayer.unsubscribe(&topic).await.is_err());
+        assert!(relayer.is_subscribed(&topic));
+        assert_eq!(relayer.persisted().unwrap(), HashMap::from([(topic, id)]));
+    }
+
+    #[tokio::test]
+    async fn test_restore() {
+        let db = database();
+        let transport = Arc::new(RecordingTransport::new(LoopbackRelay::new().connect()));
+        let (persisted, live, passed) = (topic(1), topic(2), topic(3));
+        relayer(&db, &transport).0.subscribe(&persisted).await.unwrap();
+        let (relayer, _) = relayer(&db, &transport);
+        relayer.subscribe(&live).await.unwrap();
+
+        // persisted and passed topics are merged, and live subscriptions are opened again
+        let topics = [passed.clone(), persisted.clone()];
+        assert_eq!(relayer.restore(&topics).await.unwrap(), 0);
+        let restored = [&persisted, &live, &passed];
+        let fetch =
+            TransportCall::Fetch { topics: restored.iter().map(|t| t.to_string()).collect() };
+        let expected: Vec<_> =
+            [&persisted, &live].into_iter().chain(restored).map(subscribe).chain([fetch]).collect();
+        assert_eq!(transport.calls(), expected);
+        assert_eq!(relayer.topics().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_drain_mailbox() {
+        let topic = topic(1);
+        let pages = VecDeque::from([
+            FetchMessagesResponse {
+                messages: vec![message(&topic, 0), message(&topic, 1)],
+                has_more: true,
+            },
+            FetchMessagesResponse { messages: vec![message(&topic, 2)], has_more: false },
+        ]);
+        let inner = LoopbackRelay::new().connect();
+        let transport =
+            Arc::new(RecordingTransport::new(Paged { inner, pages: Mutex::new(pages) }));
+        let (relayer, mut inbound) = relayer(&database(), &transport);
+
+        assert_eq!(relayer.drain_mailbox(&[]).await.unwrap(), 0);
+        assert!(transport.calls().is_empty());
+
+        // the mailbox is fetched until the relay has no more messages
+        assert_eq!(relayer.drain_mailbox(std::slice::from_ref(&topic)).await.unwrap(), 3);
+        let fetch = TransportCall::Fetch { topics: vec![topic.to_string()] };
+        assert_eq!(transport.calls(), vec![fetch.clone(), fetch.clone()]);
+        for n in 0..3 {
+            assert_eq!(inbound.try_recv().unwrap(), message(&topic, n));
+        }
+
+        // a relay which always reports more messages is fetched a bounded number of times
+        assert_eq!(relayer.drain_mailbox(std::slice::from_ref(&topic)).await.unwrap(), 0);
+        assert_eq!(transport.calls().len(), 2 + MAX_FETCH_ROUNDS);
+    }
+}
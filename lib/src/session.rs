@@ -0,0 +1,83 @@
+//! Persistence of sessions for the WalletConnect Sign protocol.
+
+mod types;
+
+use std::sync::Arc;
+
+use const_format::concatcp;
+use redb::{ReadableTable, TableDefinition};
+
+pub use self::types::*;
+use crate::{error::SessionError, time, types::Topic, WalletConnect, STORAGE_PREFIX};
+
+pub type Result<T> = std::result::Result<T, SessionError>;
+
+pub const SESSION: &str = "session";
+pub const VERSION: u16 = 1;
+pub const NAMESPACE: &str = concatcp!(STORAGE_PREFIX, ":", VERSION, "//", SESSION);
+const TABLE: TableDefinition<&Topic, &[u8]> = TableDefinition::new(NAMESPACE);
+
+/// How long a settled session lasts before it must be extended
+pub const SESSION_TTL: std::time::Duration = time::WEEK;
+
+/// Sessions stored by their topic
+pub struct Sessions {
+    db: Arc<redb::Database>,
+}
+
+impl Sessions {
+    pub fn new(context: &WalletConnect) -> Result<Self> {
+        Ok(Self { db: context.db.clone() })
+    }
+
+    /// Persist a session to the database
+    pub fn set(&self, session: &Session) -> Result<()> {
+        let bytes = serde_json::to_vec(session)?;
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            table.insert(&session.topic, bytes.as_slice())?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    /// Get a persisted session
+    pub fn get(&self, topic: &Topic<'static>) -> Result<Option<Session>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let session = table.get(topic)?.map(|v| serde_json::from_slice(v.value())).transpose()?;
+        Ok(session)
+    }
+
+    /// All persisted sessions
+    pub fn all(&self) -> Result<Vec<Session>> {
+        let read_txn = self.db.begin_read()?;
+        let table = match read_txn.open_table(TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut sessions = Vec::new();
+        for entry in table.iter()? {
+            let (_, value) = entry?;
+            sessions.push(serde_json::from_slice(value.value())?);
+        }
+        Ok(sessions)
+    }
+
+    /// Delete a persisted session
+    pub fn delete(&self, topic: &Topic<'static>) -> Result<()> {
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            let _value_guard = table.remove(topic)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+}
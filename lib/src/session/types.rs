@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rpc::types::{
+        payload::Request, ChainId, DeleteParams, NamespaceMap, Participant, Relay,
+        SessionEventParams, SessionRequestParams,
+    },
+    types::Topic,
+};
+
+/// A settled session of the Sign protocol
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub topic: Topic<'static>,
+    pub pairing_topic: Topic<'static>,
+    pub relay: Relay,
+    /// expiry as a millisecond timestamp
+    pub expiry: i64,
+    pub acknowledged: bool,
+    /// hex encoded public key of the controller, the wallet
+    pub controller: String,
+    pub namespaces: NamespaceMap,
+    pub required_namespaces: NamespaceMap,
+    #[serde(rename = "self")]
+    pub this: Participant,
+    pub peer: Participant,
+}
+
+impl Session {
+    /// Whether the wallet approved `chain_id` for this session
+    pub fn supports_chain(&self, chain_id: &ChainId) -> bool {
+        self.namespaces.get(chain_id.namespace()).is_some_and(|ns| ns.supports_chain(chain_id))
+    }
+}
+
+/// Something that happened on a session, or on the pairing a session was proposed on
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEvent {
+    Proposal {
+        pairing_topic: Topic<'static>,
+        request: Request,
+        proposer: Participant,
+    },
+    /// The session was settled. `request` is the `wc_sessionSettle` if it was received from the
+    /// wallet, and `None` on the wallet once the dapp acknowledged it.
+    Settle {
+        topic: Topic<'static>,
+        request: Option<Request>,
+        peer: Participant,
+        namespaces: NamespaceMap,
+        /// expiry as a millisecond timestamp
+        expiry: i64,
+    },
+    Update {
+        topic: Topic<'static>,
+        request: Request,
+        namespaces: NamespaceMap,
+    },
+    Extend {
+        topic: Topic<'static>,
+        request: Request,
+        /// new expiry as a millisecond timestamp
+        expiry: i64,
+    },
+    Request {
+        topic: Topic<'static>,
+        request: Request,
+        params: SessionRequestParams,
+    },
+    Event {
+        topic: Topic<'static>,
+        request: Request,
+        params: SessionEventParams,
+    },
+    Ping {
+        topic: Topic<'static>,
+        request: Request,
+    },
+    /// The peer deleted the session with `wc_sessionDelete`
+    Delete {
+        topic: Topic<'static>,
+        request: Request,
+        reason: DeleteParams,
+    },
+    Expire {
+        topic: Topic<'static>,
+    },
+    /// The session was loaded from storage and resubscribed on startup
+    Restore {
+        topic: Topic<'static>,
+        peer: Participant,
+        /// expiry as a millisecond timestamp
+        expiry: i64,
+    },
+}
+
+impl SessionEvent {
+    /// The session topic, or the pairing topic of a proposal
+    pub fn topic(&self) -> &Topic<'static> {
+        match self {
+            SessionEvent::Proposal { pairing_topic: topic, .. }
+            | SessionEvent::Settle { topic, .. }
+            | SessionEvent::Update { topic, .. }
+            | SessionEvent::Extend { topic, .. }
+            | SessionEvent::Request { topic, .. }
+            | SessionEvent::Event { topic, .. }
+            | SessionEvent::Ping { topic, .. }
+            | SessionEvent::Delete { topic, .. }
+            | SessionEvent::Expire { topic }
+            | SessionEvent::Restore { topic, .. } => topic,
+        }
+    }
+
+    pub fn request(&self) -> Option<&Request> {
+        match self {
+            SessionEvent::Settle { request, .. } => request.as_ref(),
+            SessionEvent::Proposal { request, .. }
+            | SessionEvent::Update { request, .. }
+            | SessionEvent::Extend { request, .. }
+            | SessionEvent::Request { request, .. }
+            | SessionEvent::Event { request, .. }
+            | SessionEvent::Ping { request, .. }
+            | SessionEvent::Delete { request, .. } => Some(request),
+            SessionEvent::Expire { .. } | SessionEvent::Restore { .. } => None,
+        }
+    }
+}
+
+impl From<SessionEvent> for crate::EventPayload {
+    fn from(event: SessionEvent) -> crate::EventPayload {
+        crate::EventPayload::Session(event)
+    }
+}
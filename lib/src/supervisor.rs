@@ -0,0 +1,109 @@
+//! Lifecycle of the background tasks of a [`WalletConnect`](crate::WalletConnect) context.
+//! Every task listening to the relay or the event bus is spawned through the [`Supervisor`] of
+//! its context, so [`Supervisor::shutdown`] can stop all of them and wait until they are gone,
+//! instead of leaving them running until the runtime is dropped.
+
+use std::future::Future;
+
+use tokio::task::JoinHandle;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// Tracks spawned tasks and cancels them on shutdown
+#[derive(Clone, Debug, Default)]
+pub struct Supervisor {
+    token: CancellationToken,
+    tracker: TaskTracker,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawn `task`, dropping it at its next await point once the supervisor shuts down.
+    /// The handle can still abort the task on its own.
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let token = self.token.clone();
+        self.tracker.spawn(async move {
+            tokio::select! {
+                _ = token.cancelled() => {}
+                _ = task => {}
+            }
+        })
+    }
+
+    /// A token cancelled when the supervisor shuts down, for work spawned elsewhere
+    pub fn token(&self) -> CancellationToken {
+        self.token.child_token()
+    }
+
+    /// The tracker of the tasks, for work spawned elsewhere which shutdown must wait for
+    pub(crate) fn tracker(&self) -> TaskTracker {
+        self.tracker.clone()
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Number of tasks still running
+    pub fn len(&self) -> usize {
+        self.tracker.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracker.is_empty()
+    }
+
+    /// Cancel every task and wait for all of them to finish. Tasks spawned afterwards are
+    /// cancelled right away.
+    pub async fn shutdown(&self) {
+        self.token.cancel();
+        self.tracker.close();
+        self.tracker.wait().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    struct SetOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let supervisor = Supervisor::new();
+        let dropped = Arc::new(AtomicBool::new(false));
+        let guard = SetOnDrop(dropped.clone());
+        supervisor.spawn(async move {
+            let _guard = guard;
+            std::future::pending::<()>().await
+        });
+        supervisor.spawn(async {});
+        let token = supervisor.token();
+        assert!(!token.is_cancelled());
+
+        supervisor.shutdown().await;
+        assert!(supervisor.is_empty());
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(token.is_cancelled());
+
+        let late = supervisor.spawn(std::future::pending());
+        late.await.unwrap();
+        assert!(supervisor.is_shutdown());
+    }
+}
@@ -0,0 +1,189 @@
+//! Helpers shared by the tests of components built on a [`WalletConnect`] context
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{RawQuery, State},
+    http::StatusCode,
+    routing::post,
+    Json, Router,
+};
+use ed25519_dalek::VerifyingKey;
+use tokio::{net::TcpListener, task::JoinHandle};
+use url::Url;
+
+use crate::{
+    crypto::Crypto,
+    pairing::{Pairing, PairingUri},
+    peer::{self, PeerRequest, PeerRpc},
+    rpc::{
+        api::core::KeysClient,
+        auth::{decode_did_key, decode_jwt, encode_public_key_as_did},
+        transport::LoopbackRelay,
+        types::{
+            identity::{
+                RegisterIdentity, ResolveIdentity, UnregisterIdentity, UnregisterIdentityClaims,
+                UNREGISTER_IDENTITY,
+            },
+            Cacao, Metadata, NamespaceMap, Participant, Relay, SessionProposeParams,
+            SessionProposeResult, SessionSettleParams, CACAO_TYPE_EIP4361, WC_SESSION_PROPOSE,
+            WC_SESSION_SETTLE,
+        },
+    },
+    session::{Session, Sessions},
+    WalletConnect,
+};
+
+/// An in-memory database
+pub fn db() -> redb::Database {
+    redb::Database::builder().create_with_backend(redb::backends::InMemoryBackend::new()).unwrap()
+}
+
+/// A context on an in-memory database, connected to `relay`
+pub fn context(relay: &LoopbackRelay) -> WalletConnect {
+    WalletConnect::with_transport(db(), relay.connect())
+}
+
+/// CACAOs registered with the stub keys server, by `did:key` of their identity key
+pub type Registry = Arc<Mutex<HashMap<String, Cacao>>>;
+
+/// A keys server keeping identities in memory. It only registers `eip4361` CACAOs, and
+/// unregistering requires a JWT signed by a registered identity key on behalf of its account.
+pub async fn keys_server() -> (KeysClient, Registry) {
+    async fn register(
+        State(registry): State<Registry>,
+        Json(RegisterIdentity { cacao }): Json<RegisterIdentity>,
+    ) -> StatusCode {
+        if cacao.h.t != CACAO_TYPE_EIP4361 {
+            return StatusCode::BAD_REQUEST;
+        }
+        let Some(did) = cacao.p.resources.as_ref().and_then(|r| r.first()).cloned() else {
+            return StatusCode::BAD_REQUEST;
+        };
+        registry.lock().unwrap().insert(did, cacao);
+        StatusCode::OK
+    }
+
+    async fn resolve(
+        State(registry): State<Registry>,
+        RawQuery(query): RawQuery,
+    ) -> Result<Json<ResolveIdentity>, StatusCode> {
+        let public_key = query
+            .as_deref()
+            .and_then(|q| q.strip_prefix("publicKey="))
+            .and_then(|key| <[u8; 32]>::try_from(hex::decode(key).ok()?).ok())
+            .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        let cacao = registry.lock().unwrap().get(&encode_public_key_as_did(&public_key)).cloned();
+        let cacao = cacao.ok_or(StatusCode::NOT_FOUND)?;
+        Ok(Json(ResolveIdentity { value: RegisterIdentity { cacao } }))
+    }
+
+    async fn unregister(
+        State(registry): State<Registry>,
+        Json(UnregisterIdentity { id_auth }): Json<UnregisterIdentity>,
+    ) -> StatusCode {
+        let mut registry = registry.lock().unwrap();
+        let signer = registry.iter().find_map(|(did, cacao)| {
+            let claims: UnregisterIdentityClaims =
+                decode_jwt(&id_auth, &decode_did_key(did).ok()?).ok()?;
+            (claims.iss == *did && claims.act == UNREGISTER_IDENTITY && claims.pkh == cacao.p.iss)
+                .then(|| did.clone())
+        });
+        match signer {
+            Some(did) => {
+                registry.remove(&did);
+                StatusCode::OK
+            }
+            None => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    let registry = Registry::default();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    let router = Router::new()
+        .route("/identity", post(register).get(resolve).delete(unregister))
+        .with_state(registry.clone());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (KeysClient::new(url), registry)
+}
+
+/// The proposer side of the Sign protocol, which this crate leaves to the dapp SDKs
+pub struct Dapp {
+    pub context: WalletConnect,
+    pub peer: PeerRpc,
+    crypto: Arc<Crypto>,
+    pairing: Pairing,
+}
+
+impl Dapp {
+    pub fn new(relay: &LoopbackRelay) -> Self {
+        let context = context(relay);
+        Self {
+            peer: PeerRpc::new(&context).unwrap(),
+            crypto: Arc::new(Crypto::new(&context).unwrap()),
+            pairing: Pairing::new(&context, context.events().clone()).unwrap(),
+            context,
+        }
+    }
+
+    /// Propose a session requiring `required_namespaces` on a new pairing. The handle resolves
+    /// to the session once the wallet approves and settles it, and fails if the wallet rejects
+    /// the proposal.
+    pub async fn propose(
+        &self,
+        required_namespaces: NamespaceMap,
+        expiry_timestamp: Option<i64>,
+    ) -> (PairingUri<'static>, JoinHandle<peer::Result<Session>>) {
+        let (pairing_topic, uri) = self.pairing.create().await.unwrap();
+        let self_public = self.crypto.generate_keypair().unwrap();
+        let params = SessionProposeParams {
+            relays: vec![Relay::new("irn", None::<&str>)],
+            proposer: Participant::new(self_public, Metadata::default()),
+            required_namespaces: required_namespaces.clone(),
+            optional_namespaces: Default::default(),
+            session_properties: None,
+            expiry_timestamp,
+        };
+
+        let (peer, crypto, context) =
+            (self.peer.clone(), self.crypto.clone(), self.context.clone());
+        let mut requests = peer.requests();
+        let handle = tokio::spawn(async move {
+            let result = peer.request(&pairing_topic, WC_SESSION_PROPOSE, params.clone()).await?;
+            let result: SessionProposeResult = serde_json::from_value(result)?;
+            let mut responder = [0u8; 32];
+            hex::decode_to_slice(&result.responder_public_key, &mut responder).unwrap();
+            let topic = crypto.generate_shared_key(&self_public, &responder)?;
+            context.relayer().subscribe(&topic).await?;
+
+            loop {
+                let PeerRequest { topic: on, request, .. } = requests.recv().await.unwrap();
+                if on != topic || request.method != WC_SESSION_SETTLE {
+                    continue;
+                }
+                let settle: SessionSettleParams = serde_json::from_value(request.params)?;
+                let session = Session {
+                    topic: topic.clone(),
+                    pairing_topic,
+                    relay: settle.relay,
+                    expiry: settle.expiry * 1000,
+                    acknowledged: true,
+                    controller: hex::encode(settle.controller.public_key),
+                    namespaces: settle.namespaces,
+                    required_namespaces,
+                    this: params.proposer,
+                    peer: settle.controller,
+                };
+                Sessions::new(&context)?.set(&session)?;
+                peer.respond(&topic, request.id, true).await?;
+                return Ok(session);
+            }
+        });
+        (uri.into_owned(), handle)
+    }
+}
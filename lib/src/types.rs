@@ -4,6 +4,8 @@ use std::{borrow::Cow, cmp::Ord, fmt};
 use const_format::concatcp;
 use speedy::{Readable, Writable};
 
+use crate::rpc::{transport::ConnectionHealth, types::payload::Request};
+
 pub const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 pub const TYPE_NAME: &str = concatcp!("{PKG_NAME}-TOPIC");
 
@@ -21,10 +23,111 @@ pub struct Metadata {
     */
 }
 
-#[derive(Clone, Copy)]
-pub enum GlobalEvent {
+/// An event on the event bus
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlobalEvent {
+    /// millisecond timestamp the event was emitted at
+    pub timestamp: i64,
+    pub payload: EventPayload,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventPayload {
     Pairing(super::pairing::PairingEvent),
+    Session(super::session::SessionEvent),
     Expiration(super::expirations::ExpirationEvent),
+    Relay(RelayEvent),
+}
+
+/// The state of the websocket to the relay
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelayEvent {
+    Connect,
+    Disconnect {
+        reason: String,
+    },
+    /// Sent on every heartbeat, and whenever the connection is lost or opened again
+    Health(ConnectionHealth),
+}
+
+impl From<RelayEvent> for EventPayload {
+    fn from(event: RelayEvent) -> EventPayload {
+        EventPayload::Relay(event)
+    }
+}
+
+/// The kind of a [`GlobalEvent`], to filter subscriptions by
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GlobalEventKind {
+    PairingCreate,
+    PairingExpire,
+    PairingDelete,
+    PairingPing,
+    PairingRestore,
+    SessionProposal,
+    SessionSettle,
+    SessionUpdate,
+    SessionExtend,
+    SessionRequest,
+    SessionEvent,
+    SessionPing,
+    SessionDelete,
+    SessionExpire,
+    SessionRestore,
+    Expiration,
+    RelayConnect,
+    RelayDisconnect,
+    RelayHealth,
+}
+
+impl GlobalEvent {
+    pub fn new(payload: impl Into<EventPayload>) -> Self {
+        Self { timestamp: chrono::Utc::now().timestamp_millis(), payload: payload.into() }
+    }
+
+    pub fn kind(&self) -> GlobalEventKind {
+        use super::{pairing::PairingEvent as P, session::SessionEvent as S};
+        match &self.payload {
+            EventPayload::Pairing(P::Create { .. }) => GlobalEventKind::PairingCreate,
+            EventPayload::Pairing(P::Expire { .. }) => GlobalEventKind::PairingExpire,
+            EventPayload::Pairing(P::Delete { .. }) => GlobalEventKind::PairingDelete,
+            EventPayload::Pairing(P::Ping { .. }) => GlobalEventKind::PairingPing,
+            EventPayload::Pairing(P::Restore { .. }) => GlobalEventKind::PairingRestore,
+            EventPayload::Session(S::Proposal { .. }) => GlobalEventKind::SessionProposal,
+            EventPayload::Session(S::Settle { .. }) => GlobalEventKind::SessionSettle,
+            EventPayload::Session(S::Update { .. }) => GlobalEventKind::SessionUpdate,
+            EventPayload::Session(S::Extend { .. }) => GlobalEventKind::SessionExtend,
+            EventPayload::Session(S::Request { .. }) => GlobalEventKind::SessionRequest,
+            EventPayload::Session(S::Event { .. }) => GlobalEventKind::SessionEvent,
+            EventPayload::Session(S::Ping { .. }) => GlobalEventKind::SessionPing,
+            EventPayload::Session(S::Delete { .. }) => GlobalEventKind::SessionDelete,
+            EventPayload::Session(S::Expire { .. }) => GlobalEventKind::SessionExpire,
+            EventPayload::Session(S::Restore { .. }) => GlobalEventKind::SessionRestore,
+            EventPayload::Expiration(_) => GlobalEventKind::Expiration,
+            EventPayload::Relay(RelayEvent::Connect) => GlobalEventKind::RelayConnect,
+            EventPayload::Relay(RelayEvent::Disconnect { .. }) => GlobalEventKind::RelayDisconnect,
+            EventPayload::Relay(RelayEvent::Health(_)) => GlobalEventKind::RelayHealth,
+        }
+    }
+
+    /// The topic the event happened on, if any
+    pub fn topic(&self) -> Option<&Topic<'static>> {
+        match &self.payload {
+            EventPayload::Pairing(event) => Some(event.topic()),
+            EventPayload::Session(event) => Some(event.topic()),
+            EventPayload::Expiration(event) => Some(event.topic()),
+            EventPayload::Relay(_) => None,
+        }
+    }
+
+    /// The JSON-RPC request received from the peer that caused the event, if any
+    pub fn request(&self) -> Option<&Request> {
+        match &self.payload {
+            EventPayload::Pairing(event) => event.request(),
+            EventPayload::Session(event) => event.request(),
+            EventPayload::Expiration(_) | EventPayload::Relay(_) => None,
+        }
+    }
 }
 
 /// A Topic, by default the sha256 hash of the symmetric key
@@ -36,6 +139,7 @@ pub enum GlobalEvent {
     Eq,
     PartialOrd,
     Ord,
+    Hash,
     Clone,
     Debug,
     serde::Serialize,
@@ -51,6 +155,10 @@ impl<'a> Topic<'a> {
     pub fn into_owned(self) -> Topic<'static> {
         Topic(self.0.into_owned().into())
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 impl redb::Value for Topic<'static> {
@@ -169,18 +277,15 @@ impl redb::Value for &'static Topic<'_> {
 */
 
 impl redb::Key for Topic<'static> {
+    // keys are stored as the bare UTF-8 of the topic, which orders the same as the `str`
     fn compare(data1: &[u8], data2: &[u8]) -> std::cmp::Ordering {
-        let topic1 = Topic::read_from_buffer(data1).unwrap();
-        let topic2 = Topic::read_from_buffer(data2).unwrap();
-        topic1.cmp(&topic2)
+        data1.cmp(data2)
     }
 }
 
 impl redb::Key for &'static Topic<'static> {
     fn compare(data1: &[u8], data2: &[u8]) -> std::cmp::Ordering {
-        let topic1 = Topic::read_from_buffer(data1).unwrap();
-        let topic2 = Topic::read_from_buffer(data2).unwrap();
-        topic1.cmp(&topic2)
+        data1.cmp(data2)
     }
 }
 
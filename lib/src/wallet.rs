@@ -0,0 +1,572 @@
+//! The wallet side of the Sign protocol. A wallet pairs with a dapp from the URI it shows,
+//! receives its `wc_sessionPropose`, and settles a session by deriving a symmetric key from its
+//! own X25519 keypair and the proposer's public key. Requests the dapp then sends on the session
+//! are surfaced as [`WalletEvent::SessionRequest`] and answered with [`WalletClient::respond`].
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::Utc;
+use tokio::sync::{broadcast, broadcast::error::RecvError};
+
+use crate::{
+    crypto::Crypto,
+    error::WalletError,
+    events::GlobalEvents,
+    expirations::ExpiryManager,
+    pairing::{Pairing, PairingUri},
+    peer::{PeerRequest, PeerRpc},
+    relayer::Relayer,
+    rpc::types::{
+        payload::{ErrorData, NO_MATCHING_KEY, USER_DISCONNECTED},
+        AccountId, DeleteParams, Metadata, NamespaceMap, Participant, Relay, SessionProposeParams,
+        SessionProposeResult, SessionRequestParams, SessionSettleParams, VerifyContext,
+        WC_SESSION_DELETE, WC_SESSION_PING, WC_SESSION_PROPOSE, WC_SESSION_REQUEST,
+        WC_SESSION_SETTLE,
+    },
+    session::{Session, SessionEvent, Sessions, SESSION_TTL},
+    supervisor::Supervisor,
+    types::Topic,
+    WalletConnect, MESSAGE_CAPACITY,
+};
+
+pub type Result<T> = std::result::Result<T, WalletError>;
+
+/// A session proposed by a dapp, waiting to be approved or rejected
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionProposal {
+    /// id of the `wc_sessionPropose` request
+    pub id: u64,
+    pub pairing_topic: Topic<'static>,
+    pub params: SessionProposeParams,
+    /// The verified origin of the dapp, if a verify server is configured
+    pub verify_context: Option<VerifyContext>,
+}
+
+/// A `wc_sessionRequest` received on a settled session, waiting for a response
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionRequest {
+    pub id: u64,
+    pub topic: Topic<'static>,
+    pub params: SessionRequestParams,
+    pub verify_context: Option<VerifyContext>,
+}
+
+/// Events emitted by a [`WalletClient`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum WalletEvent {
+    SessionProposal(Box<SessionProposal>),
+    /// The dapp acknowledged the `wc_sessionSettle` of an approved session
+    SessionSettled(Box<Session>),
+    SessionRequest(Box<SessionRequest>),
+    /// The dapp deleted the session on `topic`
+    SessionDeleted {
+        topic: Topic<'static>,
+        reason: DeleteParams,
+    },
+}
+
+#[derive(Clone)]
+pub struct WalletClient {
+    crypto: Arc<Crypto>,
+    sessions: Arc<Sessions>,
+    pairing: Arc<Pairing>,
+    peer: PeerRpc,
+    /// subscribes to the topics of settled sessions
+    listener: Arc<Relayer>,
+    proposals: Arc<Mutex<HashMap<u64, SessionProposal>>>,
+    events: broadcast::Sender<WalletEvent>,
+    /// the event bus of the context
+    bus: GlobalEvents,
+    tasks: Supervisor,
+    metadata: Metadata,
+}
+
+impl WalletClient {
+    pub fn new(context: &WalletConnect, metadata: Metadata) -> Result<Self> {
+        let crypto = Arc::new(Crypto::new(context)?);
+        let listener = context.relayer().clone();
+        let pairing = Pairing::with_parts(
+            context,
+            crypto.clone(),
+            listener.clone(),
+            Arc::new(ExpiryManager::new(context)?),
+            context.events().clone(),
+        )?;
+        let pairing = Arc::new(pairing);
+        let peer = PeerRpc::new(context)?;
+        Self::with_parts(context, crypto, pairing, peer, listener, metadata)
+    }
+
+    /// Create a wallet sharing its crypto, pairing, peer and relayer with other components
+    pub(crate) fn with_parts(
+        context: &WalletConnect,
+        crypto: Arc<Crypto>,
+        pairing: Arc<Pairing>,
+        peer: PeerRpc,
+        listener: Arc<Relayer>,
+        metadata: Metadata,
+    ) -> Result<Self> {
+        let (events, _) = broadcast::channel(MESSAGE_CAPACITY);
+        let wallet = Self {
+            crypto,
+            sessions: Arc::new(Sessions::new(context)?),
+            pairing,
+            peer,
+            listener,
+            proposals: Default::default(),
+            events,
+            bus: context.events().clone(),
+            tasks: context.tasks().clone(),
+            metadata,
+        };
+
+        let mut requests = wallet.peer.requests();
+        let this = wallet.clone();
+        wallet.tasks.spawn(async move {
+            loop {
+                match requests.recv().await {
+                    Ok(request) => {
+                        if let Err(e) = this.handle_request(request).await {
+                            log::warn!("Failed to handle request: {e}");
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => log::warn!("WalletClient lagged by {n} requests"),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        Ok(wallet)
+    }
+
+    /// Subscribe to session proposals, settlements, requests and deletions
+    pub fn events(&self) -> broadcast::Receiver<WalletEvent> {
+        self.events.subscribe()
+    }
+
+    /// Pair with a dapp from its URI, then handle the proposal it may have already sent
+    pub async fn pair(&self, uri: PairingUri<'static>) -> Result<Topic<'static>> {
+        let topic = uri.topic.clone();
+        self.pairing.pair(uri, false, false).await?;
+        self.peer.drain_mailbox(std::slice::from_ref(&topic)).await?;
+        Ok(topic)
+    }
+
+    /// Proposals waiting to be approved or rejected
+    pub fn proposals(&self) -> Vec<SessionProposal> {
+        self.proposals.lock().expect("proposals lock poisoned").values().cloned().collect()
+    }
+
+    /// Approve the proposal `id` with `namespaces`, adding `accounts` to the namespace of their
+    /// chain. The session is persisted right away, and acknowledged once the dapp responds to
+    /// `wc_sessionSettle`. A proposal past its expiry is dropped instead.
+    pub async fn approve(
+        &self,
+        id: u64,
+        namespaces: NamespaceMap,
+        accounts: Vec<AccountId>,
+    ) -> Result<Session> {
+        let proposal = self.proposal(id)?;
+        if proposal.params.expiry_timestamp.is_some_and(|expiry| expiry <= Utc::now().timestamp()) {
+            self.remove_proposal(id);
+            return Err(WalletError::ExpiredProposal(id));
+        }
+        let namespaces = session_namespaces(&proposal.params, namespaces, accounts)?;
+        let proposer = proposal.params.proposer.clone();
+
+        let self_public = self.crypto.generate_keypair()?;
+        let topic = self.crypto.generate_shared_key(&self_public, &proposer.public_key)?;
+        self.listener.subscribe(&topic).await?;
+
+        let relay = Relay::new("irn", None::<&str>);
+        let result = SessionProposeResult {
+            relay: relay.clone(),
+            responder_public_key: hex::encode(self_public),
+        };
+        if let Err(e) = self.peer.respond(&proposal.pairing_topic, id, result).await {
+            // the dapp never learns the session topic, so forget it and keep the proposal
+            self.listener.unsubscribe(&topic).await?;
+            self.crypto.delete_symkey(topic)?;
+            self.crypto.delete_symkey(hex::encode(self_public).into())?;
+            return Err(e.into());
+        }
+        self.remove_proposal(id);
+
+        let expiry = Utc::now() + SESSION_TTL;
+        let controller = Participant::new(self_public, self.metadata.clone());
+        let session = Session {
+            topic: topic.clone(),
+            pairing_topic: proposal.pairing_topic,
+            relay: relay.clone(),
+            expiry: expiry.timestamp_millis(),
+            acknowledged: false,
+            controller: hex::encode(self_public),
+            namespaces: namespaces.clone(),
+            required_namespaces: proposal.params.required_namespaces,
+            this: controller.clone(),
+            peer: proposer,
+        };
+        self.sessions.set(&session)?;
+
+        let settle = SessionSettleParams {
+            relay,
+            controller,
+            namespaces,
+            session_properties: proposal.params.session_properties,
+            expiry: expiry.timestamp(),
+        };
+        let this = self.clone();
+        self.tasks.spawn(async move {
+            if let Err(e) = this.settle(&topic, settle).await {
+                log::warn!("Session {topic} was not acknowledged: {e}");
+            }
+        });
+
+        Ok(session)
+    }
+
+    /// Reject the proposal `id`
+    pub async fn reject(&self, id: u64, reason: ErrorData) -> Result<()> {
+        let proposal = self.proposal(id)?;
+        self.peer.respond_error(&proposal.pairing_topic, id, reason).await?;
+        self.remove_proposal(id);
+        Ok(())
+    }
+
+    /// Respond to the session request `id` received on `topic`
+    pub async fn respond(
+        &self,
+        topic: &Topic<'static>,
+        id: u64,
+        result: serde_json::Value,
+    ) -> Result<()> {
+        self.peer.respond(topic, id, result).await?;
+        Ok(())
+    }
+
+    /// Respond to the session request `id` received on `topic` with an error
+    pub async fn respond_error(
+        &self,
+        topic: &Topic<'static>,
+        id: u64,
+        error: ErrorData,
+    ) -> Result<()> {
+        self.peer.respond_error(topic, id, error).await?;
+        Ok(())
+    }
+
+    /// Delete the session on `topic`. The dapp is sent `wc_sessionDelete`, but its response is
+    /// not awaited since the topic is unsubscribed right away.
+    pub async fn disconnect(&self, topic: &Topic<'static>) -> Result<()> {
+        let reason = DeleteParams { code: USER_DISCONNECTED, message: "User disconnected.".into() };
+        self.peer.send(topic, WC_SESSION_DELETE, reason).await?;
+        self.remove_session(topic).await
+    }
+
+    async fn remove_session(&self, topic: &Topic<'static>) -> Result<()> {
+        self.sessions.delete(topic)?;
+        self.listener.unsubscribe(topic).await?;
+        Ok(())
+    }
+
+    async fn settle(&self, topic: &Topic<'static>, params: SessionSettleParams) -> Result<()> {
+        self.peer.request(topic, WC_SESSION_SETTLE, params).await?;
+        if let Some(mut session) = self.sessions.get(topic)? {
+            session.acknowledged = true;
+            self.sessions.set(&session)?;
+            self.bus.emit(SessionEvent::Settle {
+                topic: topic.clone(),
+                request: None,
+                peer: session.peer.clone(),
+                namespaces: session.namespaces.clone(),
+                expiry: session.expiry,
+            });
+            let _ = self.events.send(WalletEvent::SessionSettled(Box::new(session)));
+        }
+        Ok(())
+    }
+
+    async fn handle_request(&self, request: PeerRequest) -> Result<()> {
+        let PeerRequest { topic, request, verify_context } = request;
+        match request.method.as_str() {
+            WC_SESSION_PROPOSE => {
+                let params: SessionProposeParams = serde_json::from_value(request.params)?;
+                let proposal = SessionProposal {
+                    id: request.id,
+                    pairing_topic: topic,
+                    params,
+                    verify_context,
+                };
+                self.proposals
+                    .lock()
+                    .expect("proposals lock poisoned")
+                    .insert(request.id, proposal.clone());
+                let _ = self.events.send(WalletEvent::SessionProposal(Box::new(proposal)));
+            }
+            WC_SESSION_REQUEST => {
+                if self.sessions.get(&topic)?.is_none() {
+                    log::warn!("Rejecting request {} on unknown session {topic}", request.id);
+                    let reason = ErrorData::new(NO_MATCHING_KEY, format!("No session on {topic}."));
+                    self.peer.respond_error(&topic, request.id, reason).await?;
+                    return Ok(());
+                }
+                let params: SessionRequestParams = serde_json::from_value(request.params)?;
+                let request = SessionRequest { id: request.id, topic, params, verify_context };
+                let _ = self.events.send(WalletEvent::SessionRequest(Box::new(request)));
+            }
+            WC_SESSION_DELETE => {
+                let reason: DeleteParams = serde_json::from_value(request.params)?;
+                self.peer.respond(&topic, request.id, true).await?;
+                self.remove_session(&topic).await?;
+                let _ = self.events.send(WalletEvent::SessionDeleted { topic, reason });
+            }
+            WC_SESSION_PING => self.peer.respond(&topic, request.id, true).await?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn proposal(&self, id: u64) -> Result<SessionProposal> {
+        let proposals = self.proposals.lock().expect("proposals lock poisoned");
+        proposals.get(&id).cloned().ok_or(WalletError::UnknownProposal(id))
+    }
+
+    fn remove_proposal(&self, id: u64) {
+        self.proposals.lock().expect("proposals lock poisoned").remove(&id);
+    }
+}
+
+/// Add `accounts` to the namespace of their chain, and check that the result satisfies every
+/// namespace the dapp requires
+pub fn session_namespaces(
+    proposal: &SessionProposeParams,
+    mut namespaces: NamespaceMap,
+    accounts: Vec<AccountId>,
+) -> Result<NamespaceMap> {
+    for account in accounts {
+        let chain = account.chain_id().to_string();
+        let namespace = namespaces
+            .get_mut(account.chain_id().namespace())
+            .ok_or_else(|| WalletError::UnsupportedAccount(account.to_string()))?;
+        if !namespace.chains.contains(&chain) {
+            namespace.chains.push(chain);
+        }
+        if !namespace.accounts.contains(&account) {
+            namespace.accounts.push(account);
+        }
+    }
+
+    for (key, required) in &proposal.required_namespaces {
+        let unsatisfied = || WalletError::UnsatisfiedNamespace(key.clone());
+        // a required namespace can be keyed by a single chain, i.e `eip155:1`
+        let (name, chains) = match key.split_once(':') {
+            Some((name, _)) if required.chains.is_empty() => (name, vec![key.clone()]),
+            _ => (key.as_str(), required.chains.clone()),
+        };
+        let namespace = namespaces.get(name).ok_or_else(unsatisfied)?;
+        if !chains.iter().all(|chain| namespace.chains.contains(chain))
+            || !required.methods.iter().all(|method| namespace.methods.contains(method))
+            || !required.events.iter().all(|event| namespace.events.contains(event))
+        {
+            return Err(unsatisfied());
+        }
+    }
+    Ok(namespaces)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        error::PeerError,
+        rpc::{
+            transport::LoopbackRelay,
+            types::{payload::USER_REJECTED, ChainId, Namespace},
+        },
+        testing::{self, Dapp},
+    };
+
+    fn namespace(chains: &[&str], methods: &[&str]) -> Namespace {
+        Namespace {
+            chains: chains.iter().map(ToString::to_string).collect(),
+            methods: methods.iter().map(ToString::to_string).collect(),
+            events: vec![],
+            accounts: vec![],
+        }
+    }
+
+    #[test]
+    fn test_session_namespaces() {
+        let proposal = SessionProposeParams {
+            relays: vec![Relay::new("irn", None::<&str>)],
+            proposer: Participant::new([1u8; 32], Metadata::default()),
+            required_namespaces: HashMap::from([(
+                "eip155".to_string(),
+                namespace(&["eip155:1"], &["personal_sign"]),
+            )]),
+            optional_namespaces: HashMap::new(),
+            session_properties: None,
+            expiry_timestamp: None,
+        };
+        let account = AccountId::new(
+            ChainId::new("eip155", "1"),
+            "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+        );
+
+        let approved = HashMap::from([(
+            "eip155".to_string(),
+            namespace(&[], &["personal_sign", "eth_sendTransaction"]),
+        )]);
+        let namespaces =
+            session_namespaces(&proposal, approved.clone(), vec![account.clone()]).unwrap();
+        assert_eq!(namespaces["eip155"].chains, vec!["eip155:1".to_string()]);
+        assert_eq!(namespaces["eip155"].accounts, vec![account.clone()]);
+
+        // the required chain has no account
+        assert!(matches!(
+            session_namespaces(&proposal, approved, vec![]),
+            Err(WalletError::UnsatisfiedNamespace(_))
+        ));
+
+        let solana = AccountId::new(ChainId::new("solana", "mainnet"), "address");
+        let approved = HashMap::from([("eip155".to_string(), namespace(&[], &["personal_sign"]))]);
+        assert!(matches!(
+            session_namespaces(&proposal, approved, vec![account, solana]),
+            Err(WalletError::UnsupportedAccount(_))
+        ));
+    }
+
+    fn required() -> NamespaceMap {
+        HashMap::from([("eip155".to_string(), namespace(&["eip155:1"], &["personal_sign"]))])
+    }
+
+    fn account() -> AccountId {
+        AccountId::new(ChainId::new("eip155", "1"), "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")
+    }
+
+    async fn proposal(events: &mut broadcast::Receiver<WalletEvent>) -> SessionProposal {
+        match events.recv().await.unwrap() {
+            WalletEvent::SessionProposal(proposal) => *proposal,
+            event => panic!("expected a session proposal, got {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_approve() {
+        let relay = LoopbackRelay::new();
+        let (dapp, context) = (Dapp::new(&relay), testing::context(&relay));
+        let wallet = WalletClient::new(&context, Metadata::default()).unwrap();
+        let mut events = wallet.events();
+
+        let (uri, handle) = dapp.propose(required(), None).await;
+        let pairing_topic = wallet.pair(uri).await.unwrap();
+        let proposal = proposal(&mut events).await;
+        assert_eq!(proposal.pairing_topic, pairing_topic);
+        assert_eq!(proposal.params.required_namespaces, required());
+        assert_eq!(wallet.proposals(), vec![proposal.clone()]);
+
+        let approved = HashMap::from([("eip155".to_string(), namespace(&[], &["personal_sign"]))]);
+        let session = wallet.approve(proposal.id, approved, vec![account()]).await.unwrap();
+        assert!(!session.acknowledged);
+        assert!(wallet.proposals().is_empty());
+        assert!(context.relayer().is_subscribed(&session.topic));
+
+        // the dapp derives the same topic and acknowledges the settlement
+        let settled = handle.await.unwrap().unwrap();
+        assert_eq!(settled.topic, session.topic);
+        assert_eq!(settled.namespaces, session.namespaces);
+        assert_eq!(settled.namespaces["eip155"].accounts, vec![account()]);
+        let WalletEvent::SessionSettled(acknowledged) = events.recv().await.unwrap() else {
+            panic!("expected the session to be settled");
+        };
+        assert!(acknowledged.acknowledged);
+        assert_eq!(wallet.sessions.get(&session.topic).unwrap(), Some(*acknowledged));
+    }
+
+    #[tokio::test]
+    async fn test_reject() {
+        let relay = LoopbackRelay::new();
+        let (dapp, context) = (Dapp::new(&relay), testing::context(&relay));
+        let wallet = WalletClient::new(&context, Metadata::default()).unwrap();
+        let mut events = wallet.events();
+
+        let (uri, handle) = dapp.propose(required(), None).await;
+        wallet.pair(uri).await.unwrap();
+        let proposal = proposal(&mut events).await;
+        let reason = ErrorData::new(USER_REJECTED, "User rejected.");
+        wallet.reject(proposal.id, reason.clone()).await.unwrap();
+        assert!(wallet.proposals().is_empty());
+        assert!(matches!(handle.await.unwrap(), Err(PeerError::Response(e)) if e == reason));
+        assert!(matches!(
+            wallet.approve(proposal.id, required(), vec![account()]).await,
+            Err(WalletError::UnknownProposal(_))
+        ));
+        assert!(wallet.sessions.all().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_approve_expired() {
+        let relay = LoopbackRelay::new();
+        let (dapp, context) = (Dapp::new(&relay), testing::context(&relay));
+        let wallet = WalletClient::new(&context, Metadata::default()).unwrap();
+        let mut events = wallet.events();
+
+        let expiry = Utc::now().timestamp() - 1;
+        let (uri, _handle) = dapp.propose(required(), Some(expiry)).await;
+        wallet.pair(uri).await.unwrap();
+        let proposal = proposal(&mut events).await;
+        assert!(matches!(
+            wallet.approve(proposal.id, required(), vec![account()]).await,
+            Err(WalletError::ExpiredProposal(id)) if id == proposal.id
+        ));
+        assert!(wallet.proposals().is_empty());
+        assert!(wallet.sessions.all().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_approve_failed_response() {
+        let relay = LoopbackRelay::new();
+        let (dapp, context) = (Dapp::new(&relay), testing::context(&relay));
+        let wallet = WalletClient::new(&context, Metadata::default()).unwrap();
+        let mut events = wallet.events();
+
+        let (uri, _handle) = dapp.propose(required(), None).await;
+        let pairing_topic = wallet.pair(uri).await.unwrap();
+        let proposal = proposal(&mut events).await;
+
+        // without the pairing key the response cannot be sent
+        wallet.crypto.delete_symkey(pairing_topic.clone()).unwrap();
+        let approved = HashMap::from([("eip155".to_string(), namespace(&[], &["personal_sign"]))]);
+        assert!(matches!(
+            wallet.approve(proposal.id, approved, vec![account()]).await,
+            Err(WalletError::Peer(_))
+        ));
+        assert_eq!(context.relayer().topics(), vec![pairing_topic]);
+        assert_eq!(wallet.proposals(), vec![proposal]);
+        assert!(wallet.sessions.all().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_request_unknown_session() {
+        let relay = LoopbackRelay::new();
+        let (dapp, context) = (Dapp::new(&relay), testing::context(&relay));
+        let _wallet = WalletClient::new(&context, Metadata::default()).unwrap();
+        let topic = Crypto::new(&dapp.context).unwrap().set_symkey([9u8; 32], None).unwrap();
+        Crypto::new(&context).unwrap().set_symkey([9u8; 32], Some(&topic)).unwrap();
+        dapp.context.relayer().subscribe(&topic).await.unwrap();
+        context.relayer().subscribe(&topic).await.unwrap();
+
+        let result = dapp
+            .peer
+            .request_with_timeout(&topic, WC_SESSION_REQUEST, json!({}), Duration::from_secs(5))
+            .await;
+        assert!(matches!(result, Err(PeerError::Response(e)) if e.code == NO_MATCHING_KEY));
+    }
+}
@@ -0,0 +1,247 @@
+//! Relay webhooks, for server-side components which receive messages over HTTP instead of holding
+//! a websocket. Events the relay posts to the webhook are verified and fed into the
+//! [`WalletConnect`] inbound message channel, as if the relay had delivered them on a
+//! subscription.
+
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use chrono::Utc;
+use ed25519_dalek::VerifyingKey;
+use tokio::net::TcpListener;
+
+use crate::{
+    error::WatchError,
+    rpc::{
+        auth::{decode_jwt, encode_public_key_as_did},
+        types::{
+            Watch, WatchEventClaims, WatchEventPayload, WatchType, WatchWebhookPayload, WATCH_EVENT,
+        },
+    },
+    WalletConnect,
+};
+
+pub type Result<T> = std::result::Result<T, WatchError>;
+
+/// A webhook registered with the relay
+pub struct Webhook {
+    /// the context whose websocket registered the webhook, and whose inbound channel receives
+    /// its messages
+    context: WalletConnect,
+    watch: Watch,
+    /// key the relay signs events with
+    relay_key: VerifyingKey,
+    /// did:key of our relay key, the audience of the events
+    client_id: String,
+}
+
+impl Webhook {
+    /// Register `watch` with the relay
+    pub async fn register(context: &WalletConnect, watch: Watch) -> Result<Self> {
+        let rpc = context.client().ok_or(WatchError::NoWebsocket)?;
+        let relay_key = rpc.watch_register(&watch).await?;
+        let client_id = encode_public_key_as_did(&rpc.public_key());
+        Ok(Self { context: context.clone(), watch, relay_key, client_id })
+    }
+
+    /// Unregister the webhook. The relay stops posting to it.
+    pub async fn unregister(&self) -> Result<()> {
+        // the connection that registered the webhook may have been replaced since, the key it
+        // was registered with is the same
+        let rpc = self.context.client().ok_or(WatchError::NoWebsocket)?;
+        rpc.watch_unregister(&self.watch).await?;
+        Ok(())
+    }
+
+    pub fn watch(&self) -> &Watch {
+        &self.watch
+    }
+
+    /// Verify the events of a webhook request and feed the messages of subscriber watches into
+    /// the inbound message channel. Returns the number of events.
+    pub fn handle(&self, payload: WatchWebhookPayload) -> Result<usize> {
+        let events = payload
+            .event_auth
+            .iter()
+            .map(|token| {
+                verify_event(
+                    token,
+                    &self.relay_key,
+                    &self.client_id,
+                    &self.watch,
+                    Utc::now().timestamp(),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let count = events.len();
+        if self.watch.typ == WatchType::Subscriber {
+            for event in events {
+                let _ = self.context.messages.send(event.into());
+            }
+        }
+        Ok(count)
+    }
+
+    /// A router receiving the relay's requests on the path of the webhook URL
+    pub fn router(self: Arc<Self>) -> Router {
+        let path = self.watch.webhook_url.path().to_string();
+        Router::new().route(&path, post(receive)).with_state(self)
+    }
+
+    /// Receive the relay's requests on `listener` until the server fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+}
+
+async fn receive(
+    State(webhook): State<Arc<Webhook>>,
+    Json(payload): Json<WatchWebhookPayload>,
+) -> StatusCode {
+    match webhook.handle(payload) {
+        Ok(_) => StatusCode::OK,
+        Err(e) => {
+            log::warn!("Rejecting webhook request: {e}");
+            StatusCode::UNAUTHORIZED
+        }
+    }
+}
+
+/// Verify an event posted by the relay for `watch`, registered by the client `client_id`, at the
+/// timestamp `now`, in seconds
+pub fn verify_event(
+    token: &str,
+    relay_key: &VerifyingKey,
+    client_id: &str,
+    watch: &Watch,
+    now: i64,
+) -> Result<WatchEventPayload> {
+    let claims: WatchEventClaims = decode_jwt(token, relay_key)?;
+    if claims.act != WATCH_EVENT {
+        return Err(WatchError::InvalidEvent("act"));
+    }
+    if claims.basic.aud != client_id {
+        return Err(WatchError::InvalidEvent("aud"));
+    }
+    if claims.typ != watch.typ {
+        return Err(WatchError::InvalidEvent("typ"));
+    }
+    if claims.whu != watch.webhook_url.as_str() {
+        return Err(WatchError::InvalidEvent("whu"));
+    }
+    if claims.basic.exp.is_some_and(|exp| exp < now) {
+        return Err(WatchError::InvalidEvent("exp"));
+    }
+    Ok(claims.evt)
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use url::Url;
+
+    use super::*;
+    use crate::{
+        rpc::{
+            auth::{encode_jwt, JwtBasicClaims},
+            transport::LoopbackRelay,
+            types::WatchStatus,
+        },
+        testing,
+    };
+
+    const CLIENT_ID: &str = "did:key:client";
+
+    fn event(relay: &SigningKey, watch: &Watch, act: &str, aud: &str, exp: i64) -> String {
+        let claims = WatchEventClaims {
+            basic: JwtBasicClaims {
+                iss: "did:key:relay".into(),
+                aud: aud.into(),
+                sub: String::new(),
+                iat: exp - 60,
+                exp: Some(exp),
+            },
+            act: act.into(),
+            typ: watch.typ,
+            whu: watch.webhook_url.to_string(),
+            evt: WatchEventPayload {
+                status: WatchStatus::Queued,
+                topic: "topic".into(),
+                message: "message".into(),
+                published_at: 0,
+                tag: 1108,
+            },
+        };
+        encode_jwt(relay, &claims).unwrap()
+    }
+
+    fn watch() -> Watch {
+        Watch::subscriber(
+            Url::parse("https://service.example/webhook").unwrap(),
+            "https://service.example",
+            vec![1108],
+        )
+    }
+
+    #[test]
+    fn test_verify_event() {
+        let relay = SigningKey::generate(&mut rand::thread_rng());
+        let watch = watch();
+        let relay_key = relay.verifying_key();
+
+        let valid = event(&relay, &watch, WATCH_EVENT, CLIENT_ID, 100);
+        assert_eq!(verify_event(&valid, &relay_key, CLIENT_ID, &watch, 50).unwrap().topic, "topic");
+        assert!(verify_event(&valid, &relay_key, CLIENT_ID, &watch, 101).is_err());
+
+        let wrong_act = event(&relay, &watch, "irn_publish", CLIENT_ID, 100);
+        assert!(verify_event(&wrong_act, &relay_key, CLIENT_ID, &watch, 50).is_err());
+
+        let other_client = event(&relay, &watch, WATCH_EVENT, "did:key:other", 100);
+        assert!(matches!(
+            verify_event(&other_client, &relay_key, CLIENT_ID, &watch, 50),
+            Err(WatchError::InvalidEvent("aud"))
+        ));
+
+        let other = SigningKey::generate(&mut rand::thread_rng());
+        let forged = event(&other, &watch, WATCH_EVENT, CLIENT_ID, 100);
+        assert!(verify_event(&forged, &relay_key, CLIENT_ID, &watch, 50).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        let context = testing::context(&LoopbackRelay::new());
+        let mut messages = context.messages.subscribe();
+
+        let relay = SigningKey::generate(&mut rand::thread_rng());
+        let watch = watch();
+        let webhook = Arc::new(Webhook {
+            context: context.clone(),
+            watch: watch.clone(),
+            relay_key: relay.verifying_key(),
+            client_id: CLIENT_ID.into(),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/webhook", listener.local_addr().unwrap());
+        tokio::spawn(webhook.serve(listener));
+
+        let exp = Utc::now().timestamp() + 60;
+        let http = reqwest::Client::new();
+        let forged = SigningKey::generate(&mut rand::thread_rng());
+        let payload = WatchWebhookPayload {
+            event_auth: vec![event(&forged, &watch, WATCH_EVENT, CLIENT_ID, exp)],
+        };
+        let response = http.post(&url).json(&payload).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        let payload = WatchWebhookPayload {
+            event_auth: vec![event(&relay, &watch, WATCH_EVENT, CLIENT_ID, exp)],
+        };
+        let response = http.post(&url).json(&payload).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        let message = messages.recv().await.unwrap();
+        assert_eq!((message.topic.as_str(), message.message.as_str()), ("topic", "message"));
+        assert!(messages.try_recv().is_err());
+    }
+}
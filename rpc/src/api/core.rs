@@ -1,15 +1,15 @@
 // mod crypto;
-// mod identity;
+mod identity;
 mod pairing;
 mod relay;
 // mod storage;
 // mod sync;
-// mod verify;
+mod verify;
 
 // pub use self::crypto::*;
-// pub use self::identity::*;
+pub use self::identity::*;
 pub use self::pairing::*;
 pub use self::relay::*;
 // pub use self::storage::*;
 // pub use self::sync::*;
-// pub use self::verify::*;
+pub use self::verify::*;
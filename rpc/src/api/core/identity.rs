@@ -0,0 +1,145 @@
+//! HTTP client of the keys server, which binds identity keys to blockchain accounts.
+
+use chrono::{SecondsFormat, Utc};
+use ed25519_dalek::SigningKey;
+use rand::Rng as _;
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::{
+    auth::{encode_jwt, encode_key_as_did},
+    error::IdentityError,
+    types::{identity::*, AccountId, Cacao, CacaoPayload, DID_PKH_PREFIX},
+};
+
+/// How long the JWT unregistering an identity key is valid for
+pub const UNREGISTER_TTL: chrono::Duration = chrono::Duration::hours(1);
+
+#[derive(Debug, Clone)]
+pub struct KeysClient {
+    url: Url,
+    http: reqwest::Client,
+}
+
+impl Default for KeysClient {
+    fn default() -> Self {
+        Self::new(Url::parse(KEYS_SERVER_URL).expect("Static URL is correct"))
+    }
+}
+
+impl KeysClient {
+    /// Create a client of the keys server at `url`
+    pub fn new(url: Url) -> Self {
+        Self { url, http: reqwest::Client::new() }
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The CACAO payload `account` signs to bind `identity_key` to it, on behalf of the app at
+    /// `domain`
+    pub fn identity_payload(
+        &self,
+        account: &AccountId,
+        identity_key: &SigningKey,
+        domain: &str,
+        statement: &str,
+    ) -> CacaoPayload {
+        let nonce: u64 = rand::thread_rng().gen();
+        CacaoPayload {
+            domain: domain.into(),
+            aud: self.audience(),
+            version: "1".into(),
+            nonce: nonce.to_string(),
+            iat: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            iss: format!("{DID_PKH_PREFIX}{account}"),
+            nbf: None,
+            exp: None,
+            statement: Some(statement.into()),
+            request_id: None,
+            resources: Some(vec![encode_key_as_did(identity_key)]),
+        }
+    }
+
+    /// Register the identity key bound to an account by `cacao`
+    pub async fn register(&self, cacao: Cacao) -> Result<(), IdentityError> {
+        self.http
+            .post(self.url.join("identity")?)
+            .json(&RegisterIdentity { cacao })
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Resolve the CACAO binding the hex-encoded identity key `public_key` to its account
+    pub async fn resolve(&self, public_key: &str) -> Result<Option<Cacao>, IdentityError> {
+        let mut url = self.url.join("identity")?;
+        url.query_pairs_mut().append_pair("publicKey", public_key);
+        let response = self.http.get(url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let identity: ResolveIdentity = response.error_for_status()?.json().await?;
+        Ok(Some(identity.value.cacao))
+    }
+
+    /// Unregister the identity key of `account`
+    pub async fn unregister(
+        &self,
+        account: &AccountId,
+        identity_key: &SigningKey,
+    ) -> Result<(), IdentityError> {
+        let iat = Utc::now();
+        let claims = UnregisterIdentityClaims {
+            iss: encode_key_as_did(identity_key),
+            aud: self.audience(),
+            iat: iat.timestamp(),
+            exp: (iat + UNREGISTER_TTL).timestamp(),
+            act: UNREGISTER_IDENTITY.into(),
+            pkh: format!("{DID_PKH_PREFIX}{account}"),
+        };
+        let id_auth = encode_jwt(identity_key, &claims)?;
+        self.http
+            .delete(self.url.join("identity")?)
+            .json(&UnregisterIdentity { id_auth })
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn audience(&self) -> String {
+        self.url.as_str().trim_end_matches('/').to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ChainId;
+
+    #[test]
+    fn test_identity_payload() {
+        let client = KeysClient::default();
+        let account = AccountId::new(
+            ChainId::new("eip155", "1"),
+            "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+        );
+        let identity_key = SigningKey::generate(&mut rand::thread_rng());
+        let payload = client.identity_payload(
+            &account,
+            &identity_key,
+            "app.example",
+            IDENTITY_STATEMENT_LIMITED,
+        );
+
+        assert_eq!(payload.aud, KEYS_SERVER_URL);
+        assert_eq!(payload.account().unwrap(), account);
+        assert_eq!(payload.resources, Some(vec![encode_key_as_did(&identity_key)]));
+        let message = payload.siwe_message().unwrap();
+        assert!(message.starts_with("app.example wants you to sign in with your Ethereum account:"));
+        assert!(message.ends_with(&format!("Resources:\n- {}", encode_key_as_did(&identity_key))));
+    }
+}
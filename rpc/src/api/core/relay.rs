@@ -7,8 +7,37 @@ pub trait Relay {
     #[method(name = "publish")]
     fn publish(&self, topic: String, message: String, policy: Policy);
 
-    #[subscription(name = "subscribe", unsubscribe = "unsubscribe", item = serde_json::Value)]
+    #[subscription(name = "subscribe", unsubscribe = "unsubscribe", item = SubscriptionData)]
     fn relay_subscribe(&self, topic: String) -> SubscriptionResult;
+
+    /// Subscribe to `topics`, returning their subscription ids in order
+    #[method(name = "batchSubscribe", param_kind = map)]
+    fn batch_subscribe(&self, topics: Vec<String>) -> RpcResult<Vec<String>>;
+
+    #[method(name = "batchUnsubscribe", param_kind = map)]
+    fn batch_unsubscribe(&self, subscriptions: Vec<Unsubscription>) -> RpcResult<bool>;
+
+    /// Fetch messages queued for `topic` while no client was subscribed
+    #[method(name = "fetchMessages", param_kind = map)]
+    fn fetch_messages(&self, topic: String) -> RpcResult<FetchMessagesResponse>;
+
+    /// Fetch messages queued for any of `topics` while no client was subscribed
+    #[method(name = "batchFetchMessages", param_kind = map)]
+    fn batch_fetch_messages(&self, topics: Vec<String>) -> RpcResult<FetchMessagesResponse>;
+
+    /// Register a webhook with a JWT of [`WatchRegisterClaims`]
+    #[method(name = "watchRegister", param_kind = map)]
+    fn watch_register(
+        &self,
+        #[argument(rename = "registerAuth")] register_auth: String,
+    ) -> RpcResult<WatchRegisterResponse>;
+
+    /// Unregister a webhook with a JWT of [`WatchUnregisterClaims`]
+    #[method(name = "watchUnregister", param_kind = map)]
+    fn watch_unregister(
+        &self,
+        #[argument(rename = "unregisterAuth")] unregister_auth: String,
+    ) -> RpcResult<bool>;
 }
 
 #[cfg(test)]
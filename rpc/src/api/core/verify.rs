@@ -0,0 +1,135 @@
+//! HTTP client of the verify server. Unlike the relay, the verify server is not JSON-RPC.
+
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::{error::VerifyError, types::verify::*};
+
+#[derive(Debug, Clone)]
+pub struct VerifyClient {
+    url: Url,
+    origin: Option<Url>,
+    http: reqwest::Client,
+}
+
+impl Default for VerifyClient {
+    fn default() -> Self {
+        Self::new(Url::parse(VERIFY_SERVER_URL).expect("Static URL is correct"))
+    }
+}
+
+impl VerifyClient {
+    /// Create a client of the verify server at `url`
+    pub fn new(url: Url) -> Self {
+        Self { url, origin: None, http: reqwest::Client::new() }
+    }
+
+    /// Register attestations of outgoing messages for `origin`. Only dapps register attestations.
+    pub fn origin(mut self, origin: Url) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The origin attestations are registered for, if this client belongs to a dapp
+    pub fn registered_origin(&self) -> Option<&Url> {
+        self.origin.as_ref()
+    }
+
+    /// Register the attestation of an encrypted `message` for the configured origin
+    pub async fn register(&self, message: &str) -> Result<(), VerifyError> {
+        let origin = self.origin.as_ref().ok_or(VerifyError::MissingOrigin)?;
+        let attestation =
+            Attestation::new(attestation_id(message), origin.origin().ascii_serialization());
+        self.http
+            .post(self.url.join("attestation")?)
+            .json(&attestation)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Fetch the attestation with `id`, `None` if it was never registered
+    pub async fn resolve(&self, id: &str) -> Result<Option<Attestation>, VerifyError> {
+        let response = self.http.get(self.url.join(&format!("attestation/{id}"))?).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    /// Verify the origin of an encrypted `message` against the origin the peer claims.
+    /// Failing to reach the verify server is not an error, the origin is [`Validation::Unknown`]
+    pub async fn verify(&self, message: &str, expected: &Url) -> VerifyContext {
+        match self.resolve(&attestation_id(message)).await {
+            Ok(attestation) => VerifyContext::new(attestation.as_ref(), expected, &self.url),
+            Err(e) => {
+                log::warn!("Failed to resolve attestation: {e}");
+                VerifyContext::unknown(&self.url)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// A verify server which attests every message it is asked about to `origin`
+    async fn stub_server(origin: &'static str) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body) = match path.strip_prefix("/attestation/") {
+                    Some("missing") => ("404 Not Found", String::new()),
+                    Some(id) => {
+                        ("200 OK", serde_json::to_string(&Attestation::new(id, origin)).unwrap())
+                    }
+                    None => ("200 OK", String::new()),
+                };
+                let response = format!(
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: \
+                     {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        Url::parse(&format!("http://{addr}/")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let url = stub_server("https://app.example").await;
+        let client = VerifyClient::new(url.clone());
+
+        assert_eq!(client.resolve("missing").await.unwrap(), None);
+
+        let expected = Url::parse("https://app.example/connect").unwrap();
+        let context = client.verify("message", &expected).await;
+        assert_eq!(context.validation, Validation::Valid);
+        assert_eq!(context.origin.as_deref(), Some("https://app.example"));
+        assert_eq!(context.verify_url, url.to_string());
+
+        let other = Url::parse("https://other.example").unwrap();
+        assert_eq!(client.verify("message", &other).await.validation, Validation::Invalid);
+
+        let dapp = client.origin(expected);
+        dapp.register("message").await.unwrap();
+    }
+}
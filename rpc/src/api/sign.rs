@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use jsonrpsee::proc_macros::rpc;
 
-use crate::types::{common::*, sign::*};
+use crate::types::{authenticate::*, common::*, sign::*};
 
 // https://specs.walletconnect.com/2.0/specs/clients/sign/rpc-methods
 #[rpc(client, namespace = "wc")]
@@ -32,7 +32,11 @@ pub trait Sign {
     fn session_extend(&self, expiry: i64) -> RpcResult<bool>;
 
     #[method(name = "sessionRequest")]
-    fn session_request(&self, request: Caip27Request, chain_id: String) -> RpcResult<bool>;
+    fn session_request(
+        &self,
+        request: Caip27Request,
+        chain_id: String,
+    ) -> RpcResult<serde_json::Value>;
 
     #[method(name = "sessionEvent")]
     fn session_event(&self, event: Event) -> RpcResult<bool>;
@@ -42,6 +46,14 @@ pub trait Sign {
 
     #[method(name = "sessionPing")]
     fn session_ping(&self) -> RpcResult<bool>;
+
+    #[method(name = "sessionAuthenticate", param_kind = map)]
+    fn session_authenticate(
+        &self,
+        requester: Participant,
+        #[argument(rename = "authPayload")] auth_payload: AuthPayload,
+        #[argument(rename = "expiryTimestamp")] expiry_timestamp: i64,
+    ) -> RpcResult<SessionAuthenticateResponse>;
 }
 
 #[cfg(test)]
@@ -72,6 +84,7 @@ mod tests {
                     "eth_signTypedData".into(),
                 ],
                 events: vec!["chainChanged".into(), "acountsChanged".into()],
+                accounts: vec![],
             };
             let mut required_namespaces = HashMap::new();
             required_namespaces.insert("eip155".to_string(), eip155);
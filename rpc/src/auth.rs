@@ -1,10 +1,14 @@
+pub mod cacao;
+
 use crate::error::AuthError;
 use chrono::{DateTime, Utc};
-use ed25519_dalek::{Signer, SigningKey};
-use serde::{Deserialize, Serialize};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{fmt::Display, time::Duration};
 
 pub const RELAY_WEBSOCKET_ADDRESS: &str = "wss://relay.walletconnect.com";
+pub const RELAY_HTTP_ADDRESS: &str = "https://relay.walletconnect.com";
+pub const DID_KEY_PREFIX: &str = "did:key:";
 
 pub const MULTICODEC_ED25519_BASE: &str = "z";
 pub const MULTICODEC_ED25519_HEADER: [u8; 2] = [237, 1];
@@ -124,22 +128,25 @@ fn encode_auth_token(
     iat: &DateTime<Utc>,
     ttl: &Duration,
 ) -> Result<SerializedAuthToken, AuthError> {
-    let encoder = &data_encoding::BASE64URL_NOPAD;
-
     let exp = (*iat + chrono::Duration::from_std(*ttl)?).timestamp();
 
-    let claims = {
-        let data = JwtBasicClaims {
-            iss: encode_key_as_did(key),
-            sub: sub.into(),
-            aud: aud.into(),
-            iat: iat.timestamp(),
-            exp: Some(exp),
-        };
-        log::debug!("Claims={}", serde_json::to_string_pretty(&data).unwrap());
-        encoder.encode(serde_json::to_string(&data)?.as_bytes())
+    let claims = JwtBasicClaims {
+        iss: encode_key_as_did(key),
+        sub: sub.into(),
+        aud: aud.into(),
+        iat: iat.timestamp(),
+        exp: Some(exp),
     };
+    log::debug!("Claims={}", serde_json::to_string_pretty(&claims).unwrap());
+
+    Ok(SerializedAuthToken(encode_jwt(key, &claims)?))
+}
 
+/// Encode `claims` as a JWT signed with `key`
+pub fn encode_jwt<C: Serialize>(key: &SigningKey, claims: &C) -> Result<String, AuthError> {
+    let encoder = &data_encoding::BASE64URL_NOPAD;
+
+    let claims = encoder.encode(serde_json::to_string(claims)?.as_bytes());
     let header = encoder.encode(serde_json::to_string(&JwtHeader::default())?.as_bytes());
     let message = format!("{header}.{claims}");
 
@@ -149,12 +156,34 @@ fn encode_auth_token(
         encoder.encode(data.to_vec().as_slice())
     };
 
-    Ok(SerializedAuthToken(format!("{message}.{signature}")))
+    Ok(format!("{message}.{signature}"))
+}
+
+/// Decode the claims of a JWT, verifying it was signed by `key`
+pub fn decode_jwt<C: DeserializeOwned>(token: &str, key: &VerifyingKey) -> Result<C, AuthError> {
+    let decoder = &data_encoding::BASE64URL_NOPAD;
+
+    let mut parts = token.rsplitn(2, '.');
+    let (Some(signature), Some(message)) = (parts.next(), parts.next()) else {
+        return Err(AuthError::InvalidJwt);
+    };
+    let (header, claims) = message.split_once('.').ok_or(AuthError::InvalidJwt)?;
+
+    let header = decoder.decode(header.as_bytes())?;
+    if !serde_json::from_slice::<JwtHeader>(&header)?.is_valid() {
+        return Err(AuthError::InvalidJwt);
+    }
+    let signature = Signature::from_slice(&decoder.decode(signature.as_bytes())?)?;
+    key.verify(message.as_bytes(), &signature)?;
+
+    Ok(serde_json::from_slice(&decoder.decode(claims.as_bytes())?)?)
 }
 
 pub fn encode_key_as_did(key: &SigningKey) -> String {
-    let public_key = key.verifying_key();
+    encode_public_key_as_did(&key.verifying_key())
+}
 
+pub fn encode_public_key_as_did(public_key: &VerifyingKey) -> String {
     const PREFIX_LEN: usize = MULTICODEC_ED25519_HEADER.len();
     const TOTAL_LEN: usize = MULTICODEC_ED25519_LENGTH + PREFIX_LEN;
 
@@ -168,3 +197,18 @@ pub fn encode_key_as_did(key: &SigningKey) -> String {
     log::debug!("did={did}");
     did
 }
+
+/// Decode the ed25519 public key of a `did:key`
+pub fn decode_did_key(did: &str) -> Result<VerifyingKey, AuthError> {
+    let invalid = || AuthError::InvalidDid(did.to_string());
+    let encoded = did
+        .strip_prefix(DID_KEY_PREFIX)
+        .and_then(|key| key.strip_prefix(MULTICODEC_ED25519_BASE))
+        .ok_or_else(invalid)?;
+    let decoded = bs58::decode(encoded).into_vec().map_err(|_| invalid())?;
+    let key = decoded
+        .strip_prefix(MULTICODEC_ED25519_HEADER.as_slice())
+        .and_then(|key| <[u8; MULTICODEC_ED25519_LENGTH]>::try_from(key).ok())
+        .ok_or_else(invalid)?;
+    Ok(VerifyingKey::from_bytes(&key)?)
+}
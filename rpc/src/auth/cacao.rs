@@ -0,0 +1,259 @@
+//! Verification of [CAIP-74](https://chainagnostic.org/CAIPs/caip-74) CACAOs.
+//! `eip191` signatures are recovered locally. `eip1271` signatures belong to contract wallets and
+//! are checked by calling `isValidSignature` on the contract, through an [`Eip1271Rpc`] supplied by
+//! the user.
+
+use std::{future::Future, sync::Arc};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    error::CacaoError,
+    signature::{self, eip191_hash, strip_hex_prefix},
+    types::{AccountId, Cacao, ChainId, CACAO_TYPE_CAIP122},
+    BoxError, BoxFuture,
+};
+
+pub type Result<T> = std::result::Result<T, CacaoError>;
+
+pub const EIP191: &str = "eip191";
+pub const EIP1271: &str = "eip1271";
+/// `bytes4(keccak256("isValidSignature(bytes32,bytes)"))`, returned by the contract when the
+/// signature is valid
+pub const EIP1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+
+/// Performs an `eth_call` against a chain, used to verify signatures of contract wallets
+pub trait Eip1271Rpc: Send + Sync {
+    /// Call contract `to` on `chain_id` with hex-encoded `data`, returning the hex-encoded result
+    fn eth_call<'a>(
+        &'a self,
+        chain_id: &'a ChainId,
+        to: &'a str,
+        data: &'a str,
+    ) -> BoxFuture<'a, std::result::Result<String, BoxError>>;
+}
+
+impl<F, Fut> Eip1271Rpc for F
+where
+    F: Fn(ChainId, String, String) -> Fut + Send + Sync,
+    Fut: Future<Output = std::result::Result<String, BoxError>> + Send + 'static,
+{
+    fn eth_call<'a>(
+        &'a self,
+        chain_id: &'a ChainId,
+        to: &'a str,
+        data: &'a str,
+    ) -> BoxFuture<'a, std::result::Result<String, BoxError>> {
+        Box::pin(self(chain_id.clone(), to.to_string(), data.to_string()))
+    }
+}
+
+/// Verifies CACAOs returned by a wallet
+#[derive(Clone, Default)]
+pub struct CacaoVerifier {
+    eip1271: Option<Arc<dyn Eip1271Rpc>>,
+}
+
+impl std::fmt::Debug for CacaoVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacaoVerifier").field("eip1271", &self.eip1271.is_some()).finish()
+    }
+}
+
+impl CacaoVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verify `eip1271` signatures with `rpc`. Without it, CACAOs signed by contract wallets are
+    /// rejected.
+    pub fn eip1271(mut self, rpc: impl Eip1271Rpc + 'static) -> Self {
+        self.eip1271 = Some(Arc::new(rpc));
+        self
+    }
+
+    /// Verify that `cacao` is currently valid and was signed by its issuer
+    pub async fn verify(&self, cacao: &Cacao) -> Result<()> {
+        self.verify_at(cacao, Utc::now()).await
+    }
+
+    /// Verify that `cacao` is valid at `now` and was signed by its issuer
+    pub async fn verify_at(&self, cacao: &Cacao, now: DateTime<Utc>) -> Result<()> {
+        if cacao.h.t != CACAO_TYPE_CAIP122 {
+            return Err(CacaoError::UnsupportedHeader(cacao.h.t.clone()));
+        }
+        if let Some(exp) = &cacao.p.exp {
+            if DateTime::parse_from_rfc3339(exp)? <= now {
+                return Err(CacaoError::Expired(exp.clone()));
+            }
+        }
+        if let Some(nbf) = &cacao.p.nbf {
+            if DateTime::parse_from_rfc3339(nbf)? > now {
+                return Err(CacaoError::NotYetValid(nbf.clone()));
+            }
+        }
+
+        let account = cacao.p.account()?;
+        let message = cacao.p.siwe_message()?;
+        match cacao.s.t.as_str() {
+            EIP191 => {
+                signature::verify_personal_sign(&account, message.as_bytes(), &cacao.s.s)?;
+                Ok(())
+            }
+            EIP1271 => {
+                let rpc = self
+                    .eip1271
+                    .as_ref()
+                    .ok_or_else(|| CacaoError::MissingEip1271(account.clone()))?;
+                let hash = eip191_hash(message.as_bytes());
+                let signature = hex::decode(strip_hex_prefix(&cacao.s.s))?;
+                let data = format!("0x{}", hex::encode(is_valid_signature_call(&hash, &signature)));
+                let result = rpc
+                    .eth_call(account.chain_id(), account.address(), &data)
+                    .await
+                    .map_err(CacaoError::Eip1271Call)?;
+                let result = hex::decode(strip_hex_prefix(&result))?;
+                if result.get(..4) == Some(&EIP1271_MAGIC_VALUE[..]) {
+                    Ok(())
+                } else {
+                    Err(CacaoError::Eip1271Rejected(account))
+                }
+            }
+            t => Err(CacaoError::UnsupportedSignature(t.to_string())),
+        }
+    }
+}
+
+/// ABI encode a call to `isValidSignature(bytes32 hash, bytes signature)`
+pub fn is_valid_signature_call(hash: &[u8; 32], signature: &[u8]) -> Vec<u8> {
+    let padded = signature.len().div_ceil(32) * 32;
+    let mut data = Vec::with_capacity(4 + 32 * 3 + padded);
+    // the magic value is the selector of `isValidSignature`
+    data.extend_from_slice(&EIP1271_MAGIC_VALUE);
+    data.extend_from_slice(hash);
+    data.extend_from_slice(&abi_word(64));
+    data.extend_from_slice(&abi_word(signature.len()));
+    data.extend_from_slice(signature);
+    data.resize(4 + 32 * 3 + padded, 0);
+    data
+}
+
+fn abi_word(value: usize) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+    word
+}
+
+/// The `did:pkh` of `account`, as used in the `iss` of a CACAO
+pub fn did_pkh(account: &AccountId) -> String {
+    format!("{}{account}", crate::types::DID_PKH_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        signature::{address_of, keccak256, sign_hash, to_checksum_address},
+        types::{CacaoHeader, CacaoPayload, CacaoSignature},
+    };
+
+    fn payload(account: &AccountId) -> CacaoPayload {
+        CacaoPayload {
+            domain: "app.example".into(),
+            aud: "https://app.example/login".into(),
+            version: "1".into(),
+            nonce: "1234".into(),
+            iat: "2024-01-01T00:00:00.000Z".into(),
+            iss: did_pkh(account),
+            nbf: None,
+            exp: Some("2024-01-02T00:00:00.000Z".into()),
+            statement: Some("Sign in to app.example.".into()),
+            request_id: None,
+            resources: None,
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2024-01-01T12:00:00Z".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_verify_eip191() {
+        let key = SigningKey::from_slice(&keccak256(b"cow")).unwrap();
+        let address = to_checksum_address(&address_of(key.verifying_key()));
+        let account = AccountId::new(ChainId::new("eip155", "1"), address);
+
+        let p = payload(&account);
+        let hash = eip191_hash(p.siwe_message().unwrap().as_bytes());
+        let s = format!("0x{}", hex::encode(sign_hash(&key, &hash).unwrap()));
+        let cacao = Cacao {
+            h: CacaoHeader::default(),
+            p,
+            s: CacaoSignature { t: EIP191.into(), s, m: None },
+        };
+
+        // CACAOs serialize as described in CAIP-74
+        let json = serde_json::to_value(&cacao).unwrap();
+        assert_eq!(json["h"], json!({ "t": "caip122" }));
+        assert_eq!(json["p"]["iss"], json!(did_pkh(&account)));
+        let cacao: Cacao = serde_json::from_value(json).unwrap();
+
+        let verifier = CacaoVerifier::new();
+        verifier.verify_at(&cacao, now()).await.unwrap();
+
+        let expired = "2024-01-03T00:00:00Z".parse().unwrap();
+        assert!(matches!(verifier.verify_at(&cacao, expired).await, Err(CacaoError::Expired(_))));
+
+        let mut tampered = cacao.clone();
+        tampered.p.nonce = "4321".into();
+        assert!(matches!(
+            verifier.verify_at(&tampered, now()).await,
+            Err(CacaoError::Signature(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_eip1271() {
+        let account = AccountId::new(
+            ChainId::new("eip155", "1"),
+            "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+        );
+        let p = payload(&account);
+        let hash = eip191_hash(p.siwe_message().unwrap().as_bytes());
+        let cacao = Cacao {
+            h: CacaoHeader::default(),
+            p,
+            s: CacaoSignature { t: EIP1271.into(), s: "0xc0ffee".into(), m: None },
+        };
+
+        assert!(matches!(
+            CacaoVerifier::new().verify_at(&cacao, now()).await,
+            Err(CacaoError::MissingEip1271(_))
+        ));
+
+        // the contract accepts only the signature 0xc0ffee over the expected hash
+        let expected =
+            format!("0x{}", hex::encode(is_valid_signature_call(&hash, &[0xc0, 0xff, 0xee])));
+        let verifier =
+            CacaoVerifier::new().eip1271(move |chain_id: ChainId, to: String, data: String| {
+                let valid = chain_id.reference() == "1"
+                    && to == "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+                    && data == expected;
+                async move {
+                    let result = if valid { EIP1271_MAGIC_VALUE } else { [0u8; 4] };
+                    Ok(format!("0x{}{}", hex::encode(result), "00".repeat(28)))
+                }
+            });
+        verifier.verify_at(&cacao, now()).await.unwrap();
+
+        let mut tampered = cacao.clone();
+        tampered.s.s = "0xdeadbeef".into();
+        assert!(matches!(
+            verifier.verify_at(&tampered, now()).await,
+            Err(CacaoError::Eip1271Rejected(_))
+        ));
+    }
+}
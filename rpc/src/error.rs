@@ -4,10 +4,16 @@ pub use jsonrpsee::core::ClientError as JsonRpcError;
 use peg::str::LineCol;
 use thiserror::Error;
 
+use crate::types::AccountId;
+
 #[derive(Debug, Error)]
 pub enum TypeError {
     #[error("Failed to parse pairing URI {0}")]
     Parse(#[from] peg::error::ParseError<LineCol>),
+    #[error("{0} is not a did:pkh")]
+    Did(String),
+    #[error("{input:?} is not a valid {kind}: {source}")]
+    Caip { kind: &'static str, input: String, source: peg::error::ParseError<LineCol> },
 }
 
 #[derive(Debug, Error)]
@@ -22,6 +28,46 @@ pub enum ClientError {
     QueryString(#[from] serde_qs::Error),
     #[error(transparent)]
     Url(#[from] url::ParseError),
+    #[error(transparent)]
+    Connect(#[from] ConnectError),
+}
+
+#[derive(Debug, Error)]
+pub enum ConnectError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Tls(#[from] tokio_rustls::rustls::Error),
+    #[error("invalid PEM: {0}")]
+    Pem(#[from] rustls_pki_types::pem::Error),
+    #[error("no certificate found in PEM")]
+    MissingCertificate,
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+    #[error("{0} has no host")]
+    MissingHost(String),
+    #[error("unsupported proxy scheme {0}")]
+    UnsupportedProxy(String),
+    #[error("unsupported relay scheme {0}")]
+    UnsupportedScheme(String),
+    #[error("proxy refused the tunnel: {0}")]
+    Proxy(String),
+    #[error("refusing to connect to {0} without TLS, insecure relays must be allowed explicitly")]
+    Insecure(String),
+    #[error("timed out connecting to the relay")]
+    Timeout,
+}
+
+#[derive(Debug, Error)]
+pub enum TransportError {
+    #[error(transparent)]
+    JsonRpc(#[from] JsonRpcError),
+    #[error("relay rejected the request: {0}")]
+    Rejected(String),
+    #[error("relay did not return a subscription id")]
+    MissingSubscriptionId,
+    #[error("transport is closed")]
+    Closed,
 }
 
 #[derive(Debug, Error)]
@@ -30,6 +76,86 @@ pub enum AuthError {
     Serialization(#[from] serde_json::Error),
     #[error(transparent)]
     OutOrRange(#[from] chrono::OutOfRangeError),
+    #[error("malformed JWT")]
+    InvalidJwt,
+    #[error(transparent)]
+    Base64(#[from] data_encoding::DecodeError),
+    #[error(transparent)]
+    Signature(#[from] ed25519_dalek::SignatureError),
+    #[error("{0} is not an ed25519 did:key")]
+    InvalidDid(String),
+}
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("signature was produced by {recovered}, expected {expected}")]
+    Mismatch { expected: AccountId, recovered: String },
+    #[error("signature must be 65 bytes, got {0}")]
+    InvalidLength(usize),
+    #[error("invalid recovery id {0}")]
+    InvalidRecoveryId(u8),
+    #[error("{0} is not an account of this session")]
+    UnknownAccount(String),
+    #[error("invalid address {0}")]
+    InvalidAddress(String),
+    #[error("accounts in namespace {0} cannot be verified locally")]
+    UnsupportedNamespace(String),
+    #[error("invalid typed data: {0}")]
+    TypedData(String),
+    #[error("malformed signing request: {0}")]
+    Malformed(String),
+    #[error(transparent)]
+    Ecdsa(#[from] k256::ecdsa::Error),
+    #[error(transparent)]
+    Hex(#[from] hex::FromHexError),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum CacaoError {
+    #[error("unsupported CACAO header type {0}")]
+    UnsupportedHeader(String),
+    #[error("unsupported CACAO signature type {0}")]
+    UnsupportedSignature(String),
+    #[error("CACAO expired at {0}")]
+    Expired(String),
+    #[error("CACAO is not valid before {0}")]
+    NotYetValid(String),
+    #[error("no EIP-1271 verifier configured to verify the signature of {0}")]
+    MissingEip1271(AccountId),
+    #[error("contract wallet {0} rejected the signature")]
+    Eip1271Rejected(AccountId),
+    #[error("EIP-1271 call failed: {0}")]
+    Eip1271Call(crate::BoxError),
+    #[error(transparent)]
+    Type(#[from] TypeError),
+    #[error(transparent)]
+    Signature(#[from] SignatureError),
+    #[error(transparent)]
+    Time(#[from] chrono::ParseError),
+    #[error(transparent)]
+    Hex(#[from] hex::FromHexError),
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+    #[error("attestations can only be registered with an origin")]
+    MissingOrigin,
+}
+
+#[derive(Debug, Error)]
+pub enum IdentityError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+    #[error(transparent)]
+    Auth(#[from] AuthError),
 }
 
 impl From<Infallible> for ClientError {
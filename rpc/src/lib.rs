@@ -1,26 +1,41 @@
 pub mod api;
 pub mod auth;
+pub mod connect;
 pub mod error;
+pub mod signature;
+pub mod transport;
 pub mod types;
 
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
-use auth::RELAY_WEBSOCKET_ADDRESS;
+use auth::RELAY_HTTP_ADDRESS;
+use chrono::{DateTime, Utc};
 use ed25519_dalek::{SigningKey, VerifyingKey};
-use jsonrpsee::{
-    core::traits::IdKind,
-    types::Id,
-    ws_client::{WsClient, WsClientBuilder},
-};
+use jsonrpsee::{core::traits::IdKind, types::Id, ws_client::WsClient};
 use rand::Rng as _;
 
-use crate::{auth::AuthToken, error::ClientError};
+use crate::{
+    api::core::RelayClient,
+    auth::{decode_did_key, encode_jwt},
+    connect::{ClientBuilder, CloseFrame, Closer, TOKEN_REFRESH_MARGIN},
+    error::ClientError,
+    transport::WsTransport,
+    types::Watch,
+};
+
+/// A boxed, sendable future, returned by user-supplied hooks
+pub type BoxFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+/// Error returned by user-supplied hooks
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
 /// This is required to avoid request id collisions
 pub const REQUEST_ID_ENTROPY: u32 = 6;
 
 pub mod prelude {
-    pub use jsonrpsee::core::client::Subscription;
+    pub use jsonrpsee::{
+        core::client::{Subscription, SubscriptionKind},
+        types::SubscriptionId,
+    };
 
     pub use super::api::core::*;
     // pub use jsonrpsee::Subscription;
@@ -29,7 +44,13 @@ pub mod prelude {
 #[derive(Debug, Clone)]
 pub struct Client {
     pub client: Arc<WsClient<RequestIdGen>>,
-    key: SigningKey,
+    pub(crate) key: SigningKey,
+    /// the settings the client connected with, to reconnect
+    pub(crate) config: ClientBuilder,
+    token_expiry: DateTime<Utc>,
+    /// the close frame the relay ended the connection with
+    close: Arc<OnceLock<CloseFrame>>,
+    closer: Arc<Closer>,
 }
 
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -38,42 +59,61 @@ pub struct RequestIdGen;
 
 impl IdKind for RequestIdGen {
     fn into_id(&self, _: u64) -> Id<'static> {
-        let date = chrono::Utc::now().timestamp_millis() as u64;
-        let date = date * 10_u64.pow(REQUEST_ID_ENTROPY);
+        Id::Number(payload_id())
+    }
+}
 
-        let extra = rand::thread_rng().gen_range(0..10_u64.pow(REQUEST_ID_ENTROPY));
+/// Generate a JSON-RPC id from the current millisecond timestamp followed by
+/// [`REQUEST_ID_ENTROPY`] random digits
+pub fn payload_id() -> u64 {
+    let date = chrono::Utc::now().timestamp_millis() as u64;
+    let date = date * 10_u64.pow(REQUEST_ID_ENTROPY);
 
-        Id::Number(date + extra)
-    }
+    let extra = rand::thread_rng().gen_range(0..10_u64.pow(REQUEST_ID_ENTROPY));
+
+    date + extra
 }
 
 impl Client {
+    /// Connect to the WalletConnect relay with a fresh key
     pub async fn new(project_id: &str, url: &str) -> Result<Self, ClientError> {
-        let key = SigningKey::generate(&mut rand::thread_rng());
-        let token = AuthToken::builder(url)
-            .aud(RELAY_WEBSOCKET_ADDRESS)
-            .ttl(std::time::Duration::from_secs(60 * 60))
-            .build()
-            .as_jwt(&key)?;
-
-        #[derive(serde::Serialize)]
-        #[serde(rename_all = "camelCase")]
-        struct QueryParams<'a> {
-            project_id: &'a str,
-            auth: &'a auth::SerializedAuthToken,
-        }
+        Self::builder(project_id, url).build().await
+    }
 
-        let query = serde_qs::to_string(&QueryParams { project_id, auth: &token })?;
+    /// Configure the relay, proxy and TLS settings of the connection
+    pub fn builder(project_id: &str, url: &str) -> ClientBuilder {
+        ClientBuilder::new(project_id, url)
+    }
 
-        let mut url = url::Url::parse(RELAY_WEBSOCKET_ADDRESS)?;
-        url.set_query(Some(&query));
+    /// Open a new connection to the relay, with the same settings and key and a new auth token
+    pub async fn reconnect(&self) -> Result<Self, ClientError> {
+        self.config.clone().connect(self.key.clone()).await
+    }
 
-        let client: WsClient<RequestIdGen> =
-            WsClientBuilder::<RequestIdGen>::default().id_format(RequestIdGen).build(url).await?;
+    /// When the auth token of the connection expires
+    pub fn token_expiry(&self) -> DateTime<Utc> {
+        self.token_expiry
+    }
+
+    /// When the connection should be replaced by one with a new auth token
+    pub fn token_refresh_at(&self) -> DateTime<Utc> {
+        let margin = TOKEN_REFRESH_MARGIN.min(self.config.token_ttl / 2);
+        self.token_expiry - margin
+    }
 
-        let client = Arc::new(client);
+    /// The close frame the relay ended the connection with, once it did
+    pub fn close_frame(&self) -> Option<&CloseFrame> {
+        self.close.get()
+    }
 
-        Ok(Self { client, key })
+    /// Close the websocket with a close frame, and wait until jsonrpsee shut the connection down
+    /// or the connection timeout elapsed
+    pub async fn close(&self) {
+        self.closer.close();
+        let closed = self.client.disconnect_reason();
+        if tokio::time::timeout(self.config.connection_timeout, closed).await.is_err() {
+            log::warn!("The relay connection did not shut down in time");
+        }
     }
 
     /// Get the inner WsClient
@@ -81,10 +121,30 @@ impl Client {
         self.client.clone()
     }
 
+    /// A transport publishing and subscribing over the websocket of this client, and
+    /// reconnecting when it is lost
+    pub fn transport(&self) -> Arc<WsTransport> {
+        WsTransport::new(self.clone())
+    }
+
     /// The the ed25519 public key associated with the session
     pub fn public_key(&self) -> VerifyingKey {
         self.key.verifying_key()
     }
+
+    /// Register a webhook the relay posts messages to, returning the key the relay signs them
+    /// with
+    pub async fn watch_register(&self, watch: &Watch) -> Result<VerifyingKey, ClientError> {
+        let claims = watch.register_claims(&self.key, RELAY_HTTP_ADDRESS, chrono::Utc::now());
+        let response = self.client.watch_register(encode_jwt(&self.key, &claims)?).await?;
+        Ok(decode_did_key(&response.relay_id)?)
+    }
+
+    /// Unregister a webhook registered with [`Client::watch_register`]
+    pub async fn watch_unregister(&self, watch: &Watch) -> Result<bool, ClientError> {
+        let claims = watch.unregister_claims(&self.key, RELAY_HTTP_ADDRESS, chrono::Utc::now());
+        Ok(self.client.watch_unregister(encode_jwt(&self.key, &claims)?).await?)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,651 @@
+//! Local verification of Ethereum signatures returned by a wallet.
+//! Messages are hashed according to [EIP-191](https://eips.ethereum.org/EIPS/eip-191) for
+//! `personal_sign` and [EIP-712](https://eips.ethereum.org/EIPS/eip-712) for
+//! `eth_signTypedData_v4`, the secp256k1 signer is recovered and compared with the CAIP-10
+//! account the session was approved for.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    error::SignatureError,
+    types::{AccountId, ChainId, NamespaceMap},
+};
+
+pub type Result<T> = std::result::Result<T, SignatureError>;
+
+/// A 20-byte Ethereum address
+pub type Address = [u8; 20];
+
+pub const PERSONAL_SIGN: &str = "personal_sign";
+pub const ETH_SIGN_TYPED_DATA_V4: &str = "eth_signTypedData_v4";
+pub const EIP155: &str = "eip155";
+pub const EIP712_DOMAIN: &str = "EIP712Domain";
+
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+/// Hash a message according to EIP-191 version `0x45`
+/// `keccak256("\x19Ethereum Signed Message:\n" + len(message) + message)`
+pub fn eip191_hash(message: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", message.len()).as_bytes());
+    hasher.update(message);
+    hasher.finalize().into()
+}
+
+/// Derive the Ethereum address of a secp256k1 public key
+pub fn address_of(key: &VerifyingKey) -> Address {
+    let point = key.to_encoded_point(false);
+    let hash = keccak256(&point.as_bytes()[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
+/// Format an address with its [EIP-55](https://eips.ethereum.org/EIPS/eip-55) checksum
+pub fn to_checksum_address(address: &Address) -> String {
+    let lower = hex::encode(address);
+    let hash = hex::encode(keccak256(lower.as_bytes()));
+    let checksummed: String = lower
+        .chars()
+        .zip(hash.chars())
+        .map(|(c, h)| if h >= '8' { c.to_ascii_uppercase() } else { c })
+        .collect();
+    format!("0x{checksummed}")
+}
+
+/// Parse a hex-encoded address, with or without the `0x` prefix
+pub fn parse_address(address: &str) -> Result<Address> {
+    let mut bytes = [0u8; 20];
+    hex::decode_to_slice(strip_hex_prefix(address), &mut bytes)
+        .map_err(|_| SignatureError::InvalidAddress(address.to_string()))?;
+    Ok(bytes)
+}
+
+/// Sign a 32-byte prehashed message, returning the 65-byte `r || s || v` signature with `v` in
+/// `{27, 28}`
+pub fn sign_hash(key: &SigningKey, hash: &[u8; 32]) -> Result<[u8; 65]> {
+    let (signature, recovery_id) = key.sign_prehash_recoverable(hash)?;
+    let mut bytes = [0u8; 65];
+    bytes[..64].copy_from_slice(&signature.to_bytes());
+    bytes[64] = recovery_id.to_byte() + 27;
+    Ok(bytes)
+}
+
+/// Recover the address which produced a 65-byte `r || s || v` signature over `hash`
+pub fn recover(hash: &[u8; 32], signature: &[u8]) -> Result<Address> {
+    if signature.len() != 65 {
+        return Err(SignatureError::InvalidLength(signature.len()));
+    }
+    let v = match signature[64] {
+        v @ (27 | 28) => v - 27,
+        v @ (0 | 1) => v,
+        v => return Err(SignatureError::InvalidRecoveryId(v)),
+    };
+    let mut recovery_id = RecoveryId::from_byte(v).ok_or(SignatureError::InvalidRecoveryId(v))?;
+    let mut signature = Signature::from_slice(&signature[..64])?;
+    // wallets should never produce high-s signatures, but normalizing flips the y-parity
+    if let Some(normalized) = signature.normalize_s() {
+        signature = normalized;
+        recovery_id = RecoveryId::new(!recovery_id.is_y_odd(), recovery_id.is_x_reduced());
+    }
+    let key = VerifyingKey::recover_from_prehash(hash, &signature, recovery_id)?;
+    Ok(address_of(&key))
+}
+
+/// Verify that the hex-encoded `signature` over `hash` was produced by `account`
+pub fn verify_hash(account: &AccountId, hash: &[u8; 32], signature: &str) -> Result<()> {
+    if account.chain_id().namespace() != EIP155 {
+        return Err(SignatureError::UnsupportedNamespace(account.chain_id().namespace().into()));
+    }
+    let expected = parse_address(account.address())?;
+    let signature = hex::decode(strip_hex_prefix(signature))?;
+    let recovered = recover(hash, &signature)?;
+
+    if recovered != expected {
+        return Err(SignatureError::Mismatch {
+            expected: account.clone(),
+            recovered: to_checksum_address(&recovered),
+        });
+    }
+    Ok(())
+}
+
+/// Verify a `personal_sign` signature over `message` against `account`
+pub fn verify_personal_sign(account: &AccountId, message: &[u8], signature: &str) -> Result<()> {
+    verify_hash(account, &eip191_hash(message), signature)
+}
+
+/// Verify an `eth_signTypedData_v4` signature over `typed_data` against `account`
+pub fn verify_typed_data(
+    account: &AccountId,
+    typed_data: &TypedData,
+    signature: &str,
+) -> Result<()> {
+    verify_hash(account, &typed_data.signing_hash()?, signature)
+}
+
+/// Verify the result of a signing request against `account`.
+/// `params` are the JSON-RPC parameters sent to the wallet and `result` its response.
+/// Returns `Ok(false)` if `method` is not a signing method which can be verified locally.
+pub fn verify_response(
+    account: &AccountId,
+    method: &str,
+    params: &Value,
+    result: &Value,
+) -> Result<bool> {
+    let signature = || {
+        result
+            .as_str()
+            .ok_or_else(|| SignatureError::Malformed("signature must be a hex string".into()))
+    };
+
+    match method {
+        PERSONAL_SIGN => {
+            // personal_sign params are `[message, address]`
+            let message = params
+                .get(0)
+                .and_then(Value::as_str)
+                .ok_or_else(|| SignatureError::Malformed("missing personal_sign message".into()))?;
+            verify_personal_sign(account, &decode_message(message), signature()?)?;
+            Ok(true)
+        }
+        ETH_SIGN_TYPED_DATA_V4 => {
+            // eth_signTypedData_v4 params are `[address, typedData]`, where typed data may be
+            // passed as an object or as a JSON-encoded string
+            let typed_data: TypedData = match params.get(1) {
+                Some(Value::String(s)) => serde_json::from_str(s)?,
+                Some(v @ Value::Object(_)) => serde_json::from_value(v.clone())?,
+                _ => return Err(SignatureError::Malformed("missing typed data".into())),
+            };
+            verify_typed_data(account, &typed_data, signature()?)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Verify the result of a signing request sent on a session with approved `namespaces`.
+/// The address the dapp asked to sign with must be one of the session's accounts on `chain_id`,
+/// and the recovered signer must match it.
+pub fn verify_session_response(
+    namespaces: &NamespaceMap,
+    chain_id: &ChainId,
+    method: &str,
+    params: &Value,
+    result: &Value,
+) -> Result<bool> {
+    let address = match method {
+        PERSONAL_SIGN => params.get(1),
+        ETH_SIGN_TYPED_DATA_V4 => params.get(0),
+        _ => return Ok(false),
+    }
+    .and_then(Value::as_str)
+    .ok_or_else(|| SignatureError::Malformed(format!("missing {method} address")))?;
+
+    let account = namespaces
+        .values()
+        .find_map(|namespace| namespace.account(chain_id, address))
+        .ok_or_else(|| SignatureError::UnknownAccount(format!("{chain_id}:{address}")))?;
+
+    verify_response(account, method, params, result)
+}
+
+/// Sign a signing request with `key`, the counterpart of [`verify_response`].
+/// The address the dapp asked to sign with must be the address of `key`.
+/// Returns `Ok(None)` if `method` is not a signing method which can be answered locally.
+pub fn sign_request(key: &SigningKey, method: &str, params: &Value) -> Result<Option<String>> {
+    let (address, hash) = match method {
+        PERSONAL_SIGN => {
+            let message = params
+                .get(0)
+                .and_then(Value::as_str)
+                .ok_or_else(|| SignatureError::Malformed("missing personal_sign message".into()))?;
+            (params.get(1), eip191_hash(&decode_message(message)))
+        }
+        ETH_SIGN_TYPED_DATA_V4 => {
+            let typed_data: TypedData = match params.get(1) {
+                Some(Value::String(s)) => serde_json::from_str(s)?,
+                Some(v @ Value::Object(_)) => serde_json::from_value(v.clone())?,
+                _ => return Err(SignatureError::Malformed("missing typed data".into())),
+            };
+            (params.get(0), typed_data.signing_hash()?)
+        }
+        _ => return Ok(None),
+    };
+    let address = address
+        .and_then(Value::as_str)
+        .ok_or_else(|| SignatureError::Malformed(format!("missing {method} address")))?;
+    if parse_address(address)? != address_of(key.verifying_key()) {
+        return Err(SignatureError::UnknownAccount(address.to_string()));
+    }
+    Ok(Some(format!("0x{}", hex::encode(sign_hash(key, &hash)?))))
+}
+
+/// `personal_sign` messages are hex-encoded bytes, but some dapps send plain UTF-8 strings
+fn decode_message(message: &str) -> Vec<u8> {
+    match message.strip_prefix("0x").map(hex::decode) {
+        Some(Ok(bytes)) => bytes,
+        _ => message.as_bytes().to_vec(),
+    }
+}
+
+pub(crate) fn strip_hex_prefix(s: &str) -> &str {
+    s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TypedDataField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+/// EIP-712 Typed Structured Data, as passed to `eth_signTypedData_v4`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: BTreeMap<String, Vec<TypedDataField>>,
+    pub primary_type: String,
+    pub domain: Value,
+    pub message: Value,
+}
+
+impl TypedData {
+    /// The hash that is signed:
+    /// `keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))`
+    pub fn signing_hash(&self) -> Result<[u8; 32]> {
+        let mut hasher = Keccak256::new();
+        hasher.update([0x19, 0x01]);
+        hasher.update(self.domain_separator()?);
+        hasher.update(self.hash_struct(&self.primary_type, &self.message)?);
+        Ok(hasher.finalize().into())
+    }
+
+    /// `hashStruct(eip712Domain)`
+    pub fn domain_separator(&self) -> Result<[u8; 32]> {
+        if self.types.contains_key(EIP712_DOMAIN) {
+            return self.hash_struct(EIP712_DOMAIN, &self.domain);
+        }
+        // `EIP712Domain` may be omitted from `types`, derive it from the fields that are present
+        const FIELDS: [(&str, &str); 5] = [
+            ("name", "string"),
+            ("version", "string"),
+            ("chainId", "uint256"),
+            ("verifyingContract", "address"),
+            ("salt", "bytes32"),
+        ];
+        let fields = FIELDS
+            .iter()
+            .filter(|(name, _)| self.domain.get(name).is_some())
+            .map(|(name, ty)| TypedDataField { name: name.to_string(), ty: ty.to_string() })
+            .collect();
+        let mut with_domain = self.clone();
+        with_domain.types.insert(EIP712_DOMAIN.into(), fields);
+        with_domain.hash_struct(EIP712_DOMAIN, &self.domain)
+    }
+
+    /// `keccak256(typeHash ‖ encodeData(s))`
+    pub fn hash_struct(&self, ty: &str, data: &Value) -> Result<[u8; 32]> {
+        let fields = self.fields(ty)?;
+        let mut encoded = Vec::with_capacity(32 * (fields.len() + 1));
+        encoded.extend_from_slice(&keccak256(self.encode_type(ty)?.as_bytes()));
+        for field in fields {
+            let value = data.get(&field.name).unwrap_or(&Value::Null);
+            encoded.extend_from_slice(&self.encode_value(&field.ty, value)?);
+        }
+        Ok(keccak256(&encoded))
+    }
+
+    /// `encodeType`, the primary type followed by its dependencies in alphabetical order
+    pub fn encode_type(&self, ty: &str) -> Result<String> {
+        let mut deps = BTreeSet::new();
+        self.dependencies(ty, &mut deps)?;
+        deps.remove(ty);
+
+        let mut encoded = String::new();
+        for dep in std::iter::once(ty).chain(deps.iter().map(String::as_str)) {
+            let fields = self
+                .fields(dep)?
+                .iter()
+                .map(|f| format!("{} {}", f.ty, f.name))
+                .collect::<Vec<_>>()
+                .join(",");
+            encoded.push_str(&format!("{dep}({fields})"));
+        }
+        Ok(encoded)
+    }
+
+    fn fields(&self, ty: &str) -> Result<&Vec<TypedDataField>> {
+        self.types.get(ty).ok_or_else(|| SignatureError::TypedData(format!("unknown type {ty}")))
+    }
+
+    fn dependencies(&self, ty: &str, found: &mut BTreeSet<String>) -> Result<()> {
+        let base = base_type(ty);
+        if found.contains(base) || !self.types.contains_key(base) {
+            return Ok(());
+        }
+        found.insert(base.to_string());
+        for field in self.fields(base)? {
+            self.dependencies(&field.ty, found)?;
+        }
+        Ok(())
+    }
+
+    fn encode_value(&self, ty: &str, value: &Value) -> Result<[u8; 32]> {
+        if let Some(inner) = array_item_type(ty) {
+            let items = value
+                .as_array()
+                .ok_or_else(|| SignatureError::TypedData(format!("expected array for {ty}")))?;
+            let mut encoded = Vec::with_capacity(32 * items.len());
+            for item in items {
+                encoded.extend_from_slice(&self.encode_value(inner, item)?);
+            }
+            return Ok(keccak256(&encoded));
+        }
+
+        if self.types.contains_key(ty) {
+            return self.hash_struct(ty, value);
+        }
+
+        let mismatch = || SignatureError::TypedData(format!("invalid value {value} for {ty}"));
+        match ty {
+            "string" => Ok(keccak256(value.as_str().ok_or_else(mismatch)?.as_bytes())),
+            "bytes" => {
+                let s = value.as_str().ok_or_else(mismatch)?;
+                Ok(keccak256(&hex::decode(strip_hex_prefix(s))?))
+            }
+            "bool" => {
+                let mut word = [0u8; 32];
+                word[31] = value.as_bool().ok_or_else(mismatch)? as u8;
+                Ok(word)
+            }
+            "address" => {
+                let mut word = [0u8; 32];
+                word[12..].copy_from_slice(&parse_address(value.as_str().ok_or_else(mismatch)?)?);
+                Ok(word)
+            }
+            _ if ty.starts_with("uint") || ty.starts_with("int") => {
+                encode_integer(value, ty.starts_with("int")).ok_or_else(mismatch)
+            }
+            _ if ty.starts_with("bytes") => {
+                let bytes = hex::decode(strip_hex_prefix(value.as_str().ok_or_else(mismatch)?))?;
+                if bytes.len() > 32 {
+                    return Err(mismatch());
+                }
+                let mut word = [0u8; 32];
+                word[..bytes.len()].copy_from_slice(&bytes);
+                Ok(word)
+            }
+            _ => Err(SignatureError::TypedData(format!("unknown type {ty}"))),
+        }
+    }
+}
+
+/// Strip any array suffixes from a type, `Person[][2]` -> `Person`
+fn base_type(ty: &str) -> &str {
+    ty.split('[').next().unwrap_or(ty)
+}
+
+/// The item type of an array type, `Person[][2]` -> `Person[]`
+fn array_item_type(ty: &str) -> Option<&str> {
+    ty.strip_suffix(']').and_then(|t| t.rfind('[').map(|i| &ty[..i]))
+}
+
+/// Encode an integer as a 32-byte big-endian word. Integers may be JSON numbers, decimal strings
+/// or `0x`-prefixed hex strings. Negative values are encoded in two's complement.
+fn encode_integer(value: &Value, signed: bool) -> Option<[u8; 32]> {
+    let (negative, magnitude) = match value {
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => (false, u.to_string()),
+            (None, Some(i)) => (true, i.unsigned_abs().to_string()),
+            _ => return None,
+        },
+        Value::String(s) => match s.strip_prefix('-') {
+            Some(s) => (true, s.to_string()),
+            None => (false, s.to_string()),
+        },
+        _ => return None,
+    };
+    if negative && !signed {
+        return None;
+    }
+
+    let mut word = [0u8; 32];
+    if let Some(hex) = magnitude.strip_prefix("0x") {
+        let hex = if hex.len() % 2 == 1 { format!("0{hex}") } else { hex.to_string() };
+        let bytes = hex::decode(hex).ok()?;
+        if bytes.len() > 32 {
+            return None;
+        }
+        word[32 - bytes.len()..].copy_from_slice(&bytes);
+    } else {
+        if magnitude.is_empty() {
+            return None;
+        }
+        for digit in magnitude.chars() {
+            let mut carry = digit.to_digit(10)?;
+            for byte in word.iter_mut().rev() {
+                let v = *byte as u32 * 10 + carry;
+                *byte = v as u8;
+                carry = v >> 8;
+            }
+            if carry != 0 {
+                return None;
+            }
+        }
+    }
+
+    if negative {
+        // two's complement
+        let mut carry = true;
+        for byte in word.iter_mut().rev() {
+            let (v, overflow) = (!*byte).overflowing_add(carry as u8);
+            *byte = v;
+            carry = overflow;
+        }
+    }
+    Some(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::ChainId;
+
+    fn mail() -> TypedData {
+        serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "version", "type": "string" },
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" }
+                ],
+                "Person": [
+                    { "name": "name", "type": "string" },
+                    { "name": "wallet", "type": "address" }
+                ],
+                "Mail": [
+                    { "name": "from", "type": "Person" },
+                    { "name": "to", "type": "Person" },
+                    { "name": "contents", "type": "string" }
+                ]
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": {
+                "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+                "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+                "contents": "Hello, Bob!"
+            }
+        }))
+        .unwrap()
+    }
+
+    // private key of the EIP-712 example, `keccak256("cow")`
+    fn cow() -> SigningKey {
+        SigningKey::from_slice(&keccak256(b"cow")).unwrap()
+    }
+
+    fn account(address: &str) -> AccountId {
+        AccountId::new(ChainId::new("eip155", "1"), address)
+    }
+
+    #[test]
+    fn test_eip712_example() {
+        let data = mail();
+        assert_eq!(
+            data.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            hex::encode(data.domain_separator().unwrap()),
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+        assert_eq!(
+            hex::encode(data.hash_struct("Mail", &data.message).unwrap()),
+            "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+        );
+        assert_eq!(
+            hex::encode(data.signing_hash().unwrap()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+    }
+
+    #[test]
+    fn test_recover_typed_data() {
+        let signature = "0x4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d\
+                         07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b915621c";
+        let cow = account("0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826");
+        verify_typed_data(&cow, &mail(), signature).unwrap();
+
+        let bob = account("0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB");
+        let err = verify_typed_data(&bob, &mail(), signature).unwrap_err();
+        assert!(matches!(
+            err,
+            SignatureError::Mismatch { recovered, .. }
+                if recovered == "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"
+        ));
+    }
+
+    #[test]
+    fn test_personal_sign() {
+        let key = cow();
+        let address = to_checksum_address(&address_of(key.verifying_key()));
+        assert_eq!(address, "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826");
+
+        let message = b"hello walletconnect";
+        let signature = hex::encode(sign_hash(&key, &eip191_hash(message)).unwrap());
+        let params = json!([format!("0x{}", hex::encode(message)), address]);
+        assert!(
+            verify_response(&account(&address), PERSONAL_SIGN, &params, &json!(signature)).unwrap()
+        );
+
+        let other = account("0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB");
+        assert!(matches!(
+            verify_response(&other, PERSONAL_SIGN, &params, &json!(signature)),
+            Err(SignatureError::Mismatch { .. })
+        ));
+        assert!(matches!(
+            verify_response(&other, PERSONAL_SIGN, &params, &json!({ "signature": signature })),
+            Err(SignatureError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_unverifiable_response() {
+        // results of methods which are not signatures are not checked, whatever their shape
+        let cow = account("0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826");
+        let params = json!([{ "from": cow.address(), "to": cow.address(), "value": "0x0" }]);
+        let receipt = json!({ "transactionHash": "0xdeadbeef", "status": "0x1" });
+        assert!(!verify_response(&cow, "eth_sendTransaction", &params, &receipt).unwrap());
+        assert!(!verify_response(&cow, "eth_chainId", &json!([]), &json!(1)).unwrap());
+    }
+
+    #[test]
+    fn test_session_response() {
+        let key = cow();
+        let address = to_checksum_address(&address_of(key.verifying_key()));
+        let chain = ChainId::new("eip155", "1");
+        let namespace = crate::types::Namespace {
+            chains: vec![chain.to_string()],
+            methods: vec![PERSONAL_SIGN.into()],
+            events: vec![],
+            accounts: vec![account(&address)],
+        };
+        let namespaces = NamespaceMap::from([(EIP155.to_string(), namespace)]);
+
+        let message = "0xdeadbeef";
+        let signature =
+            hex::encode(sign_hash(&key, &eip191_hash(&[0xde, 0xad, 0xbe, 0xef])).unwrap());
+        let params = json!([message, address.to_lowercase()]);
+        assert!(verify_session_response(
+            &namespaces,
+            &chain,
+            PERSONAL_SIGN,
+            &params,
+            &json!(signature)
+        )
+        .unwrap());
+
+        let params = json!([message, "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"]);
+        assert!(matches!(
+            verify_session_response(&namespaces, &chain, PERSONAL_SIGN, &params, &json!(signature)),
+            Err(SignatureError::UnknownAccount(_))
+        ));
+    }
+
+    #[test]
+    fn test_sign_request() {
+        let key = cow();
+        let address = "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826";
+
+        let params = json!(["hello walletconnect", address]);
+        let signature = sign_request(&key, PERSONAL_SIGN, &params).unwrap().unwrap();
+        assert!(
+            verify_response(&account(address), PERSONAL_SIGN, &params, &json!(signature)).unwrap()
+        );
+
+        let params = json!([address, serde_json::to_string(&mail()).unwrap()]);
+        let signature = sign_request(&key, ETH_SIGN_TYPED_DATA_V4, &params).unwrap().unwrap();
+        assert!(verify_response(
+            &account(address),
+            ETH_SIGN_TYPED_DATA_V4,
+            &params,
+            &json!(signature)
+        )
+        .unwrap());
+
+        let params = json!(["hello", "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"]);
+        assert!(matches!(
+            sign_request(&key, PERSONAL_SIGN, &params),
+            Err(SignatureError::UnknownAccount(_))
+        ));
+        assert_eq!(sign_request(&key, "eth_sendTransaction", &json!([])).unwrap(), None);
+    }
+
+    #[test]
+    fn test_encode_integer() {
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        assert_eq!(encode_integer(&json!(1), false), Some(one));
+        assert_eq!(encode_integer(&json!("1"), false), Some(one));
+        assert_eq!(encode_integer(&json!("0x01"), false), Some(one));
+        assert_eq!(encode_integer(&json!(-1), true), Some([0xff; 32]));
+        assert_eq!(encode_integer(&json!("-1"), false), None);
+    }
+}
@@ -0,0 +1,900 @@
+//! How a client reaches the relay. A [`RelayTransport`] publishes, subscribes, unsubscribes and
+//! fetches queued messages, and delivers the messages of every subscription on one
+//! [`incoming`](RelayTransport::incoming) channel. [`WsTransport`] talks to a relay over the
+//! websocket of a [`Client`](crate::Client), [`LoopbackTransport`] connects clients living in the
+//! same process through a [`LoopbackRelay`], and [`RecordingTransport`] keeps every call made
+//! to another transport, so higher layers can be tested without sockets.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, RwLock, Weak},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use jsonrpsee::{
+    core::client::{Subscription, SubscriptionKind},
+    types::SubscriptionId,
+    ws_client::WsClient,
+};
+use tokio::{
+    sync::{broadcast, watch},
+    task::JoinHandle,
+    time::Instant,
+};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+use crate::{
+    api::core::RelayClient,
+    connect::CloseFrame,
+    error::{JsonRpcError, TransportError},
+    types::{FetchMessagesResponse, Policy, SubscriptionData, Unsubscription},
+    BoxFuture, Client, RequestIdGen,
+};
+
+pub type Result<T> = std::result::Result<T, TransportError>;
+
+/// Number of incoming messages buffered for slow consumers
+pub const INCOMING_CAPACITY: usize = 256;
+
+/// Delay before the first attempt to reconnect, doubled after every failed attempt
+pub const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest delay between two attempts to reconnect
+pub const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Why the connection was lost once it was closed on purpose
+pub const CLOSED: &str = "connection closed";
+
+/// A connection to a relay
+pub trait RelayTransport: Send + Sync + fmt::Debug {
+    /// Publish `message` on `topic`
+    fn publish<'a>(
+        &'a self,
+        topic: &'a str,
+        message: String,
+        policy: Policy,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Subscribe to `topic`, delivering its messages to [`RelayTransport::incoming`].
+    /// Returns the subscription id.
+    fn subscribe<'a>(&'a self, topic: &'a str) -> BoxFuture<'a, Result<String>>;
+
+    /// Remove the subscription `id` to `topic`.
+    /// Returns whether the relay knew the subscription.
+    fn unsubscribe<'a>(&'a self, topic: &'a str, id: &'a str) -> BoxFuture<'a, Result<bool>>;
+
+    /// Fetch messages queued for `topics` while no client was subscribed
+    fn fetch(&self, topics: Vec<String>) -> BoxFuture<'_, Result<FetchMessagesResponse>>;
+
+    /// Messages delivered on any subscription, from now on
+    fn incoming(&self) -> broadcast::Receiver<SubscriptionData>;
+
+    /// The health of the connection, updated whenever it changes
+    fn health(&self) -> watch::Receiver<ConnectionHealth>;
+
+    /// Close the connection for good. Resolves once it is closed and its health reports it lost
+    /// because of [`CLOSED`].
+    fn close(&self) -> BoxFuture<'_, ()>;
+
+    /// Resolves with the reason the next time the connection is lost
+    fn disconnected(&self) -> BoxFuture<'static, String> {
+        let mut health = self.health();
+        Box::pin(async move {
+            match health.wait_for(|health| !health.connected).await {
+                Ok(health) => health.error.clone().unwrap_or_default(),
+                Err(_) => "transport dropped".into(),
+            }
+        })
+    }
+}
+
+/// How well the connection to the relay is doing
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionHealth {
+    pub connected: bool,
+    /// when the relay last sent a message or answered a request
+    pub last_message: Option<DateTime<Utc>>,
+    /// round trip time of the last heartbeat
+    pub rtt: Option<Duration>,
+    /// times the connection was lost and opened again
+    pub reconnects: u32,
+    /// why the connection was last lost, or why subscriptions could not be restored on it
+    pub error: Option<String>,
+}
+
+impl ConnectionHealth {
+    /// The health of a connection which was just opened
+    pub fn connected() -> Self {
+        Self { connected: true, last_message: Some(Utc::now()), ..Default::default() }
+    }
+}
+
+/// A relay reached over the websocket of a [`Client`](crate::Client). The relay is sent a
+/// heartbeat every [`heartbeat`](crate::connect::ClientBuilder::heartbeat) to measure the round
+/// trip time. When the socket closes or the relay does not answer a heartbeat within the
+/// [`liveness timeout`](crate::connect::ClientBuilder::liveness_timeout), the transport reconnects
+/// with the settings of the client and subscribes to every topic again. The connection is also
+/// replaced shortly before its auth token expires. Topics which cannot be subscribed to again are
+/// retried on every heartbeat, and reported in the [`ConnectionHealth::error`] until they are.
+/// The tasks of the transport are spawned on a [`TaskTracker`] and stop once their
+/// [`CancellationToken`] is cancelled, so the owner of the transport can wait until none is left.
+#[derive(Debug)]
+pub struct WsTransport {
+    /// the connection in use, replaced when reconnecting
+    client: RwLock<Client>,
+    subscriptions: Mutex<HashMap<String, WsSubscription>>,
+    /// topics whose subscription could not be restored on the connection in use
+    unrestored: Mutex<HashSet<String>>,
+    incoming: broadcast::Sender<SubscriptionData>,
+    health: watch::Sender<ConnectionHealth>,
+    /// cancels the task sending heartbeats and reconnecting, and every forwarding task
+    token: CancellationToken,
+    tracker: TaskTracker,
+}
+
+#[derive(Debug)]
+struct WsSubscription {
+    id: String,
+    /// task forwarding the messages of the subscription to `incoming`
+    forward: JoinHandle<()>,
+}
+
+impl WsTransport {
+    pub fn new(client: Client) -> Arc<Self> {
+        Self::supervised(client, CancellationToken::new(), TaskTracker::new())
+    }
+
+    /// Create a transport whose tasks are tracked by `tracker` and stop once `token` is cancelled
+    pub fn supervised(client: Client, token: CancellationToken, tracker: TaskTracker) -> Arc<Self> {
+        let (incoming, _) = broadcast::channel(INCOMING_CAPACITY);
+        let transport = Arc::new(Self {
+            client: RwLock::new(client),
+            subscriptions: Default::default(),
+            unrestored: Default::default(),
+            incoming,
+            health: watch::Sender::new(ConnectionHealth::connected()),
+            token,
+            tracker,
+        });
+        transport.spawn(monitor(Arc::downgrade(&transport)));
+        transport
+    }
+
+    /// Spawn `task` on the tracker, dropping it once the token is cancelled
+    fn spawn<F>(&self, task: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let token = self.token.clone();
+        self.tracker.spawn(async move {
+            tokio::select! {
+                biased;
+                _ = token.cancelled() => {}
+                _ = task => {}
+            }
+        })
+    }
+
+    /// The connection in use
+    pub fn client(&self) -> Client {
+        self.client.read().expect("client lock poisoned").clone()
+    }
+
+    fn ws(&self) -> Arc<WsClient<RequestIdGen>> {
+        self.client.read().expect("client lock poisoned").client.clone()
+    }
+
+    fn subscriptions(&self) -> MutexGuard<'_, HashMap<String, WsSubscription>> {
+        self.subscriptions.lock().expect("subscriptions lock poisoned")
+    }
+
+    /// Subscribe to `topic` on the connection in use
+    async fn open(&self, topic: &str) -> Result<WsSubscription> {
+        let mut subscription =
+            self.ws().relay_subscribe(topic.to_string()).await.map_err(relay_error)?;
+        let id = subscription_id(&subscription)?;
+        let (incoming, health) = (self.incoming.clone(), self.health.clone());
+        let forward = self.spawn(async move {
+            while let Some(message) = subscription.next().await {
+                match message {
+                    Ok(data) => {
+                        touch(&health);
+                        let _ = incoming.send(data);
+                    }
+                    Err(e) => log::warn!("Malformed relay message: {e}"),
+                }
+            }
+        });
+        Ok(WsSubscription { id, forward })
+    }
+
+    fn unrestored(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.unrestored.lock().expect("unrestored lock poisoned")
+    }
+
+    /// Switch to `client` and subscribe to every topic again on it. `lost` is whether the
+    /// previous connection was lost, rather than replaced before its auth token expired.
+    async fn resume(&self, client: Client, lost: bool) {
+        *self.client.write().expect("client lock poisoned") = client;
+        self.health.send_modify(|health| {
+            if lost {
+                let reconnects = health.reconnects + 1;
+                *health = ConnectionHealth { reconnects, ..ConnectionHealth::connected() }
+            } else {
+                health.last_message = Some(Utc::now());
+            }
+        });
+        let topics = self.subscriptions().keys().cloned().collect::<Vec<_>>();
+        self.restore(topics).await;
+    }
+
+    /// Subscribe to `topics` again on the connection in use, keeping the ones which fail to be
+    /// retried on the next heartbeat
+    async fn restore(&self, topics: Vec<String>) {
+        let mut error = None;
+        for topic in topics {
+            let subscription = match self.open(&topic).await {
+                Ok(subscription) => subscription,
+                Err(e) => {
+                    log::warn!("Failed to subscribe to {topic} again: {e}");
+                    error = Some(e.to_string());
+                    self.unrestored().insert(topic);
+                    continue;
+                }
+            };
+            self.unrestored().remove(&topic);
+            let stale = match self.subscriptions().get_mut(&topic) {
+                Some(current) => std::mem::replace(current, subscription),
+                // unsubscribed in the meantime
+                None => subscription,
+            };
+            stale.forward.abort();
+        }
+
+        let unrestored = self.unrestored().len();
+        let error = error
+            .filter(|_| unrestored > 0)
+            .map(|e| format!("failed to subscribe to {unrestored} topics again: {e}"));
+        self.health.send_if_modified(|health| {
+            let changed = health.error != error;
+            health.error = error;
+            changed
+        });
+    }
+}
+
+fn subscription_id(subscription: &Subscription<SubscriptionData>) -> Result<String> {
+    match subscription.kind() {
+        SubscriptionKind::Subscription(SubscriptionId::Num(id)) => Ok(id.to_string()),
+        SubscriptionKind::Subscription(SubscriptionId::Str(id)) => Ok(id.to_string()),
+        _ => Err(TransportError::MissingSubscriptionId),
+    }
+}
+
+/// Record that the relay sent something, without waking up the watchers of the health
+fn touch(health: &watch::Sender<ConnectionHealth>) {
+    health.send_if_modified(|health| {
+        health.last_message = Some(Utc::now());
+        false
+    });
+}
+
+/// Send heartbeats on the connection in use, reconnect when it is lost and replace it before its
+/// auth token expires, until the transport is dropped
+async fn monitor(transport: Weak<WsTransport>) {
+    loop {
+        let Some(client) = transport.upgrade().map(|t| t.client()) else { return };
+        let lost = match heartbeat(&transport, &client).await {
+            Interruption::Refresh => {
+                log::debug!("Renewing the auth token of the relay connection");
+                false
+            }
+            Interruption::Lost(reason) => {
+                let reason = client.close_frame().map(ToString::to_string).unwrap_or(reason);
+                if client.close_frame().is_some_and(CloseFrame::is_token_expired) {
+                    log::warn!("Auth token expired, reconnecting with a new one: {reason}");
+                } else {
+                    log::warn!("Lost the connection to the relay: {reason}");
+                }
+                let Some(this) = transport.upgrade() else { return };
+                this.health.send_modify(|health| {
+                    health.connected = false;
+                    health.error = Some(reason);
+                });
+                true
+            }
+        };
+
+        let mut backoff = RECONNECT_BACKOFF;
+        let client = loop {
+            match client.reconnect().await {
+                Ok(client) => break client,
+                Err(e) => {
+                    log::warn!("Failed to reconnect to the relay, retrying in {backoff:?}: {e}");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+                }
+            }
+            if transport.strong_count() == 0 {
+                return;
+            }
+        };
+        let Some(this) = transport.upgrade() else { return };
+        this.resume(client, lost).await;
+        if lost {
+            log::info!("Reconnected to the relay");
+        }
+    }
+}
+
+/// Why [`heartbeat`] returned
+enum Interruption {
+    /// the connection was lost, for this reason
+    Lost(String),
+    /// the auth token of the connection is about to expire
+    Refresh,
+}
+
+/// Measure the round trip time to the relay every heartbeat interval, until the connection of
+/// `client` is lost or has to be replaced
+async fn heartbeat(transport: &Weak<WsTransport>, client: &Client) -> Interruption {
+    let (interval, timeout) = (client.config.heartbeat, client.config.liveness_timeout);
+    let mut ticker = tokio::time::interval_at(Instant::now() + interval, interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let refresh = (client.token_refresh_at() - Utc::now()).to_std().unwrap_or_default();
+    let refresh = tokio::time::sleep(refresh);
+    tokio::pin!(refresh);
+    loop {
+        tokio::select! {
+            reason = client.client.disconnect_reason() => {
+                return Interruption::Lost(reason.to_string())
+            }
+            _ = &mut refresh => return Interruption::Refresh,
+            _ = ticker.tick() => {}
+        }
+        // fetching no topic is answered right away and has no side effects
+        let start = Instant::now();
+        match tokio::time::timeout(timeout, client.client.batch_fetch_messages(Vec::new())).await {
+            Ok(Ok(_)) | Ok(Err(JsonRpcError::Call(_))) => {}
+            Ok(Err(e)) => return Interruption::Lost(e.to_string()),
+            Err(_) => {
+                return Interruption::Lost(format!("relay did not answer within {timeout:?}"))
+            }
+        }
+        let rtt = start.elapsed();
+        let Some(this) = transport.upgrade() else {
+            return Interruption::Lost("transport dropped".into());
+        };
+        this.health.send_modify(|health| {
+            health.rtt = Some(rtt);
+            health.last_message = Some(Utc::now());
+        });
+
+        // topics unsubscribed from in the meantime need no retry
+        let unrestored = {
+            let subscriptions = this.subscriptions();
+            let mut unrestored = this.unrestored();
+            unrestored.retain(|topic| subscriptions.contains_key(topic));
+            unrestored.iter().cloned().collect::<Vec<_>>()
+        };
+        if !unrestored.is_empty() {
+            this.restore(unrestored).await;
+        }
+    }
+}
+
+/// Report errors the relay answered with as rejections, so they are not retried
+fn relay_error(e: JsonRpcError) -> TransportError {
+    match e {
+        JsonRpcError::Call(e) => TransportError::Rejected(e.message().to_string()),
+        e => e.into(),
+    }
+}
+
+impl RelayTransport for WsTransport {
+    fn publish<'a>(
+        &'a self,
+        topic: &'a str,
+        message: String,
+        policy: Policy,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.ws().publish(topic.to_string(), message, policy).await.map_err(relay_error)?;
+            touch(&self.health);
+            Ok(())
+        })
+    }
+
+    /// Subscribing to a topic again replaces its subscription
+    fn subscribe<'a>(&'a self, topic: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let subscription = self.open(topic).await?;
+            touch(&self.health);
+            let id = subscription.id.clone();
+            if let Some(previous) = self.subscriptions().insert(topic.to_string(), subscription) {
+                previous.forward.abort();
+            }
+            Ok(id)
+        })
+    }
+
+    /// Subscriptions are renewed when reconnecting, so the id of the current subscription to
+    /// `topic` is used over `id`
+    fn unsubscribe<'a>(&'a self, topic: &'a str, id: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            let id = match self.subscriptions().remove(topic) {
+                Some(subscription) => {
+                    subscription.forward.abort();
+                    subscription.id
+                }
+                None => id.to_string(),
+            };
+            let unsubscription = Unsubscription { topic: topic.to_string(), id };
+            let known =
+                self.ws().batch_unsubscribe(vec![unsubscription]).await.map_err(relay_error)?;
+            touch(&self.health);
+            Ok(known)
+        })
+    }
+
+    fn fetch(&self, topics: Vec<String>) -> BoxFuture<'_, Result<FetchMessagesResponse>> {
+        Box::pin(async move {
+            let response = self.ws().batch_fetch_messages(topics).await.map_err(relay_error)?;
+            touch(&self.health);
+            Ok(response)
+        })
+    }
+
+    fn incoming(&self) -> broadcast::Receiver<SubscriptionData> {
+        self.incoming.subscribe()
+    }
+
+    fn health(&self) -> watch::Receiver<ConnectionHealth> {
+        self.health.subscribe()
+    }
+
+    /// Stops reconnecting and forwarding, then closes the websocket with a close frame
+    fn close(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.token.cancel();
+            self.client().close().await;
+            self.subscriptions().clear();
+            self.health.send_modify(|health| {
+                health.connected = false;
+                health.error = Some(CLOSED.into());
+            });
+        })
+    }
+}
+
+impl Drop for WsTransport {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+/// An in-memory relay. Messages published by one of its transports are delivered to every other
+/// transport subscribed to the topic, or queued until one subscribes or fetches them.
+#[derive(Clone, Debug, Default)]
+pub struct LoopbackRelay {
+    state: Arc<Mutex<LoopbackState>>,
+}
+
+#[derive(Debug, Default)]
+struct LoopbackState {
+    next_id: u64,
+    /// topic -> subscription id -> subscriber
+    subscriptions: HashMap<String, HashMap<String, Subscriber>>,
+    /// messages published while nobody else was subscribed, by topic
+    mailbox: HashMap<String, Vec<SubscriptionData>>,
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    connection: u64,
+    incoming: broadcast::Sender<SubscriptionData>,
+}
+
+impl LoopbackRelay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a new connection to the relay
+    pub fn connect(&self) -> LoopbackTransport {
+        let (incoming, _) = broadcast::channel(INCOMING_CAPACITY);
+        let health = watch::Sender::new(ConnectionHealth::connected());
+        let connection = {
+            let mut state = self.state();
+            state.next_id += 1;
+            state.next_id
+        };
+        LoopbackTransport { connection, relay: self.clone(), incoming, health }
+    }
+
+    /// Messages queued on `topic`
+    pub fn queued(&self, topic: &str) -> Vec<SubscriptionData> {
+        self.state().mailbox.get(topic).cloned().unwrap_or_default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, LoopbackState> {
+        self.state.lock().expect("loopback relay lock poisoned")
+    }
+}
+
+/// A connection to a [`LoopbackRelay`]
+#[derive(Debug)]
+pub struct LoopbackTransport {
+    connection: u64,
+    relay: LoopbackRelay,
+    incoming: broadcast::Sender<SubscriptionData>,
+    health: watch::Sender<ConnectionHealth>,
+}
+
+impl LoopbackTransport {
+    /// Close the connection, dropping its subscriptions
+    pub fn disconnect(&self, reason: impl Into<String>) {
+        self.forget_subscriptions();
+        self.health.send_modify(|health| {
+            health.connected = false;
+            health.error = Some(reason.into());
+        });
+    }
+
+    fn check_open(&self) -> Result<()> {
+        if self.health.borrow().connected {
+            Ok(())
+        } else {
+            Err(TransportError::Closed)
+        }
+    }
+
+    fn forget_subscriptions(&self) {
+        let mut state = self.relay.state();
+        for subscribers in state.subscriptions.values_mut() {
+            subscribers.retain(|_, subscriber| subscriber.connection != self.connection);
+        }
+    }
+}
+
+impl RelayTransport for LoopbackTransport {
+    fn publish<'a>(
+        &'a self,
+        topic: &'a str,
+        message: String,
+        policy: Policy,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.check_open()?;
+            let data = SubscriptionData {
+                topic: topic.to_string(),
+                message,
+                published_at: chrono::Utc::now().timestamp_millis(),
+                tag: policy.tag,
+            };
+            let mut state = self.relay.state();
+            let mut delivered = false;
+            for subscriber in state.subscriptions.get(topic).into_iter().flat_map(|s| s.values()) {
+                if subscriber.connection != self.connection {
+                    delivered |= subscriber.incoming.send(data.clone()).is_ok();
+                }
+            }
+            if !delivered {
+                state.mailbox.entry(topic.to_string()).or_default().push(data);
+            }
+            Ok(())
+        })
+    }
+
+    /// Subscribing to a topic again returns the existing subscription id. Messages queued on the
+    /// topic are delivered right away.
+    fn subscribe<'a>(&'a self, topic: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            self.check_open()?;
+            let mut state = self.relay.state();
+            let existing = state.subscriptions.get(topic).and_then(|subscribers| {
+                let mut own = subscribers.iter().filter(|(_, s)| s.connection == self.connection);
+                own.next().map(|(id, _)| id.clone())
+            });
+            if let Some(id) = existing {
+                return Ok(id);
+            }
+            state.next_id += 1;
+            let id = format!("{:064x}", state.next_id);
+            let subscriber =
+                Subscriber { connection: self.connection, incoming: self.incoming.clone() };
+            state
+                .subscriptions
+                .entry(topic.to_string())
+                .or_default()
+                .insert(id.clone(), subscriber);
+            for data in state.mailbox.remove(topic).unwrap_or_default() {
+                let _ = self.incoming.send(data);
+            }
+            Ok(id)
+        })
+    }
+
+    fn unsubscribe<'a>(&'a self, topic: &'a str, id: &'a str) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            self.check_open()?;
+            let mut state = self.relay.state();
+            Ok(state.subscriptions.get_mut(topic).and_then(|s| s.remove(id)).is_some())
+        })
+    }
+
+    fn fetch(&self, topics: Vec<String>) -> BoxFuture<'_, Result<FetchMessagesResponse>> {
+        Box::pin(async move {
+            self.check_open()?;
+            let mut state = self.relay.state();
+            let messages =
+                topics.iter().flat_map(|t| state.mailbox.remove(t).unwrap_or_default()).collect();
+            Ok(FetchMessagesResponse { messages, has_more: false })
+        })
+    }
+
+    fn incoming(&self) -> broadcast::Receiver<SubscriptionData> {
+        self.incoming.subscribe()
+    }
+
+    fn health(&self) -> watch::Receiver<ConnectionHealth> {
+        self.health.subscribe()
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move { self.disconnect(CLOSED) })
+    }
+}
+
+impl Drop for LoopbackTransport {
+    fn drop(&mut self) {
+        self.forget_subscriptions();
+    }
+}
+
+/// A call made to a [`RecordingTransport`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransportCall {
+    Publish { topic: String, message: String, policy: Policy },
+    Subscribe { topic: String },
+    Unsubscribe { topic: String, id: String },
+    Fetch { topics: Vec<String> },
+}
+
+/// Records every call before passing it to another transport
+#[derive(Debug)]
+pub struct RecordingTransport<T> {
+    inner: T,
+    calls: Mutex<Vec<TransportCall>>,
+}
+
+impl<T: RelayTransport> RecordingTransport<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner, calls: Default::default() }
+    }
+
+    /// Every call made so far, oldest first
+    pub fn calls(&self) -> Vec<TransportCall> {
+        self.calls.lock().expect("calls lock poisoned").clone()
+    }
+
+    /// The messages published so far, oldest first
+    pub fn published(&self) -> Vec<(String, String)> {
+        self.calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                TransportCall::Publish { topic, message, .. } => Some((topic, message)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn record(&self, call: TransportCall) {
+        self.calls.lock().expect("calls lock poisoned").push(call);
+    }
+}
+
+impl<T: RelayTransport> RelayTransport for RecordingTransport<T> {
+    fn publish<'a>(
+        &'a self,
+        topic: &'a str,
+        message: String,
+        policy: Policy,
+    ) -> BoxFuture<'a, Result<()>> {
+        let call =
+            TransportCall::Publish { topic: topic.to_string(), message: message.clone(), policy };
+        self.record(call);
+        self.inner.publish(topic, message, policy)
+    }
+
+    fn subscribe<'a>(&'a self, topic: &'a str) -> BoxFuture<'a, Result<String>> {
+        self.record(TransportCall::Subscribe { topic: topic.to_string() });
+        self.inner.subscribe(topic)
+    }
+
+    fn unsubscribe<'a>(&'a self, topic: &'a str, id: &'a str) -> BoxFuture<'a, Result<bool>> {
+        self.record(TransportCall::Unsubscribe { topic: topic.to_string(), id: id.to_string() });
+        self.inner.unsubscribe(topic, id)
+    }
+
+    fn fetch(&self, topics: Vec<String>) -> BoxFuture<'_, Result<FetchMessagesResponse>> {
+        self.record(TransportCall::Fetch { topics: topics.clone() });
+        self.inner.fetch(topics)
+    }
+
+    fn incoming(&self) -> broadcast::Receiver<SubscriptionData> {
+        self.inner.incoming()
+    }
+
+    fn health(&self) -> watch::Receiver<ConnectionHealth> {
+        self.inner.health()
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        self.inner.close()
+    }
+}
+
+impl<T: RelayTransport + ?Sized> RelayTransport for Arc<T> {
+    fn publish<'a>(
+        &'a self,
+        topic: &'a str,
+        message: String,
+        policy: Policy,
+    ) -> BoxFuture<'a, Result<()>> {
+        (**self).publish(topic, message, policy)
+    }
+
+    fn subscribe<'a>(&'a self, topic: &'a str) -> BoxFuture<'a, Result<String>> {
+        (**self).subscribe(topic)
+    }
+
+    fn unsubscribe<'a>(&'a self, topic: &'a str, id: &'a str) -> BoxFuture<'a, Result<bool>> {
+        (**self).unsubscribe(topic, id)
+    }
+
+    fn fetch(&self, topics: Vec<String>) -> BoxFuture<'_, Result<FetchMessagesResponse>> {
+        (**self).fetch(topics)
+    }
+
+    fn incoming(&self) -> broadcast::Receiver<SubscriptionData> {
+        (**self).incoming()
+    }
+
+    fn health(&self) -> watch::Receiver<ConnectionHealth> {
+        (**self).health()
+    }
+
+    fn close(&self) -> BoxFuture<'_, ()> {
+        (**self).close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use soketto::{handshake, Incoming};
+    use tokio::{net::TcpListener, sync::oneshot};
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    use super::*;
+    use crate::connect::ClientBuilder;
+
+    /// A relay accepting one websocket, which answers every request and reports the code of the
+    /// close frame it receives
+    async fn stub_relay() -> (String, oneshot::Receiver<u16>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (closed, close_code) = oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut server = handshake::Server::new(stream.compat());
+            let key = server.receive_request().await.unwrap().key();
+            let accept = handshake::server::Response::Accept { key, protocol: None };
+            server.send_response(&accept).await.unwrap();
+            let (mut sender, mut receiver) = server.into_builder().finish();
+
+            let mut message = Vec::new();
+            loop {
+                message.clear();
+                let request: Value = match receiver.receive(&mut message).await {
+                    Ok(Incoming::Data(_)) => serde_json::from_slice(&message).unwrap(),
+                    Ok(Incoming::Pong(_)) => continue,
+                    Ok(Incoming::Closed(reason)) => {
+                        let _ = closed.send(reason.code);
+                        return;
+                    }
+                    Err(_) => return,
+                };
+                let result = match request["method"].as_str().unwrap() {
+                    "irn_subscribe" => json!(format!("{:064x}", 1)),
+                    "irn_batchFetchMessages" => json!({ "messages": [], "hasMore": false }),
+                    _ => json!(true),
+                };
+                let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+                if sender.send_text(response.to_string()).await.is_ok() {
+                    let _ = sender.flush().await;
+                }
+            }
+        });
+        (url, close_code)
+    }
+
+    #[tokio::test]
+    async fn test_close() {
+        let (url, close_code) = stub_relay().await;
+        let client = ClientBuilder::new("project", "https://example.com")
+            .relay_url(url)
+            .allow_insecure(true)
+            .build()
+            .await
+            .unwrap();
+        let (token, tracker) = (CancellationToken::new(), TaskTracker::new());
+        let transport = WsTransport::supervised(client, token.clone(), tracker.clone());
+        transport.subscribe("topic").await.unwrap();
+        // the monitor and the forwarding task of the subscription
+        assert_eq!(tracker.len(), 2);
+
+        let disconnected = transport.disconnected();
+        transport.close().await;
+        assert_eq!(disconnected.await, CLOSED);
+        assert_eq!(close_code.await.unwrap(), 1000);
+        assert!(!transport.client().client.is_connected());
+        assert!(token.is_cancelled());
+
+        tracker.close();
+        tokio::time::timeout(Duration::from_secs(5), tracker.wait()).await.unwrap();
+        assert!(tracker.is_empty());
+        assert!(transport.subscriptions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_loopback() {
+        let relay = LoopbackRelay::new();
+        let (alice, bob) = (relay.connect(), RecordingTransport::new(relay.connect()));
+        let policy = Policy::new(300, 1100);
+
+        alice.publish("topic", "queued".into(), policy).await.unwrap();
+        assert_eq!(relay.queued("topic").len(), 1);
+        let mut incoming = bob.incoming();
+        let id = bob.subscribe("topic").await.unwrap();
+        assert_eq!(bob.subscribe("topic").await.unwrap(), id);
+        assert_eq!(incoming.recv().await.unwrap().message, "queued");
+
+        alice.subscribe("topic").await.unwrap();
+        let mut echoes = alice.incoming();
+        alice.publish("topic", "hello".into(), policy).await.unwrap();
+        let data = incoming.recv().await.unwrap();
+        assert_eq!((data.message.as_str(), data.tag), ("hello", 1100));
+        assert!(echoes.try_recv().is_err());
+
+        assert!(bob.unsubscribe("topic", &id).await.unwrap());
+        bob.publish("topic", "reply".into(), policy).await.unwrap();
+        assert_eq!(echoes.recv().await.unwrap().message, "reply");
+        assert_eq!(bob.published(), vec![("topic".to_string(), "reply".to_string())]);
+        assert_eq!(bob.calls().len(), 4);
+
+        alice.unsubscribe("topic", "unknown").await.unwrap();
+        alice.publish("other", "later".into(), policy).await.unwrap();
+        let fetched = bob.fetch(vec!["other".into()]).await.unwrap();
+        assert_eq!(fetched.messages.len(), 1);
+        assert!(relay.queued("other").is_empty());
+
+        let disconnected = alice.disconnected();
+        assert!(alice.health().borrow().connected);
+        alice.disconnect("bye");
+        assert_eq!(disconnected.await, "bye");
+        assert_eq!(alice.health().borrow().error.as_deref(), Some("bye"));
+        assert!(matches!(
+            alice.publish("topic", "closed".into(), policy).await,
+            Err(TransportError::Closed)
+        ));
+    }
+}
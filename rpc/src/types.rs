@@ -1,21 +1,32 @@
+pub mod authenticate;
+pub mod cacao;
+pub mod caip;
 // pub mod crypto;
 // pub mod did;
-// pub mod identity;
 pub mod common;
+pub mod identity;
+pub mod notify;
 pub mod pairing;
+pub mod payload;
 pub mod relay;
 pub mod sign;
 // pub mod storage;
 // pub mod sync;
-// pub mod verify;
+pub mod verify;
+pub mod watch;
 
+pub use authenticate::*;
+pub use cacao::*;
+pub use caip::*;
+pub use common::*;
 // pub use crypto::*;
 // pub use did::*;
-// pub use identity::*;
-pub use common::*;
+pub use identity::*;
+pub use notify::*;
 pub use pairing::*;
 pub use relay::*;
 pub use sign::*;
 // pub use storage::*;
 // pub use sync::*;
-// pub use verify::*;
+pub use verify::*;
+pub use watch::*;
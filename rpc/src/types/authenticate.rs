@@ -0,0 +1,343 @@
+//! Types of `wc_sessionAuthenticate`, described [here](https://specs.walletconnect.com/2.0/specs/clients/sign/session-authenticate)
+//! Authentication requests are [CAIP-122](https://chainagnostic.org/CAIPs/caip-122) messages, with
+//! the methods a dapp requests encoded as an [ERC-5573](https://eips.ethereum.org/EIPS/eip-5573)
+//! ReCap resource.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{
+    cacao::{Cacao, CacaoPayload, CACAO_TYPE_CAIP122, DID_PKH_PREFIX},
+    AccountId, ChainId, Participant,
+};
+
+pub const WC_SESSION_AUTHENTICATE: &str = "wc_sessionAuthenticate";
+pub const RECAP_PREFIX: &str = "urn:recap:";
+/// ReCap ability namespace for JSON-RPC requests
+pub const RECAP_REQUEST: &str = "request";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthPayload {
+    #[serde(rename = "type", default = "caip122")]
+    pub ty: String,
+    pub chains: Vec<ChainId>,
+    pub domain: String,
+    pub aud: String,
+    pub nonce: String,
+    pub version: String,
+    pub iat: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statement: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Vec<String>>,
+}
+
+fn caip122() -> String {
+    CACAO_TYPE_CAIP122.into()
+}
+
+impl AuthPayload {
+    pub fn builder(
+        domain: impl Into<String>,
+        aud: impl Into<String>,
+        nonce: impl Into<String>,
+    ) -> AuthPayloadBuilder {
+        AuthPayloadBuilder::new(domain, aud, nonce)
+    }
+
+    /// Methods requested through a ReCap resource for `namespace`
+    pub fn methods(&self, namespace: &str) -> Vec<String> {
+        Recap::methods_of(self.resources.as_deref(), namespace)
+    }
+
+    /// The CACAO payload `account` signs for this request.
+    /// The statement is extended with the ReCap statement, as required by ERC-5573.
+    pub fn cacao_payload(&self, account: &AccountId) -> CacaoPayload {
+        let recaps: Vec<_> =
+            self.resources.iter().flatten().filter_map(|r| Recap::decode(r)).collect();
+        let statement = match (self.statement.as_ref(), recaps.first()) {
+            (Some(statement), Some(recap)) => Some(format!("{statement} {}", recap.statement())),
+            (None, Some(recap)) => Some(recap.statement()),
+            (statement, None) => statement.cloned(),
+        };
+
+        CacaoPayload {
+            domain: self.domain.clone(),
+            aud: self.aud.clone(),
+            version: self.version.clone(),
+            nonce: self.nonce.clone(),
+            iat: self.iat.clone(),
+            iss: format!("{DID_PKH_PREFIX}{account}"),
+            nbf: self.nbf.clone(),
+            exp: self.exp.clone(),
+            statement,
+            request_id: self.request_id.clone(),
+            resources: self.resources.clone(),
+        }
+    }
+}
+
+/// Builder for a CAIP-122 authentication request
+#[derive(Debug, Clone)]
+pub struct AuthPayloadBuilder {
+    domain: String,
+    aud: String,
+    nonce: String,
+    chains: Vec<ChainId>,
+    statement: Option<String>,
+    resources: Vec<String>,
+    methods: BTreeMap<String, Vec<String>>,
+    iat: Option<DateTime<Utc>>,
+    nbf: Option<DateTime<Utc>>,
+    exp: Option<DateTime<Utc>>,
+    request_id: Option<String>,
+}
+
+impl AuthPayloadBuilder {
+    pub fn new(
+        domain: impl Into<String>,
+        aud: impl Into<String>,
+        nonce: impl Into<String>,
+    ) -> Self {
+        Self {
+            domain: domain.into(),
+            aud: aud.into(),
+            nonce: nonce.into(),
+            chains: vec![],
+            statement: None,
+            resources: vec![],
+            methods: BTreeMap::new(),
+            iat: None,
+            nbf: None,
+            exp: None,
+            request_id: None,
+        }
+    }
+
+    pub fn chains(mut self, chains: impl IntoIterator<Item = ChainId>) -> Self {
+        self.chains.extend(chains);
+        self
+    }
+
+    pub fn statement(mut self, statement: impl Into<String>) -> Self {
+        self.statement = Some(statement.into());
+        self
+    }
+
+    pub fn resources(mut self, resources: impl IntoIterator<Item = String>) -> Self {
+        self.resources.extend(resources);
+        self
+    }
+
+    /// Request permission to send `methods` in `namespace`. If any methods are requested, the
+    /// wallet establishes a session along with the authentication.
+    pub fn methods<S: Into<String>>(
+        mut self,
+        namespace: impl Into<String>,
+        methods: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.methods
+            .entry(namespace.into())
+            .or_default()
+            .extend(methods.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn iat(mut self, iat: impl Into<DateTime<Utc>>) -> Self {
+        self.iat = Some(iat.into());
+        self
+    }
+
+    pub fn nbf(mut self, nbf: impl Into<DateTime<Utc>>) -> Self {
+        self.nbf = Some(nbf.into());
+        self
+    }
+
+    pub fn exp(mut self, exp: impl Into<DateTime<Utc>>) -> Self {
+        self.exp = Some(exp.into());
+        self
+    }
+
+    pub fn request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    pub fn build(self) -> AuthPayload {
+        let AuthPayloadBuilder {
+            domain,
+            aud,
+            nonce,
+            chains,
+            statement,
+            mut resources,
+            methods,
+            iat,
+            nbf,
+            exp,
+            request_id,
+        } = self;
+
+        let format = |time: DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::Millis, true);
+        if !methods.is_empty() {
+            let mut recap = Recap::default();
+            for (namespace, methods) in methods {
+                recap.add_methods(&namespace, methods);
+            }
+            resources.push(recap.encode());
+        }
+
+        AuthPayload {
+            ty: caip122(),
+            chains,
+            domain,
+            aud,
+            nonce,
+            version: "1".into(),
+            iat: format(iat.unwrap_or_else(Utc::now)),
+            nbf: nbf.map(format),
+            exp: exp.map(format),
+            statement,
+            request_id,
+            resources: (!resources.is_empty()).then_some(resources),
+        }
+    }
+}
+
+/// An ERC-5573 ReCap, mapping resources to abilities
+/// i.e `{ "att": { "eip155": { "request/personal_sign": [{}] } } }`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recap {
+    pub att: BTreeMap<String, BTreeMap<String, Vec<serde_json::Value>>>,
+}
+
+impl Recap {
+    pub fn add_methods<S: AsRef<str>>(
+        &mut self,
+        namespace: &str,
+        methods: impl IntoIterator<Item = S>,
+    ) {
+        let abilities = self.att.entry(namespace.to_string()).or_default();
+        for method in methods {
+            abilities.insert(
+                format!("{RECAP_REQUEST}/{}", method.as_ref()),
+                vec![serde_json::json!({})],
+            );
+        }
+    }
+
+    /// Methods requested for `namespace`
+    pub fn methods(&self, namespace: &str) -> Vec<String> {
+        self.att
+            .get(namespace)
+            .into_iter()
+            .flat_map(|abilities| abilities.keys())
+            .filter_map(|ability| ability.strip_prefix(RECAP_REQUEST)?.strip_prefix('/'))
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Methods of `namespace` in the ReCaps among `resources`
+    pub fn methods_of(resources: Option<&[String]>, namespace: &str) -> Vec<String> {
+        resources
+            .into_iter()
+            .flatten()
+            .filter_map(|resource| Recap::decode(resource))
+            .flat_map(|recap| recap.methods(namespace))
+            .collect()
+    }
+
+    /// Encode as a `urn:recap:` resource URI
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("recap is always valid json");
+        format!("{RECAP_PREFIX}{}", data_encoding::BASE64URL_NOPAD.encode(&json))
+    }
+
+    /// Decode a `urn:recap:` resource URI, `None` if the resource is not a ReCap
+    pub fn decode(resource: &str) -> Option<Self> {
+        let encoded = resource.strip_prefix(RECAP_PREFIX)?;
+        let json = data_encoding::BASE64URL_NOPAD.decode(encoded.trim_end_matches('=').as_bytes());
+        serde_json::from_slice(&json.ok()?).ok()
+    }
+
+    /// The human-readable statement a ReCap adds to the signed message
+    pub fn statement(&self) -> String {
+        let mut statement = String::from(
+            "I further authorize the stated URI to perform the following actions on my behalf:",
+        );
+        for (i, (resource, abilities)) in self.att.iter().enumerate() {
+            let mut namespaces: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+            for ability in abilities.keys() {
+                if let Some((namespace, name)) = ability.split_once('/') {
+                    namespaces.entry(namespace).or_default().push(name);
+                }
+            }
+            let actions = namespaces
+                .iter()
+                .map(|(namespace, names)| {
+                    let names: Vec<_> = names.iter().map(|n| format!("'{n}'")).collect();
+                    format!("'{namespace}': {}", names.join(", "))
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            statement.push_str(&format!(" ({}) {actions} for '{resource}'.", i + 1));
+        }
+        statement
+    }
+}
+
+/// Params of `wc_sessionAuthenticate`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionAuthenticateParams {
+    pub requester: Participant,
+    pub auth_payload: AuthPayload,
+    /// expiry of the request as a timestamp in seconds
+    pub expiry_timestamp: i64,
+}
+
+/// Result of `wc_sessionAuthenticate`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SessionAuthenticateResponse {
+    pub cacaos: Vec<Cacao>,
+    pub responder: Participant,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recap() {
+        let payload = AuthPayload::builder("app.example", "https://app.example/login", "123")
+            .chains([ChainId::new("eip155", "1")])
+            .statement("Sign in to app.example.")
+            .methods("eip155", ["personal_sign", "eth_sendTransaction"])
+            .build();
+
+        let resources = payload.resources.as_ref().unwrap();
+        assert_eq!(resources.len(), 1);
+        assert!(resources[0].starts_with(RECAP_PREFIX));
+        assert_eq!(payload.methods("eip155"), vec!["eth_sendTransaction", "personal_sign"]);
+
+        let account =
+            AccountId::parse("eip155:1:0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap();
+        let cacao = payload.cacao_payload(&account);
+        assert_eq!(
+            cacao.statement.unwrap(),
+            "Sign in to app.example. I further authorize the stated URI to perform the following \
+             actions on my behalf: (1) 'request': 'eth_sendTransaction', 'personal_sign' for \
+             'eip155'."
+        );
+        assert_eq!(cacao.iss, "did:pkh:eip155:1:0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    }
+}
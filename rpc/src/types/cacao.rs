@@ -0,0 +1,163 @@
+//! Chain Agnostic CApability Object described by [CAIP-74](https://chainagnostic.org/CAIPs/caip-74)
+
+use std::fmt::Write as _;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::TypeError,
+    types::{AccountId, Recap},
+};
+
+pub const CACAO_TYPE_CAIP122: &str = "caip122";
+/// Header type of the CACAOs registering identity keys with the keys server
+pub const CACAO_TYPE_EIP4361: &str = "eip4361";
+pub const DID_PKH_PREFIX: &str = "did:pkh:";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Cacao {
+    pub h: CacaoHeader,
+    pub p: CacaoPayload,
+    pub s: CacaoSignature,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CacaoHeader {
+    pub t: String,
+}
+
+impl Default for CacaoHeader {
+    fn default() -> Self {
+        Self { t: CACAO_TYPE_CAIP122.into() }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacaoPayload {
+    pub domain: String,
+    pub aud: String,
+    pub version: String,
+    pub nonce: String,
+    pub iat: String,
+    /// `did:pkh` of the account which signed, i.e `did:pkh:eip155:1:0xab16...`
+    pub iss: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statement: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Vec<String>>,
+}
+
+impl CacaoPayload {
+    /// The account of the issuer
+    pub fn account(&self) -> Result<AccountId, TypeError> {
+        let account = self
+            .iss
+            .strip_prefix(DID_PKH_PREFIX)
+            .ok_or_else(|| TypeError::Did(self.iss.clone()))?;
+        AccountId::parse(account)
+    }
+
+    /// Methods the issuer granted through a ReCap resource for `namespace`
+    pub fn methods(&self, namespace: &str) -> Vec<String> {
+        Recap::methods_of(self.resources.as_deref(), namespace)
+    }
+
+    /// The [EIP-4361](https://eips.ethereum.org/EIPS/eip-4361) message the issuer signed
+    pub fn siwe_message(&self) -> Result<String, TypeError> {
+        let account = self.account()?;
+        let mut message = format!(
+            "{} wants you to sign in with your Ethereum account:\n{}\n\n",
+            self.domain,
+            account.address()
+        );
+        if let Some(statement) = &self.statement {
+            let _ = write!(message, "{statement}\n\n");
+        }
+        let _ = write!(
+            message,
+            "URI: {}\nVersion: {}\nChain ID: {}\nNonce: {}\nIssued At: {}",
+            self.aud,
+            self.version,
+            account.chain_id().reference(),
+            self.nonce,
+            self.iat
+        );
+        if let Some(exp) = &self.exp {
+            let _ = write!(message, "\nExpiration Time: {exp}");
+        }
+        if let Some(nbf) = &self.nbf {
+            let _ = write!(message, "\nNot Before: {nbf}");
+        }
+        if let Some(request_id) = &self.request_id {
+            let _ = write!(message, "\nRequest ID: {request_id}");
+        }
+        if let Some(resources) = self.resources.as_ref().filter(|r| !r.is_empty()) {
+            message.push_str("\nResources:");
+            for resource in resources {
+                let _ = write!(message, "\n- {resource}");
+            }
+        }
+        Ok(message)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CacaoSignature {
+    /// signature type, `eip191` or `eip1271`
+    pub t: String,
+    /// hex encoded signature
+    pub s: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub m: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_siwe_message() {
+        let payload = CacaoPayload {
+            domain: "service.invalid".into(),
+            aud: "https://service.invalid/login".into(),
+            version: "1".into(),
+            nonce: "32891756".into(),
+            iat: "2021-09-30T16:25:24Z".into(),
+            iss: "did:pkh:eip155:1:0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".into(),
+            nbf: None,
+            exp: None,
+            statement: Some(
+                "I accept the ServiceOrg Terms of Service: https://service.invalid/tos".into(),
+            ),
+            request_id: None,
+            resources: Some(vec![
+                "ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/".into(),
+                "https://example.com/my-web2-claim.json".into(),
+            ]),
+        };
+
+        assert_eq!(
+            payload.siwe_message().unwrap(),
+            "service.invalid wants you to sign in with your Ethereum account:
+0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2
+
+I accept the ServiceOrg Terms of Service: https://service.invalid/tos
+
+URI: https://service.invalid/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756
+Issued At: 2021-09-30T16:25:24Z
+Resources:
+- ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/
+- https://example.com/my-web2-claim.json"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Chain Agnostic identifiers described by [CAIP-2](https://chainagnostic.org/CAIPs/caip-2) and
+//! [CAIP-10](https://chainagnostic.org/CAIPs/caip-10)
+//! chain_id    = namespace + ":" + reference
+//! namespace   = [-a-z0-9]{3,8}
+//! reference   = [-_a-zA-Z0-9]{1,32}
+//! account_id  = chain_id + ":" + address
+//! address     = [-.%a-zA-Z0-9]{1,128}
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TypeError;
+
+peg::parser! {
+    grammar caip_parser() for str {
+        pub rule chain_id() -> ChainId
+            = n:namespace() ":" r:reference() { ChainId { namespace: n.to_string(), reference: r.to_string() } }
+
+        pub rule account_id() -> AccountId
+            = c:chain_id() ":" a:address() { AccountId { chain_id: c, address: a.to_string() } }
+
+        rule namespace() -> &'input str
+            = n:$(['-' | 'a'..='z' | '0'..='9']*<3,8>) { n }
+
+        rule reference() -> &'input str
+            = r:$(['-' | '_' | 'a'..='z' | 'A'..='Z' | '0'..='9']*<1,32>) { r }
+
+        rule address() -> &'input str
+            = a:$(['-' | '.' | '%' | 'a'..='z' | 'A'..='Z' | '0'..='9']*<1,128>) { a }
+    }
+}
+
+/// A CAIP-2 Blockchain ID, i.e `eip155:1`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct ChainId {
+    namespace: String,
+    reference: String,
+}
+
+impl ChainId {
+    pub fn new(namespace: impl Into<String>, reference: impl Into<String>) -> Self {
+        Self { namespace: namespace.into(), reference: reference.into() }
+    }
+
+    /// Parse a CAIP-2 chain id from an input `str`
+    pub fn parse(chain_id: &str) -> Result<Self, TypeError> {
+        caip_parser::chain_id(chain_id).map_err(|source| TypeError::Caip {
+            kind: "CAIP-2 chain id",
+            input: chain_id.to_string(),
+            source,
+        })
+    }
+
+    /// The namespace of the chain, i.e `eip155`
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// The reference of the chain within its namespace, i.e `1` for Ethereum Mainnet
+    pub fn reference(&self) -> &str {
+        &self.reference
+    }
+}
+
+impl fmt::Display for ChainId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.reference)
+    }
+}
+
+impl FromStr for ChainId {
+    type Err = TypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ChainId::parse(s)
+    }
+}
+
+impl TryFrom<String> for ChainId {
+    type Error = TypeError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        ChainId::parse(&value)
+    }
+}
+
+impl From<ChainId> for String {
+    fn from(value: ChainId) -> Self {
+        value.to_string()
+    }
+}
+
+/// A CAIP-10 Account ID, i.e `eip155:1:0xab16a96D359eC26a11e2C2b3d8f8B8942d5Bfcdb`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct AccountId {
+    chain_id: ChainId,
+    address: String,
+}
+
+impl AccountId {
+    pub fn new(chain_id: ChainId, address: impl Into<String>) -> Self {
+        Self { chain_id, address: address.into() }
+    }
+
+    /// Parse a CAIP-10 account id from an input `str`
+    pub fn parse(account_id: &str) -> Result<Self, TypeError> {
+        caip_parser::account_id(account_id).map_err(|source| TypeError::Caip {
+            kind: "CAIP-10 account id",
+            input: account_id.to_string(),
+            source,
+        })
+    }
+
+    pub fn chain_id(&self) -> &ChainId {
+        &self.chain_id
+    }
+
+    /// The account address, as it appears in the account id
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.chain_id, self.address)
+    }
+}
+
+impl FromStr for AccountId {
+    type Err = TypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AccountId::parse(s)
+    }
+}
+
+impl TryFrom<String> for AccountId {
+    type Error = TypeError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        AccountId::parse(&value)
+    }
+}
+
+impl From<AccountId> for String {
+    fn from(value: AccountId) -> Self {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_id() {
+        let chain = ChainId::parse("eip155:11155111").unwrap();
+        assert_eq!(chain.namespace(), "eip155");
+        assert_eq!(chain.reference(), "11155111");
+        assert_eq!(chain.to_string(), "eip155:11155111");
+
+        assert!(ChainId::parse("e:1").is_err());
+        let error = ChainId::parse("eip155").unwrap_err();
+        assert!(matches!(error, TypeError::Caip { kind: "CAIP-2 chain id", .. }));
+        assert!(error.to_string().starts_with("\"eip155\" is not a valid CAIP-2 chain id"));
+    }
+
+    #[test]
+    fn test_account_id() {
+        let account =
+            AccountId::parse("eip155:1:0xab16a96D359eC26a11e2C2b3d8f8B8942d5Bfcdb").unwrap();
+        assert_eq!(account.chain_id(), &ChainId::new("eip155", "1"));
+        assert_eq!(account.address(), "0xab16a96D359eC26a11e2C2b3d8f8B8942d5Bfcdb");
+
+        let json = serde_json::to_string(&account).unwrap();
+        assert_eq!(json, "\"eip155:1:0xab16a96D359eC26a11e2C2b3d8f8B8942d5Bfcdb\"");
+        assert_eq!(serde_json::from_str::<AccountId>(&json).unwrap(), account);
+
+        let error = AccountId::parse("eip155:1").unwrap_err();
+        assert!(matches!(error, TypeError::Caip { kind: "CAIP-10 account id", .. }));
+    }
+}
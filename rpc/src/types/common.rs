@@ -26,6 +26,16 @@ pub struct Metadata {
     redirect: Option<Redirect>,
 }
 
+impl Metadata {
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn verify_url(&self) -> Option<&str> {
+        self.verify_url.as_deref()
+    }
+}
+
 impl Default for Metadata {
     fn default() -> Self {
         Self {
@@ -0,0 +1,50 @@
+//! Types of the keys server, described [here](https://specs.walletconnect.com/2.0/specs/servers/keys/identity-keys)
+//! An identity key is an ed25519 key bound to a blockchain account by a CACAO the account signs.
+//! Protocols like Notify authenticate as the account with JWTs signed by its identity key.
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::Cacao;
+
+pub const KEYS_SERVER_URL: &str = "https://keys.walletconnect.com";
+pub const IDENTITY_STATEMENT_LIMITED: &str =
+    "I further authorize this app to send me notifications. \
+                                              Read more at https://walletconnect.com/notifications";
+pub const IDENTITY_STATEMENT_UNLIMITED: &str =
+    "I further authorize this app to view and manage my notifications for ALL apps. Read more at \
+     https://walletconnect.com/notifications";
+/// `act` claim of the JWT unregistering an identity key
+pub const UNREGISTER_IDENTITY: &str = "unregister_identity";
+
+/// Body of `POST /identity`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RegisterIdentity {
+    pub cacao: Cacao,
+}
+
+/// Body of `DELETE /identity`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UnregisterIdentity {
+    pub id_auth: String,
+}
+
+/// Response of `GET /identity`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ResolveIdentity {
+    pub value: RegisterIdentity,
+}
+
+/// Claims of the JWT unregistering an identity key, signed by the identity key itself
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UnregisterIdentityClaims {
+    /// `did:key` of the identity key
+    pub iss: String,
+    /// URL of the keys server
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub act: String,
+    /// `did:pkh` of the account
+    pub pkh: String,
+}
@@ -0,0 +1,255 @@
+//! Types of the Notify API, described [here](https://specs.walletconnect.com/2.0/specs/clients/notify)
+//! A dapp publishes its keys in a `did:web` document and its notification types in
+//! `wc-notify-config.json`, both under `/.well-known`. Every payload carries a JWT signed by the
+//! identity key of the account, or by the authentication key of the dapp.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+pub const WC_NOTIFY_SUBSCRIBE: &str = "wc_notifySubscribe";
+pub const WC_NOTIFY_MESSAGE: &str = "wc_notifyMessage";
+pub const WC_NOTIFY_UPDATE: &str = "wc_notifyUpdate";
+pub const WC_NOTIFY_DELETE: &str = "wc_notifyDelete";
+
+pub const NOTIFY_SUBSCRIPTION: &str = "notify_subscription";
+pub const NOTIFY_SUBSCRIPTION_RESPONSE: &str = "notify_subscription_response";
+pub const NOTIFY_MESSAGE: &str = "notify_message";
+pub const NOTIFY_MESSAGE_RESPONSE: &str = "notify_message_response";
+pub const NOTIFY_UPDATE: &str = "notify_update";
+pub const NOTIFY_UPDATE_RESPONSE: &str = "notify_update_response";
+pub const NOTIFY_DELETE: &str = "notify_delete";
+pub const NOTIFY_DELETE_RESPONSE: &str = "notify_delete_response";
+
+pub const DID_JSON_PATH: &str = ".well-known/did.json";
+pub const NOTIFY_CONFIG_PATH: &str = ".well-known/wc-notify-config.json";
+pub const DID_WEB_PREFIX: &str = "did:web:";
+
+/// A `did:web` document, i.e `https://app.example/.well-known/did.json`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocument {
+    pub id: String,
+    #[serde(default)]
+    pub verification_method: Vec<VerificationMethod>,
+    /// ids of the X25519 keys the dapp accepts subscriptions with
+    #[serde(default)]
+    pub key_agreement: Vec<String>,
+    /// ids of the Ed25519 keys the dapp signs with
+    #[serde(default)]
+    pub authentication: Vec<String>,
+}
+
+impl DidDocument {
+    /// The document of a Notify dapp at `domain`, publishing the X25519 key it accepts
+    /// subscriptions with and the Ed25519 key it signs with
+    pub fn notify(domain: &str, key_agreement: &[u8; 32], authentication: &[u8; 32]) -> Self {
+        let id = format!("{DID_WEB_PREFIX}{domain}");
+        let method = |fragment: &str, crv: &str, key: &[u8; 32]| VerificationMethod {
+            id: format!("{id}#{fragment}"),
+            ty: "JsonWebKey2020".into(),
+            controller: id.clone(),
+            public_key_jwk: Jwk {
+                kty: "OKP".into(),
+                crv: crv.into(),
+                x: data_encoding::BASE64URL_NOPAD.encode(key),
+            },
+        };
+        let subscribe = method("wc-notify-subscribe-key", "X25519", key_agreement);
+        let signing = method("wc-notify-authentication-key", "Ed25519", authentication);
+        Self {
+            key_agreement: vec![subscribe.id.clone()],
+            authentication: vec![signing.id.clone()],
+            verification_method: vec![subscribe, signing],
+            id,
+        }
+    }
+
+    /// The first X25519 key agreement key
+    pub fn key_agreement_key(&self) -> Option<[u8; 32]> {
+        self.key(&self.key_agreement)
+    }
+
+    /// The first Ed25519 authentication key
+    pub fn authentication_key(&self) -> Option<[u8; 32]> {
+        self.key(&self.authentication)
+    }
+
+    fn key(&self, ids: &[String]) -> Option<[u8; 32]> {
+        ids.iter()
+            .filter_map(|id| self.verification_method.iter().find(|m| &m.id == id))
+            .find_map(|method| method.public_key_jwk.key())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationMethod {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub controller: String,
+    pub public_key_jwk: Jwk,
+}
+
+/// An OKP JSON Web Key
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    /// base64url encoded public key
+    pub x: String,
+}
+
+impl Jwk {
+    pub fn key(&self) -> Option<[u8; 32]> {
+        let bytes = data_encoding::BASE64URL_NOPAD.decode(self.x.as_bytes()).ok()?;
+        bytes.try_into().ok()
+    }
+}
+
+/// `https://app.example/.well-known/wc-notify-config.json`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyConfig {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub icons: Vec<String>,
+    #[serde(alias = "types")]
+    pub notification_types: Vec<NotificationType>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NotificationType {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Whether the account receives a type of notification
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ScopeSetting {
+    pub name: String,
+    pub description: String,
+    pub enabled: bool,
+}
+
+/// Notification types of a subscription by id
+pub type Scope = BTreeMap<String, ScopeSetting>;
+
+/// A notification sent by a dapp
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyMessage {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub title: String,
+    pub body: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<i64>,
+}
+
+/// Claims of every Notify JWT. Which optional claims are present depends on `act`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NotifyClaims {
+    pub iat: i64,
+    pub exp: i64,
+    /// `did:key` of the signer
+    pub iss: String,
+    /// `did:key` of the recipient
+    pub aud: String,
+    pub act: String,
+    /// `did:pkh` of the account
+    pub sub: String,
+    /// `did:web` of the dapp
+    pub app: String,
+    /// URL of the keys server the identity key is registered with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ksu: Option<String>,
+    /// space separated ids of the enabled notification types
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg: Option<NotifyMessage>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifySubscribeParams {
+    pub subscription_auth: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyMessageParams {
+    pub message_auth: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyUpdateParams {
+    pub update_auth: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyDeleteParams {
+    pub delete_auth: String,
+}
+
+/// Result of every Notify request
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyResponse {
+    pub response_auth: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_did_document() {
+        let document: DidDocument = serde_json::from_value(serde_json::json!({
+            "id": "did:web:app.example",
+            "verificationMethod": [
+                {
+                    "id": "did:web:app.example#wc-notify-subscribe-key",
+                    "type": "JsonWebKey2020",
+                    "controller": "did:web:app.example",
+                    "publicKeyJwk": {
+                        "kty": "OKP",
+                        "crv": "X25519",
+                        "x": data_encoding::BASE64URL_NOPAD.encode(&[1u8; 32])
+                    }
+                },
+                {
+                    "id": "did:web:app.example#wc-notify-authentication-key",
+                    "type": "JsonWebKey2020",
+                    "controller": "did:web:app.example",
+                    "publicKeyJwk": {
+                        "kty": "OKP",
+                        "crv": "Ed25519",
+                        "x": data_encoding::BASE64URL_NOPAD.encode(&[2u8; 32])
+                    }
+                }
+            ],
+            "keyAgreement": ["did:web:app.example#wc-notify-subscribe-key"],
+            "authentication": ["did:web:app.example#wc-notify-authentication-key"]
+        }))
+        .unwrap();
+
+        assert_eq!(document.key_agreement_key(), Some([1u8; 32]));
+        assert_eq!(document.authentication_key(), Some([2u8; 32]));
+        assert_eq!(DidDocument::notify("app.example", &[1u8; 32], &[2u8; 32]), document);
+    }
+}
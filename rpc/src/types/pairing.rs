@@ -1 +1,4 @@
-
+// https://specs.walletconnect.com/2.0/specs/clients/core/pairing/rpc-methods
+pub const WC_PAIRING_DELETE: &str = "wc_pairingDelete";
+pub const WC_PAIRING_PING: &str = "wc_pairingPing";
+pub const WC_PAIRING_EXTEND: &str = "wc_pairingExtend";
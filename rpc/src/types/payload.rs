@@ -0,0 +1,123 @@
+//! JSON-RPC payloads exchanged between peers over the relay.
+//! Payloads are encrypted and published with `irn_publish`, so unlike the relay methods they
+//! cannot be expressed as a jsonrpsee client.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const JSONRPC_VERSION: &str = "2.0";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Request {
+    pub id: u64,
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Value,
+}
+
+impl Request {
+    pub fn new(id: u64, method: impl Into<String>, params: Value) -> Self {
+        Self { id, jsonrpc: JSONRPC_VERSION.into(), method: method.into(), params }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Response {
+    pub id: u64,
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorData>,
+}
+
+impl Response {
+    pub fn success(id: u64, result: Value) -> Self {
+        Self { id, jsonrpc: JSONRPC_VERSION.into(), result: Some(result), error: None }
+    }
+
+    pub fn error(id: u64, error: ErrorData) -> Self {
+        Self { id, jsonrpc: JSONRPC_VERSION.into(), result: None, error: Some(error) }
+    }
+
+    /// Convert the response into a `Result`, a response without a result is `null`
+    pub fn into_result(self) -> Result<Value, ErrorData> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.result.unwrap_or(Value::Null)),
+        }
+    }
+}
+
+// https://specs.walletconnect.com/2.0/specs/clients/sign/error-codes
+pub const USER_REJECTED: i64 = 5000;
+pub const UNSUPPORTED_CHAINS: i64 = 5100;
+pub const UNSUPPORTED_METHODS: i64 = 5101;
+pub const UNSUPPORTED_EVENTS: i64 = 5102;
+pub const UNSUPPORTED_ACCOUNTS: i64 = 5103;
+pub const UNSUPPORTED_NAMESPACE_KEY: i64 = 5104;
+pub const USER_DISCONNECTED: i64 = 6000;
+/// Internal error of the reference SDKs for a request on a topic without a session
+pub const NO_MATCHING_KEY: i64 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ErrorData {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+impl ErrorData {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), data: None }
+    }
+
+    pub fn user_rejected() -> Self {
+        Self::new(USER_REJECTED, "User rejected.")
+    }
+}
+
+/// Either side of a JSON-RPC exchange
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Payload {
+    Request(Request),
+    Response(Response),
+}
+
+impl Payload {
+    pub fn id(&self) -> u64 {
+        match self {
+            Self::Request(r) => r.id,
+            Self::Response(r) => r.id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let request: Payload = serde_json::from_value(json!({
+            "id": 1, "jsonrpc": "2.0", "method": "wc_sessionPing", "params": {}
+        }))
+        .unwrap();
+        assert!(matches!(request, Payload::Request(Request { id: 1, .. })));
+
+        let response: Payload =
+            serde_json::from_value(json!({ "id": 1, "jsonrpc": "2.0", "result": true })).unwrap();
+        assert_eq!(response, Payload::Response(Response::success(1, json!(true))));
+
+        let error: Payload = serde_json::from_value(json!({
+            "id": 1, "jsonrpc": "2.0", "error": { "code": 5000, "message": "User rejected." }
+        }))
+        .unwrap();
+        let Payload::Response(error) = error else { panic!("expected response") };
+        assert_eq!(error.into_result(), Err(ErrorData::new(5000, "User rejected.")));
+    }
+}
@@ -1,7 +1,173 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Default, Serialize, Deserialize)]
+use crate::types::{
+    WC_NOTIFY_DELETE, WC_NOTIFY_MESSAGE, WC_NOTIFY_SUBSCRIBE, WC_NOTIFY_UPDATE, WC_PAIRING_DELETE,
+    WC_PAIRING_EXTEND, WC_PAIRING_PING, WC_SESSION_AUTHENTICATE, WC_SESSION_DELETE,
+    WC_SESSION_EVENT, WC_SESSION_EXTEND, WC_SESSION_PING, WC_SESSION_PROPOSE, WC_SESSION_REQUEST,
+    WC_SESSION_SETTLE, WC_SESSION_UPDATE,
+};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = MINUTE * 60;
+const DAY: u64 = HOUR * 24;
+
+/// How long the relay keeps a message and how it is tagged, used by the relay to decide which
+/// messages trigger a push notification
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Policy {
+    /// seconds the relay stores the message for
     pub ttl: u64,
     pub tag: u64,
+    /// whether the message should prompt the user through a wallet push notification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<bool>,
+}
+
+impl Default for Policy {
+    /// Policy of messages whose method has no policy in the spec
+    fn default() -> Self {
+        Self::new(5 * MINUTE, 0)
+    }
+}
+
+impl Policy {
+    pub const fn new(ttl: u64, tag: u64) -> Self {
+        Self { ttl, tag, prompt: None }
+    }
+
+    /// Prompt the user through a wallet push notification
+    pub const fn prompt(mut self) -> Self {
+        self.prompt = Some(true);
+        self
+    }
+}
+
+/// Kind of message a [`Policy`] applies to
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PolicyKind {
+    Request,
+    Response,
+    /// An error response
+    Reject,
+}
+
+/// Publish policies of a method, described
+/// [here](https://specs.walletconnect.com/2.0/specs/clients/sign/rpc-methods)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MethodPolicy {
+    pub request: Policy,
+    pub response: Policy,
+    pub reject: Policy,
+}
+
+impl MethodPolicy {
+    const fn new(request: Policy, response: Policy) -> Self {
+        Self { request, response, reject: response }
+    }
+
+    const fn reject(mut self, reject: Policy) -> Self {
+        self.reject = reject;
+        self
+    }
+
+    /// The publish policies of `method`, if the spec defines them
+    pub fn of(method: &str) -> Option<Self> {
+        let policy = match method {
+            WC_PAIRING_DELETE => Self::new(Policy::new(DAY, 1000), Policy::new(DAY, 1001)),
+            WC_PAIRING_PING => Self::new(Policy::new(30, 1002), Policy::new(30, 1003)),
+            WC_PAIRING_EXTEND => Self::new(Policy::new(30, 1004), Policy::new(30, 1005)),
+            WC_SESSION_PROPOSE => {
+                Self::new(Policy::new(5 * MINUTE, 1100).prompt(), Policy::new(5 * MINUTE, 1101))
+                    .reject(Policy::new(5 * MINUTE, 1120))
+            }
+            WC_SESSION_SETTLE => {
+                Self::new(Policy::new(5 * MINUTE, 1102), Policy::new(5 * MINUTE, 1103))
+            }
+            WC_SESSION_UPDATE => Self::new(Policy::new(DAY, 1104), Policy::new(DAY, 1105)),
+            WC_SESSION_EXTEND => Self::new(Policy::new(DAY, 1106), Policy::new(DAY, 1107)),
+            WC_SESSION_REQUEST => {
+                Self::new(Policy::new(5 * MINUTE, 1108).prompt(), Policy::new(5 * MINUTE, 1109))
+            }
+            WC_SESSION_EVENT => {
+                Self::new(Policy::new(5 * MINUTE, 1110), Policy::new(5 * MINUTE, 1111))
+            }
+            WC_SESSION_DELETE => Self::new(Policy::new(DAY, 1112), Policy::new(DAY, 1113)),
+            WC_SESSION_PING => Self::new(Policy::new(30, 1114), Policy::new(30, 1115)),
+            WC_SESSION_AUTHENTICATE => {
+                Self::new(Policy::new(HOUR, 1116).prompt(), Policy::new(HOUR, 1117))
+                    .reject(Policy::new(HOUR, 1118))
+            }
+            WC_NOTIFY_SUBSCRIBE => {
+                Self::new(Policy::new(30 * DAY, 4000), Policy::new(30 * DAY, 4001))
+            }
+            WC_NOTIFY_MESSAGE => {
+                Self::new(Policy::new(30 * DAY, 4002), Policy::new(30 * DAY, 4003))
+            }
+            WC_NOTIFY_DELETE => Self::new(Policy::new(30 * DAY, 4004), Policy::new(30 * DAY, 4005)),
+            WC_NOTIFY_UPDATE => Self::new(Policy::new(30 * DAY, 4008), Policy::new(30 * DAY, 4009)),
+            _ => return None,
+        };
+        Some(policy)
+    }
+
+    /// The policy of a message of `kind`
+    pub fn get(&self, kind: PolicyKind) -> Policy {
+        match kind {
+            PolicyKind::Request => self.request,
+            PolicyKind::Response => self.response,
+            PolicyKind::Reject => self.reject,
+        }
+    }
+}
+
+/// The publish policy of a message of `kind` for `method`, falling back to [`Policy::default`]
+pub fn policy(method: &str, kind: PolicyKind) -> Policy {
+    MethodPolicy::of(method).map(|policy| policy.get(kind)).unwrap_or_default()
+}
+
+/// A message delivered by the relay on a subscribed topic
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionData {
+    pub topic: String,
+    pub message: String,
+    pub published_at: i64,
+    #[serde(default)]
+    pub tag: u64,
+}
+
+/// A subscription to remove with `irn_batchUnsubscribe`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Unsubscription {
+    pub topic: String,
+    /// subscription id returned by `irn_subscribe`
+    pub id: String,
+}
+
+/// Messages queued by the relay while the client was offline
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchMessagesResponse {
+    pub messages: Vec<SubscriptionData>,
+    /// whether more messages are queued, to be fetched with another request
+    pub has_more: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        assert_eq!(
+            policy(WC_SESSION_PROPOSE, PolicyKind::Request),
+            Policy { ttl: 300, tag: 1100, prompt: Some(true) }
+        );
+        assert_eq!(policy(WC_SESSION_PROPOSE, PolicyKind::Reject), Policy::new(300, 1120));
+        assert_eq!(policy(WC_SESSION_PING, PolicyKind::Reject), Policy::new(30, 1115));
+        assert_eq!(policy("wc_unknown", PolicyKind::Response), Policy::default());
+
+        let json = serde_json::to_value(Policy::new(DAY, 1112)).unwrap();
+        assert_eq!(json, serde_json::json!({ "ttl": 86400, "tag": 1112 }));
+    }
 }
@@ -2,10 +2,20 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use crate::types::common::*;
+use crate::types::{caip::*, common::*};
 
 pub type NamespaceMap = HashMap<String, Namespace>;
 
+// https://specs.walletconnect.com/2.0/specs/clients/sign/rpc-methods
+pub const WC_SESSION_PROPOSE: &str = "wc_sessionPropose";
+pub const WC_SESSION_SETTLE: &str = "wc_sessionSettle";
+pub const WC_SESSION_UPDATE: &str = "wc_sessionUpdate";
+pub const WC_SESSION_EXTEND: &str = "wc_sessionExtend";
+pub const WC_SESSION_REQUEST: &str = "wc_sessionRequest";
+pub const WC_SESSION_EVENT: &str = "wc_sessionEvent";
+pub const WC_SESSION_DELETE: &str = "wc_sessionDelete";
+pub const WC_SESSION_PING: &str = "wc_sessionPing";
+
 /// The proposer/controller involved in this operation
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct Participant {
@@ -29,26 +39,124 @@ pub struct Namespace {
     pub chains: Vec<String>,
     pub methods: Vec<String>,
     pub events: Vec<String>,
+    /// CAIP-10 accounts approved by the wallet, only present on session namespaces
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accounts: Vec<AccountId>,
+}
+
+impl Namespace {
+    /// Whether `chain_id` is one of the chains of this namespace
+    pub fn supports_chain(&self, chain_id: &ChainId) -> bool {
+        self.chains.iter().any(|c| c == &chain_id.to_string())
+            || self.accounts.iter().any(|a| a.chain_id() == chain_id)
+    }
+
+    /// Find the approved account for `address` on `chain_id`
+    pub fn account(&self, chain_id: &ChainId, address: &str) -> Option<&AccountId> {
+        self.accounts
+            .iter()
+            .find(|a| a.chain_id() == chain_id && a.address().eq_ignore_ascii_case(address))
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct SessionProposeResult {
-    relay: Relay,
+    pub relay: Relay,
+    /// hex encoded X25519 public key of the wallet
     #[serde(rename = "responderPublicKey")]
-    responder_public_key: String,
+    pub responder_public_key: String,
+}
+
+/// Params of `wc_sessionPropose`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionProposeParams {
+    pub relays: Vec<Relay>,
+    pub proposer: Participant,
+    #[serde(default)]
+    pub required_namespaces: NamespaceMap,
+    #[serde(default)]
+    pub optional_namespaces: NamespaceMap,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_properties: Option<HashMap<String, String>>,
+    /// expiry of the proposal as a timestamp in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_timestamp: Option<i64>,
+}
+
+/// Params of `wc_sessionSettle`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSettleParams {
+    pub relay: Relay,
+    pub controller: Participant,
+    pub namespaces: NamespaceMap,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_properties: Option<HashMap<String, String>>,
+    /// expiry of the session as a timestamp in seconds
+    pub expiry: i64,
+}
+
+/// Params of `wc_sessionUpdate`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SessionUpdateParams {
+    pub namespaces: NamespaceMap,
+}
+
+/// Params of `wc_sessionExtend`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SessionExtendParams {
+    /// new expiry of the session as a timestamp in seconds
+    pub expiry: i64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct Caip27Request {
-    method: String,
-    params: serde_json::Value,
-    expiry: Option<i64>,
+    pub method: String,
+    pub params: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<i64>,
+}
+
+impl Caip27Request {
+    pub fn new(method: impl Into<String>, params: serde_json::Value) -> Self {
+        Self { method: method.into(), params, expiry: None }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct Event {
-    name: String,
-    data: serde_json::Value,
+    pub name: String,
+    pub data: serde_json::Value,
+}
+
+impl Event {
+    pub fn new(name: impl Into<String>, data: serde_json::Value) -> Self {
+        Self { name: name.into(), data }
+    }
+}
+
+/// Params of `wc_sessionRequest`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRequestParams {
+    pub request: Caip27Request,
+    pub chain_id: ChainId,
+}
+
+/// Params of `wc_sessionEvent`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionEventParams {
+    pub event: Event,
+    pub chain_id: ChainId,
+}
+
+/// Params of `wc_sessionDelete` and `wc_pairingDelete`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DeleteParams {
+    pub code: i64,
+    pub message: String,
 }
 
 #[derive(Serialize, Deserialize)]
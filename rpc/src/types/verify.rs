@@ -0,0 +1,120 @@
+//! Types of the Verify API, described [here](https://specs.walletconnect.com/2.0/specs/clients/core/verify/verify-api)
+//! A dapp registers the hash of every encrypted message it sends as an attestation of its origin.
+//! The wallet resolves the attestation of an incoming message and compares the attested origin
+//! with the one the peer claims in its metadata.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+pub const VERIFY_SERVER_URL: &str = "https://verify.walletconnect.org";
+
+/// Verdict on the origin of a message
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Validation {
+    /// The attested origin matches the metadata of the peer
+    Valid,
+    /// The attested origin differs from the metadata of the peer
+    Invalid,
+    /// No attestation was found, or the verify server could not be reached
+    Unknown,
+    /// The origin is a known scam
+    Scam,
+}
+
+/// An attestation registered by a dapp with the verify server
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Attestation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation_id: Option<String>,
+    pub origin: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_scam: Option<bool>,
+}
+
+impl Attestation {
+    pub fn new(attestation_id: impl Into<String>, origin: impl Into<String>) -> Self {
+        Self { attestation_id: Some(attestation_id.into()), origin: origin.into(), is_scam: None }
+    }
+}
+
+/// The verified origin of a request, surfaced to the wallet along with it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyContext {
+    /// The attested origin, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    pub validation: Validation,
+    pub verify_url: String,
+}
+
+impl VerifyContext {
+    /// Judge `attestation` against the origin the peer claims in its metadata
+    pub fn new(attestation: Option<&Attestation>, expected: &Url, verify_url: &Url) -> Self {
+        let validation = match attestation {
+            None => Validation::Unknown,
+            Some(a) if a.is_scam == Some(true) => Validation::Scam,
+            Some(a) if same_origin(&a.origin, expected) => Validation::Valid,
+            Some(_) => Validation::Invalid,
+        };
+        Self {
+            origin: attestation.map(|a| a.origin.clone()),
+            validation,
+            verify_url: verify_url.to_string(),
+        }
+    }
+
+    /// A context for a message that could not be verified
+    pub fn unknown(verify_url: &Url) -> Self {
+        Self { origin: None, validation: Validation::Unknown, verify_url: verify_url.to_string() }
+    }
+}
+
+fn same_origin(attested: &str, expected: &Url) -> bool {
+    Url::parse(attested).is_ok_and(|attested| attested.origin() == expected.origin())
+}
+
+/// The attestation id of an encrypted message, `hex(sha256(message))`
+pub fn attestation_id(message: &str) -> String {
+    hex::encode(Sha256::digest(message.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation() {
+        let verify_url = Url::parse(VERIFY_SERVER_URL).unwrap();
+        let expected = Url::parse("https://app.example/").unwrap();
+        let attestation = |origin: &str, is_scam| Attestation {
+            attestation_id: None,
+            origin: origin.into(),
+            is_scam,
+        };
+
+        let valid = attestation("https://app.example", None);
+        assert_eq!(
+            VerifyContext::new(Some(&valid), &expected, &verify_url).validation,
+            Validation::Valid
+        );
+        let invalid = attestation("https://evil.example", Some(false));
+        assert_eq!(
+            VerifyContext::new(Some(&invalid), &expected, &verify_url).validation,
+            Validation::Invalid
+        );
+        let scam = attestation("https://app.example", Some(true));
+        assert_eq!(
+            VerifyContext::new(Some(&scam), &expected, &verify_url).validation,
+            Validation::Scam
+        );
+        assert_eq!(
+            VerifyContext::new(None, &expected, &verify_url).validation,
+            Validation::Unknown
+        );
+        assert_eq!(serde_json::to_value(Validation::Scam).unwrap(), "SCAM");
+    }
+}
@@ -0,0 +1,202 @@
+//! Types of relay webhooks, described [here](https://specs.walletconnect.com/2.0/specs/servers/relay/relay-server-rpc#watch).
+//! Instead of holding a websocket, a server registers a webhook with `irn_watchRegister`, and the
+//! relay posts every message matching the watch to it, each wrapped in a JWT the relay signs.
+
+use chrono::{DateTime, Utc};
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    auth::{encode_key_as_did, JwtBasicClaims},
+    types::SubscriptionData,
+};
+
+pub const WATCH_REGISTER: &str = "irn_watchRegister";
+pub const WATCH_UNREGISTER: &str = "irn_watchUnregister";
+pub const WATCH_EVENT: &str = "irn_watchEvent";
+
+/// Longest a watch can be registered for, in seconds
+pub const MAX_WATCH_TTL: u64 = 30 * 24 * 60 * 60;
+
+/// Whether the relay reports messages published to the client, or by it
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WatchType {
+    Subscriber,
+    Publisher,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WatchStatus {
+    Accepted,
+    Queued,
+    Delivered,
+}
+
+/// A webhook to register with the relay
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watch {
+    pub typ: WatchType,
+    /// URL the relay posts messages to
+    pub webhook_url: Url,
+    /// URL of the service registering the webhook
+    pub service_url: String,
+    /// tags of the messages to report
+    pub tags: Vec<u64>,
+    pub statuses: Vec<WatchStatus>,
+    /// seconds the watch stays registered, at most [`MAX_WATCH_TTL`]
+    pub ttl: u64,
+}
+
+impl Watch {
+    /// Watch messages tagged with `tags` published to the client, for [`MAX_WATCH_TTL`]
+    pub fn subscriber(webhook_url: Url, service_url: impl Into<String>, tags: Vec<u64>) -> Self {
+        Self {
+            typ: WatchType::Subscriber,
+            webhook_url,
+            service_url: service_url.into(),
+            tags,
+            statuses: vec![WatchStatus::Queued],
+            ttl: MAX_WATCH_TTL,
+        }
+    }
+
+    pub fn register_claims(
+        &self,
+        key: &SigningKey,
+        aud: &str,
+        iat: DateTime<Utc>,
+    ) -> WatchRegisterClaims {
+        WatchRegisterClaims {
+            basic: self.basic_claims(key, aud, iat),
+            act: WATCH_REGISTER.into(),
+            typ: self.typ,
+            whu: self.webhook_url.to_string(),
+            tag: self.tags.clone(),
+            sts: self.statuses.clone(),
+        }
+    }
+
+    pub fn unregister_claims(
+        &self,
+        key: &SigningKey,
+        aud: &str,
+        iat: DateTime<Utc>,
+    ) -> WatchUnregisterClaims {
+        WatchUnregisterClaims {
+            basic: self.basic_claims(key, aud, iat),
+            act: WATCH_UNREGISTER.into(),
+            typ: self.typ,
+            whu: self.webhook_url.to_string(),
+        }
+    }
+
+    fn basic_claims(&self, key: &SigningKey, aud: &str, iat: DateTime<Utc>) -> JwtBasicClaims {
+        JwtBasicClaims {
+            iss: encode_key_as_did(key),
+            aud: aud.into(),
+            sub: self.service_url.clone(),
+            iat: iat.timestamp(),
+            exp: Some(iat.timestamp() + self.ttl.min(MAX_WATCH_TTL) as i64),
+        }
+    }
+}
+
+/// Claims of the JWT registering a webhook
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WatchRegisterClaims {
+    #[serde(flatten)]
+    pub basic: JwtBasicClaims,
+    pub act: String,
+    pub typ: WatchType,
+    /// webhook URL
+    pub whu: String,
+    pub tag: Vec<u64>,
+    pub sts: Vec<WatchStatus>,
+}
+
+/// Claims of the JWT unregistering a webhook
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WatchUnregisterClaims {
+    #[serde(flatten)]
+    pub basic: JwtBasicClaims,
+    pub act: String,
+    pub typ: WatchType,
+    /// webhook URL
+    pub whu: String,
+}
+
+/// Claims of the JWT the relay wraps each message posted to a webhook in
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WatchEventClaims {
+    #[serde(flatten)]
+    pub basic: JwtBasicClaims,
+    pub act: String,
+    pub typ: WatchType,
+    /// webhook URL
+    pub whu: String,
+    pub evt: WatchEventPayload,
+}
+
+/// A message reported by the relay
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchEventPayload {
+    pub status: WatchStatus,
+    pub topic: String,
+    pub message: String,
+    pub published_at: i64,
+    #[serde(default)]
+    pub tag: u64,
+}
+
+impl From<WatchEventPayload> for SubscriptionData {
+    fn from(event: WatchEventPayload) -> Self {
+        let WatchEventPayload { topic, message, published_at, tag, .. } = event;
+        SubscriptionData { topic, message, published_at, tag }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchRegisterResponse {
+    /// `did:key` of the key the relay signs webhook events with
+    pub relay_id: String,
+}
+
+/// Body of the requests the relay posts to a webhook
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchWebhookPayload {
+    pub event_auth: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{decode_did_key, decode_jwt, encode_jwt};
+
+    #[test]
+    fn test_register_claims() {
+        let key = SigningKey::generate(&mut rand::thread_rng());
+        let watch = Watch::subscriber(
+            Url::parse("https://service.example/webhook").unwrap(),
+            "https://service.example",
+            vec![1108],
+        );
+        let iat = Utc::now();
+        let jwt =
+            encode_jwt(&key, &watch.register_claims(&key, "https://relay.example", iat)).unwrap();
+
+        let did = encode_key_as_did(&key);
+        let claims: WatchRegisterClaims = decode_jwt(&jwt, &decode_did_key(&did).unwrap()).unwrap();
+        assert_eq!(claims.basic.iss, did);
+        assert_eq!(claims.basic.exp, Some(iat.timestamp() + MAX_WATCH_TTL as i64));
+        assert_eq!(claims.act, WATCH_REGISTER);
+        assert_eq!(claims.whu, "https://service.example/webhook");
+        assert_eq!(claims.tag, vec![1108]);
+        assert_eq!(claims.sts, vec![WatchStatus::Queued]);
+    }
+}